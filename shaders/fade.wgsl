@@ -0,0 +1,33 @@
+// Snapshot of the previous scene, blended over the next one during a transition
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) alpha: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) alpha: f32,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.alpha = model.alpha;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_snapshot: texture_2d<f32>;
+@group(0) @binding(1)
+var s_snapshot: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_snapshot, s_snapshot, in.tex_coords);
+    return vec4<f32>(color.rgb, in.alpha);
+}
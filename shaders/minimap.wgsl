@@ -0,0 +1,69 @@
+// Minimap of the whole panorama, see minimap::Minimap
+
+const PI = 3.141592654;
+
+// Kinds of vertices, see minimap::Kind
+const EQUIRECTANGULAR: u32 = 0u;
+const LITTLE_PLANET: u32 = 1u;
+const SOLID: u32 = 2u;
+
+// tan of half the angle from the nadir at the edge of the little planet, see minimap::LITTLE_PLANET_RANGE
+const LITTLE_PLANET_RANGE: f32 = 2.0;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) kind: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) kind: u32,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.coords = model.coords;
+    out.color = model.color;
+    out.kind = model.kind;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+// Texture coordinates of a point of the little planet, in [-1, 1] with y downwards
+fn little_planet(coords: vec2<f32>) -> vec2<f32> {
+    let radius = length(coords);
+    let azimuth = atan2(-coords.x, -coords.y);
+    // angle from the nadir
+    let polar = 2.0 * atan(radius * LITTLE_PLANET_RANGE);
+    // the texture azimuth grows clockwise, see project in shader.wgsl
+    let u = fract(-azimuth / (2.0 * PI) + 1.0);
+    return vec2<f32>(u, 1.0 - polar / PI);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (in.kind == SOLID) {
+        return in.color;
+    }
+    if (in.kind == LITTLE_PLANET) {
+        let edge = length(in.coords);
+        if (edge > 1.0) {
+            discard;
+        }
+        let color = textureSampleLevel(t_diffuse, s_diffuse, little_planet(in.coords), 0.0);
+        // smooth rim
+        return vec4<f32>(color.rgb, in.color.a * (1.0 - smoothstep(0.98, 1.0, edge)));
+    }
+    let color = textureSampleLevel(t_diffuse, s_diffuse, in.coords, 0.0);
+    return vec4<f32>(color.rgb, in.color.a);
+}
@@ -28,17 +28,91 @@ struct CameraSettings {
     width: f32,
     height: f32,
     focal_length: f32,
+    projection: u32,
 };
 
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+// second panorama of the comparison, see comparison::Comparison
+@group(0) @binding(2)
+var t_compare: texture_2d<f32>;
 @group(1) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
 @group(2) @binding(0)
 var<uniform> settings: CameraSettings;
 
+// Guides drawn over the panorama, see overlays::OverlayUniform
+struct Overlays {
+    horizon_color: vec4<f32>,
+    grid_color: vec4<f32>,
+    compass_color: vec4<f32>,
+    crosshair_color: vec4<f32>,
+    flags: u32,
+    grid_step: f32,
+    line_width: f32,
+    heading: f32,
+    compass_center: vec2<f32>,
+    compass_radius: f32,
+    region_count: u32,
+    // radius, rotation, opacity and 1 if drawn, for the nadir then the zenith
+    patches: array<vec4<f32>, 2>,
+    comparison: Comparison,
+}
+
+// Second panorama, see comparison::ComparisonUniform
+struct Comparison {
+    mode: u32,
+    // x of the wipe divider, in [0, 1] from the left of the window
+    divider: f32,
+    // 1 when the second panorama is shown in flicker mode
+    second: u32,
+    difference_gain: f32,
+}
+
+// Modes, see config::ComparisonMode
+const NO_COMPARISON: u32 = 0u;
+const SIDE_BY_SIDE: u32 = 1u;
+const WIPE: u32 = 2u;
+const FLICKER: u32 = 3u;
+const DIFFERENCE: u32 = 4u;
+
+const HORIZON: u32 = 1u;
+const GRID: u32 = 2u;
+const COMPASS: u32 = 4u;
+const CROSSHAIR: u32 = 8u;
+
+@group(3) @binding(0)
+var<uniform> overlays: Overlays;
+
+// Spherical polygons of the panorama, see regions::RegionUniform
+struct Region {
+    color: vec4<f32>,
+    start: u32,
+    count: u32,
+    effect: u32,
+    blur_radius: f32,
+}
+
+// Effects, see regions::RegionEffect
+const TINT: u32 = 0u;
+const OUTLINE: u32 = 1u;
+const BLUR: u32 = 2u;
+const MASK: u32 = 3u;
+
+@group(3) @binding(1)
+var<storage, read> regions: array<Region>;
+// world directions of the vertices, w is unused
+@group(3) @binding(2)
+var<storage, read> region_points: array<vec4<f32>>;
+
+// Nadir and zenith patches, see patches::PatchTexture
+@group(3) @binding(3)
+var t_patches: texture_2d_array<f32>;
+@group(3) @binding(4)
+var s_patches: sampler;
+
 fn project(raycast: vec3<f32>, camera_matrix: mat4x4<f32>) -> vec2<f32> {
   let world = camera_matrix * normalize(vec4<f32>(raycast, 0.0));
   let azimuth = (two_PI + atan2(world.x, world.z)) % (two_PI);
@@ -50,14 +124,47 @@ fn spheric_to_texture(azimuth: f32, elevation: f32) -> vec4<f32> {
   return textureSample(t_diffuse, s_diffuse, vec2<f32>(azimuth/360.0, elevation/180.0));
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let coords = in.clip_position.xy;
-    let screen = vec2<f32>(settings.width, settings.height);
-    let aspect = settings.width / settings.height;
+// Projections, see camera::Projection
+const RECTILINEAR: u32 = 0u;
+const STEREOGRAPHIC: u32 = 1u;
+const FISHEYE: u32 = 2u;
+// Whole panorama for exports, see camera::CameraSettingsBuffer::equirectangular
+const EQUIRECTANGULAR: u32 = 3u;
+
+// Camera space direction of a point on the screen, returns a null vector outside of the projection
+fn view_ray(screen: vec2<f32>, focal_length: f32, projection: u32) -> vec3<f32> {
+    if (projection == RECTILINEAR) {
+        return normalize(vec3<f32>(screen, focal_length));
+    }
+
+    let planar = screen / focal_length;
+    let radius = length(planar);
+    if (radius < 0.00001) {
+        return vec3<f32>(0.0, 0.0, 1.0);
+    }
+
+    var theta: f32;
+    if (projection == STEREOGRAPHIC) {
+        theta = 2.0 * atan(radius / 2.0);
+    } else {
+        theta = radius;
+        if (theta > PI) {
+            return vec3<f32>(0.0, 0.0, 0.0);
+        }
+    }
+    return vec3<f32>(sin(theta) * planar / radius, cos(theta));
+}
+
+// Part of the window the panorama is drawn in, origin then size in pixels, set by fs_main
+var<private> viewport: vec4<f32>;
+
+// Camera space direction seen at a pixel
+fn screen_ray(coords: vec2<f32>) -> vec3<f32> {
+    let screen = viewport.zw;
+    let aspect = viewport.z / viewport.w;
 
     // Normalize clip position into [-1, 1]
-    var uv = (coords / screen) * 2.0 - vec2<f32>(1.0, 1.0);
+    var uv = ((coords - viewport.xy) / screen) * 2.0 - vec2<f32>(1.0, 1.0);
 
     // Apply window ratio to adjust fovx
     uv.x = uv.x * aspect;
@@ -65,10 +172,289 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // Inverse Y axis for screen
     let uv_corrected = vec2<f32>(uv.x, -uv.y);
 
+    return view_ray(uv_corrected, settings.focal_length, settings.projection);
+}
+
+// Coverage of a line of `width` pixels at `distance` from the pixel, both in the unit of `pixel`
+fn line(distance: f32, width: f32, pixel: f32) -> f32 {
+    let half_width = 0.5 * width * pixel;
+    return 1.0 - smoothstep(half_width - 0.5 * pixel, half_width + 0.5 * pixel, distance);
+}
+
+fn blend(color: vec3<f32>, overlay: vec4<f32>, coverage: f32) -> vec3<f32> {
+    return mix(color, overlay.rgb, overlay.a * clamp(coverage, 0.0, 1.0));
+}
+
+// Distance to the closest multiple of `step`
+fn grid_distance(value: f32, step: f32) -> f32 {
+    return abs(value - round(value / step) * step);
+}
+
+fn compass(coords: vec2<f32>) -> f32 {
+    let offset = coords - overlays.compass_center;
+    let radius = overlays.compass_radius;
+    let distance = length(offset);
+    if (distance > radius + overlays.line_width) {
+        return 0.0;
+    }
+
+    // north up, y grows downwards on the screen
+    let angle = radians(overlays.heading);
+    let needle = vec2<f32>(
+        offset.x * cos(angle) - offset.y * sin(angle),
+        offset.x * sin(angle) + offset.y * cos(angle),
+    );
+    let length_ratio = clamp(abs(needle.y) / (0.85 * radius), 0.0, 1.0);
+    let half_width = 0.12 * radius * (1.0 - length_ratio);
+    var coverage = 0.25 + 0.75 * line(abs(distance - radius), overlays.line_width, 1.0);
+    if (abs(needle.x) < half_width && abs(needle.y) < 0.85 * radius) {
+        // the north half of the needle is solid
+        coverage = select(0.6, 1.0, needle.y < 0.0);
+    }
+    return coverage;
+}
+
+// Degrees covered by the pixel, where the projection is
+fn pixel_angle(coords: vec2<f32>, direction: vec3<f32>) -> f32 {
+    let right = screen_ray(coords + vec2<f32>(1.0, 0.0));
+    let below = screen_ray(coords + vec2<f32>(0.0, 1.0));
+    return degrees(max(length(right - direction), length(below - direction)));
+}
+
+fn draw_overlays(color: vec3<f32>, coords: vec2<f32>, direction: vec3<f32>) -> vec3<f32> {
+    var result = color;
+    let world = normalize((camera.view_proj * vec4<f32>(direction, 0.0)).xyz);
+    let elevation = degrees(asin(clamp(world.y, -1.0, 1.0)));
+    let azimuth = degrees(atan2(-world.x, world.z));
+    let pixel = pixel_angle(coords, direction);
+
+    if ((overlays.flags & GRID) != 0u) {
+        let step = overlays.grid_step;
+        var coverage = line(grid_distance(elevation, step), overlays.line_width, pixel);
+        // meridians converge at the poles
+        if (abs(elevation) < 90.0 - 0.5 * step) {
+            let distance = grid_distance(azimuth, step) * cos(radians(elevation));
+            coverage = max(coverage, line(distance, overlays.line_width, pixel));
+        }
+        result = blend(result, overlays.grid_color, coverage);
+    }
+    if ((overlays.flags & HORIZON) != 0u) {
+        result = blend(result, overlays.horizon_color, line(abs(elevation), 2.0 * overlays.line_width, pixel));
+    }
+    if ((overlays.flags & CROSSHAIR) != 0u) {
+        let offset = abs(coords - viewport.xy - 0.5 * viewport.zw);
+        let arm = 12.0;
+        let horizontal = line(offset.y, overlays.line_width, 1.0) * step(offset.x, arm);
+        let vertical = line(offset.x, overlays.line_width, 1.0) * step(offset.y, arm);
+        result = blend(result, overlays.crosshair_color, max(horizontal, vertical));
+    }
+    if ((overlays.flags & COMPASS) != 0u) {
+        result = blend(result, overlays.compass_color, compass(coords));
+    }
+    return result;
+}
+
+fn region_point(region: Region, index: u32) -> vec3<f32> {
+    return region_points[region.start + index % region.count].xyz;
+}
+
+// Whether the polygon winds around the direction on the side of its center, same test as
+// regions::polygon_contains
+fn region_contains(region: Region, p: vec3<f32>) -> bool {
+    var total = 0.0;
+    var center = vec3<f32>(0.0);
+    for (var i = 0u; i < region.count; i++) {
+        let a = region_point(region, i);
+        let b = region_point(region, i + 1u);
+        total += atan2(dot(p, cross(a, b)), dot(a, b) - dot(a, p) * dot(b, p));
+        center += a;
+    }
+    return abs(total) > PI && dot(center, p) > 0.0;
+}
+
+// Degrees between the direction and the closest edge of the polygon
+fn region_edge_distance(region: Region, p: vec3<f32>) -> f32 {
+    var distance = PI;
+    for (var i = 0u; i < region.count; i++) {
+        let a = region_point(region, i);
+        let b = region_point(region, i + 1u);
+        let normal = normalize(cross(a, b));
+        if (dot(cross(a, p), normal) >= 0.0 && dot(cross(p, b), normal) >= 0.0) {
+            distance = min(distance, abs(asin(clamp(dot(p, normal), -1.0, 1.0))));
+        } else {
+            distance = min(distance, acos(clamp(dot(p, a), -1.0, 1.0)));
+        }
+    }
+    return degrees(distance);
+}
+
+fn world_to_texture(world: vec3<f32>) -> vec2<f32> {
+    let azimuth = (two_PI + atan2(world.x, world.z)) % two_PI;
+    return vec2<f32>(azimuth / two_PI, acos(clamp(world.y, -1.0, 1.0)) / PI);
+}
+
+// Average of the panorama over a disk of `radius` degrees, on a spiral of samples
+fn blur(p: vec3<f32>, radius: f32) -> vec3<f32> {
+    let samples = 24u;
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(p.y) > 0.9) {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(p, helper));
+    let bitangent = cross(p, tangent);
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < samples; i++) {
+        let distance = tan(radians(radius)) * sqrt((f32(i) + 0.5) / f32(samples));
+        let angle = f32(i) * 2.39996;
+        let tap = normalize(p + distance * (cos(angle) * tangent + sin(angle) * bitangent));
+        sum += textureSampleLevel(t_diffuse, s_diffuse, world_to_texture(tap), 0.0).rgb;
+    }
+    return sum / f32(samples);
+}
+
+// `pixel` is the angle covered by the pixel, for the width of the outlines
+fn draw_regions(color: vec3<f32>, world: vec3<f32>, pixel: f32) -> vec3<f32> {
+    var result = color;
+    for (var i = 0u; i < overlays.region_count; i++) {
+        let region = regions[i];
+        if (region.effect == OUTLINE) {
+            let distance = region_edge_distance(region, world);
+            let coverage = line(distance, 2.0 * overlays.line_width, pixel);
+            result = blend(result, vec4<f32>(region.color.rgb, 1.0), coverage);
+            continue;
+        }
+        if (!region_contains(region, world)) {
+            continue;
+        }
+        if (region.effect == TINT) {
+            result = blend(result, region.color, 1.0);
+        } else if (region.effect == BLUR) {
+            result = blur(world, region.blur_radius);
+        } else {
+            result = region.color.rgb;
+        }
+    }
+    return result;
+}
+
+// Color of a patch at `polar` degrees from its pole, `planar` being the direction from the
+// pole with x to the right and y downwards as seen when facing the pole
+fn patch_color(layer: u32, params: vec4<f32>, planar: vec2<f32>, polar: f32) -> vec4<f32> {
+    let radius = params.x;
+    if (params.w == 0.0 || polar >= radius) {
+        return vec4<f32>(0.0);
+    }
+    var offset = vec2<f32>(0.0);
+    if (length(planar) > 0.00001) {
+        offset = normalize(planar) * polar / radius;
+    }
+    let angle = radians(params.y);
+    let turned = vec2<f32>(
+        offset.x * cos(angle) - offset.y * sin(angle),
+        offset.x * sin(angle) + offset.y * cos(angle),
+    );
+    let color = textureSampleLevel(t_patches, s_patches, 0.5 + 0.5 * turned, layer, 0.0);
+    return vec4<f32>(color.rgb, color.a * params.z);
+}
+
+fn draw_patches(color: vec3<f32>, world: vec3<f32>) -> vec3<f32> {
+    // looking down the screen right is +x and its top +z, looking up its top is -z
+    let nadir = patch_color(0u, overlays.patches[0], vec2<f32>(world.x, -world.z), degrees(acos(clamp(-world.y, -1.0, 1.0))));
+    let zenith = patch_color(1u, overlays.patches[1], vec2<f32>(world.x, world.z), degrees(acos(clamp(world.y, -1.0, 1.0))));
+    return mix(mix(color, nadir.rgb, nadir.a), zenith.rgb, zenith.a);
+}
+
+// Patches and regions over the panorama
+fn composite(color: vec3<f32>, world: vec3<f32>, pixel: f32) -> vec3<f32> {
+    var result = draw_patches(color, world);
+    if (overlays.region_count > 0u) {
+        result = draw_regions(result, world, pixel);
+    }
+    return result;
+}
+
+// The whole panorama as in its file, with the patches and regions baked in
+fn draw_equirectangular(coords: vec2<f32>) -> vec4<f32> {
+    let tex_coords = coords / vec2<f32>(settings.width, settings.height);
+    let azimuth = tex_coords.x * two_PI;
+    let polar = tex_coords.y * PI;
+    let world = vec3<f32>(sin(polar) * sin(azimuth), cos(polar), sin(polar) * cos(azimuth));
+    let color = textureSample(t_diffuse, s_diffuse, tex_coords);
+    return vec4<f32>(composite(color.rgb, world, 180.0 / settings.height), color.a);
+}
+
+// Whether the pixel shows the second panorama, `right` for the right half side by side
+fn shows_second(coords: vec2<f32>, right: bool) -> bool {
+    let comparison = overlays.comparison;
+    switch comparison.mode {
+        case SIDE_BY_SIDE: {
+            return right;
+        }
+        case WIPE: {
+            return coords.x >= comparison.divider * settings.width;
+        }
+        case FLICKER: {
+            return comparison.second == 1u;
+        }
+        default: {
+            return false;
+        }
+    }
+}
+
+// Line between the panoramas side by side and the wipe divider
+fn draw_divider(color: vec3<f32>, coords: vec2<f32>) -> vec3<f32> {
+    let comparison = overlays.comparison;
+    var x: f32;
+    if (comparison.mode == SIDE_BY_SIDE) {
+        x = 0.5 * settings.width;
+    } else if (comparison.mode == WIPE) {
+        x = comparison.divider * settings.width;
+    } else {
+        return color;
+    }
+    return blend(color, vec4<f32>(1.0, 1.0, 1.0, 0.9), line(abs(coords.x - x), 2.0, 1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = in.clip_position.xy;
+    if (settings.projection == EQUIRECTANGULAR) {
+        return draw_equirectangular(coords);
+    }
+
+    // side by side, each panorama is seen through the same camera in a half of the window
+    let mode = overlays.comparison.mode;
+    let half = 0.5 * settings.width;
+    let right = mode == SIDE_BY_SIDE && coords.x >= half;
+    viewport = vec4<f32>(0.0, 0.0, settings.width, settings.height);
+    if (mode == SIDE_BY_SIDE) {
+        viewport = vec4<f32>(select(0.0, half, right), 0.0, half, settings.height);
+    }
+
     // Generate
-    let direction = normalize(vec3<f32>(uv_corrected, settings.focal_length));
-    
+    let direction = screen_ray(coords);
+    if (length(direction) == 0.0) {
+        return vec4<f32>(draw_divider(vec3<f32>(0.0), coords), 1.0);
+    }
+
     let spheric = project(direction, camera.view_proj);
-    return spheric_to_texture(spheric.x, spheric.y); // debug colors
+    var color = spheric_to_texture(spheric.x, spheric.y);
+    let world = normalize((camera.view_proj * vec4<f32>(direction, 0.0)).xyz);
+    if (mode == DIFFERENCE) {
+        let second = textureSampleLevel(t_compare, s_diffuse, world_to_texture(world), 0.0);
+        let difference = abs(color.rgb - second.rgb) * overlays.comparison.difference_gain;
+        color = vec4<f32>(min(difference, vec3<f32>(1.0)), 1.0);
+    } else if (mode != NO_COMPARISON && shows_second(coords, right)) {
+        // the patches and regions belong to the displayed panorama
+        color = textureSampleLevel(t_compare, s_diffuse, world_to_texture(world), 0.0);
+    } else {
+        color = vec4<f32>(composite(color.rgb, world, pixel_angle(coords, direction)), color.a);
+    }
+    color = vec4<f32>(draw_divider(color.rgb, coords), color.a);
+    if (overlays.flags == 0u) {
+        return color;
+    }
+    return vec4<f32>(draw_overlays(color.rgb, coords, direction), color.a);
 }
 
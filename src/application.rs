@@ -1,21 +1,62 @@
 use crate::state::State;
+use crate::bookmarks::Bookmarks;
+use crate::cli::Arguments;
+use crate::config::{Config, ConfigLayers, ViewerConfig};
+use crate::file_watcher::FileWatcher;
+use crate::hotspots::{Hotspot, Hotspots};
+use crate::image_data::ImageData;
+use crate::image_loader::ImageLoader;
+use crate::measurement::Measurement;
+use crate::patches;
+use crate::playlist::Playlist;
+use crate::project::{Project, SceneTransition};
+use crate::regions::Regions;
+use crate::session::Session;
+use crate::shaders;
+use crate::sidecar;
+use crate::gamepad::{self, GamepadSource};
+use crate::key_bindings::Action;
+use crate::camera::{Easing, OrientationMode};
+use crate::tour::{Tour, TourCommand, TourPlayer, TourRecorder};
+use crate::export::TourExport;
+use crate::extra_window::ExtraWindow;
+use crate::view::View;
+use crate::window_settings::{self, ExtraWindowSettings, WindowGeometry, WindowMode, WindowSettings};
 
 use winit::{
     application::ApplicationHandler,
-    event::{WindowEvent, DeviceEvent, DeviceId},
-    event_loop::{ActiveEventLoop, EventLoop,},
-    window::{WindowId, WindowAttributes},
+    event::{ElementState, MouseButton, WindowEvent, DeviceEvent, DeviceId},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop,},
+    window::WindowId,
 };
 use std::thread::JoinHandle;
+use std::path::{Path, PathBuf};
 use std::result::Result;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub struct CosmosViewer {
+    arguments: Arguments,
+    playlist: Option<Playlist>,
+    config_layers: Option<ConfigLayers>,
+    config: Config,
+    session: Option<Session>,
+    tour: Option<Tour>,
+    project: Option<Project>,
+    image: Option<ImageData>,
 }
 
 impl CosmosViewer {
 
-    pub fn new() -> Self {
+    pub fn new(arguments: Arguments) -> Self {
         Self {
+            arguments,
+            playlist: None,
+            config_layers: None,
+            config: Config::default(),
+            session: None,
+            tour: None,
+            project: None,
+            image: None,
         }
     }
 
@@ -23,28 +64,1112 @@ impl CosmosViewer {
         EventLoop::new().unwrap()
     }
     
-    pub fn run() -> JoinHandle<()> {
-        //self.app = Application::new();
-        std::thread::spawn( || {
-            Self::create_event_loop().run_app(&mut Application::new()).unwrap();
+    pub fn run(self) -> JoinHandle<()> {
+        let playlist = self.playlist.expect("load_config must be called before run");
+        let config_layers = self.config_layers.expect("load_config must be called before run");
+        let image = self.image.expect("load_image must be called before run");
+        let config = self.config;
+        let session = self.session;
+        let tour = self.tour;
+        let project = self.project;
+        let arguments = self.arguments;
+        std::thread::spawn( move || {
+            let mut application = Application::new(playlist, image, config_layers, config, session, &arguments);
+            application.project = project;
+            match (tour, arguments.render_tour) {
+                (Some(tour), Some(dir)) => application.export_request = Some((tour, dir)),
+                (Some(tour), None) => application.play_tour(tour),
+                _ => (),
+            }
+            Self::create_event_loop().run_app(&mut application).unwrap();
         })
     }
 
-    pub fn load_image(&mut self) -> Result<(), &'static str> {
-        // TODO : load image
+    /// Open the scenes of the project or the playlist given on the command line, else the
+    /// one of the last session, and resolve the configuration of its first panorama.
+    pub fn load_config(&mut self) -> Result<(), String> {
+        if self.arguments.project.is_some() && !self.arguments.paths.is_empty() {
+            return Err("Option \"--project\" opens the images of the project, no others can be given".to_string());
+        }
+        self.project = self.arguments.project.as_deref().map(Project::load).transpose()?;
+        let mut session = match self.arguments.no_session {
+            true => None,
+            false => Session::load(),
+        };
+        let restored = match &session {
+            Some(session) if self.arguments.paths.is_empty() && self.project.is_none() => Playlist::from_entries(session.images.clone(), session.index),
+            _ => None,
+        };
+        let scenes = self.project.as_ref().and_then(|project| Playlist::from_entries(project.images(), project.start_index()));
+        let playlist = match (scenes, restored) {
+            (Some(scenes), _) => {
+                if let Some(session) = session.as_mut() {
+                    session.view = None;
+                }
+                scenes
+            }
+            (None, Some(playlist)) => {
+                println!("Restoring the last session, {} images", playlist.len());
+                playlist
+            }
+            (None, None) => {
+                // the saved view belongs to another image
+                if let Some(session) = session.as_mut() {
+                    session.view = None;
+                }
+                match self.arguments.paths.is_empty() {
+                    true => Playlist::from_paths(&["image.png".to_string()])?,
+                    false => Playlist::from_paths(&self.arguments.paths)?,
+                }
+            }
+        };
+        self.session = session;
+        self.tour = self.arguments.tour.as_deref().map(Tour::load).transpose()?;
+        if self.arguments.render_tour.is_some() && self.tour.is_none() {
+            return Err("Option \"--render-tour\" needs a tour, given with --tour FILE".to_string());
+        }
+        let config_layers = ConfigLayers::load(&self.arguments.overrides)?;
+        let (config, error) = config_layers.resolve_panorama(playlist.current());
+        // there is no window to show it yet
+        if let Some(why) = error {
+            println!("{why}");
+        }
+        self.config = config;
+        self.config_layers = Some(config_layers);
+        self.playlist = Some(playlist);
         Ok(())
     }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Decode the first image of the playlist.
+    pub fn load_image(&mut self) -> Result<(), String> {
+        let playlist = self.playlist.as_ref().expect("load_config must be called before load_image");
+        self.image = Some(ImageData::new(playlist.current())?);
+        Ok(())
+    }
+}
+
+/// Automatic change of panorama and rotation of the camera.
+struct Slideshow {
+    duration: Option<Duration>,
+    rotation_speed: f32,
+    shown_at: Instant,
+    last_tick: Instant,
 }
 
+impl Slideshow {
+    fn new(config: &ViewerConfig) -> Self {
+        let mut slideshow = Self {
+            duration: None,
+            rotation_speed: 0.0,
+            shown_at: Instant::now(),
+            last_tick: Instant::now(),
+        };
+        slideshow.apply_config(config);
+        slideshow
+    }
+
+    fn apply_config(&mut self, config: &ViewerConfig) {
+        self.duration = (config.slideshow > 0.0).then(|| Duration::from_secs_f32(config.slideshow));
+        self.rotation_speed = config.rotation_speed;
+    }
+}
+
+// Gamepads and background loads are polled, they do not wake up winit
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
 pub struct Application {
     state: Option<State>,
+    // windows drawn by the state next to its own, linked to its view or navigated on their own
+    extra_windows: Vec<ExtraWindow>,
+    // mouse motion turns the camera of the focused window
+    focused_window: Option<WindowId>,
+    gamepad: Option<Box<dyn GamepadSource>>,
+    window_settings: WindowSettings,
+    window_mode: WindowMode,
+    // placement to restore when leaving fullscreen and to save at exit
+    windowed_geometry: Option<WindowGeometry>,
+    playlist: Playlist,
+    loader: ImageLoader,
+    // first image, until the window exists
+    initial_image: Option<ImageData>,
+    // image displayed as soon as it is decoded
+    waiting_image: Option<PathBuf>,
+    slideshow: Slideshow,
+    config_layers: ConfigLayers,
+    // configuration of the displayed panorama
+    config: Config,
+    // reload the displayed image when it changes on disk
+    watcher: Option<FileWatcher>,
+    // the waiting image replaces the same file, keep the view
+    reloading: bool,
+    // development mode, shaders read from a directory and rebuilt when they change
+    shader_dir: Option<PathBuf>,
+    shader_watcher: FileWatcher,
+    bookmarks: Bookmarks,
+    hotspots: Hotspots,
+    regions: Regions,
+    // images of the nadir and zenith patches on the GPU
+    patch_images: [Option<PathBuf>; 2],
+    // second panorama on the GPU, compared with the displayed one
+    comparison_image: Option<PathBuf>,
+    // the wipe divider follows the cursor until the button is released
+    dragging_divider: bool,
+    // virtual tour given with --project and the links of the current scene
+    project: Option<Project>,
+    links: Vec<Hotspot>,
+    // blend the next panorama with the current one, when walking to a linked scene
+    scene_transition: bool,
+    hotspot_editing: bool,
+    measurement: Measurement,
+    measuring: bool,
+    // hotspot following the cursor in edit mode, saved when the button is released
+    dragged_hotspot: Option<usize>,
+    // view given on the command line, until the window exists
+    initial_view: Option<View>,
+    // view of a hotspot target, shown once its panorama is decoded
+    pending_view: Option<View>,
+    // kept open, the copied text vanishes with it on X11
+    clipboard: Option<arboard::Clipboard>,
+    // save the session at exit
+    save_session: bool,
+    // last tour loaded or recorded, played with ToggleTour
+    tour: Option<Tour>,
+    tour_player: Option<TourPlayer>,
+    tour_recorder: Option<TourRecorder>,
+    tour_tick: Instant,
+    // restored when the tour stops, tours are played in free mode for roll and slerp
+    mode_before_tour: Option<OrientationMode>,
+    // tour to render and its output directory, until the window exists
+    export_request: Option<(Tour, PathBuf)>,
+    export: Option<TourExport>,
 }
 
 impl Application {
     
-    pub fn new() -> Self {
+    fn new(playlist: Playlist, image: ImageData, config_layers: ConfigLayers, config: Config, session: Option<Session>, arguments: &Arguments) -> Self {
+        let window_settings = config.window.clone();
+        let session = session.unwrap_or_default();
         Self {
             state: None,
+            extra_windows: Vec::new(),
+            focused_window: None,
+            bookmarks: Bookmarks::empty(playlist.current()),
+            hotspots: Hotspots::empty(playlist.current()),
+            regions: Regions::empty(playlist.current()),
+            patch_images: [None, None],
+            comparison_image: None,
+            dragging_divider: false,
+            project: None,
+            links: Vec::new(),
+            scene_transition: false,
+            hotspot_editing: false,
+            measurement: Measurement::default(),
+            measuring: false,
+            dragged_hotspot: None,
+            initial_view: arguments.view.or(session.view),
+            pending_view: None,
+            clipboard: None,
+            save_session: !arguments.no_session && arguments.render_tour.is_none(),
+            tour: None,
+            tour_player: None,
+            tour_recorder: None,
+            tour_tick: Instant::now(),
+            mode_before_tour: None,
+            export_request: None,
+            export: None,
+            playlist,
+            loader: ImageLoader::new(),
+            initial_image: Some(image),
+            waiting_image: None,
+            slideshow: Slideshow::new(&config.viewer),
+            watcher: config.viewer.watch.then(FileWatcher::new),
+            config_layers,
+            config,
+            reloading: false,
+            shader_dir: arguments.shader_dir.clone(),
+            shader_watcher: FileWatcher::new(),
+            gamepad: gamepad::default_source(),
+            window_mode: window_settings.mode,
+            windowed_geometry: session.window.filter(|_| window_settings.remember_geometry),
+            window_settings,
+        }
+    }
+
+    fn process_action(&mut self, event_loop: &ActiveEventLoop, action: Action) {
+        let state = self.state.as_mut().unwrap();
+        match action {
+            Action::Quit => self.quit(event_loop),
+            Action::KioskExit => {
+                println!("Closing window");
+                event_loop.exit();
+            }
+            Action::ToggleFullscreen => {
+                let mode = if self.window_mode == WindowMode::Borderless { WindowMode::Windowed } else { WindowMode::Borderless };
+                self.set_window_mode(event_loop, mode);
+            }
+            Action::ToggleExclusiveFullscreen => {
+                let mode = if self.window_mode == WindowMode::Exclusive { WindowMode::Windowed } else { WindowMode::Exclusive };
+                self.set_window_mode(event_loop, mode);
+            }
+            Action::OpenWindow => self.open_window(event_loop, &ExtraWindowSettings { linked: false, ..Default::default() }),
+            Action::Screenshot => {
+                let message = match state.screenshot() {
+                    Ok(filename) => format!("Screenshot saved to \"{filename}\""),
+                    Err(why) => format!("Screenshot failed : {why}"),
+                };
+                self.report(&message);
+            }
+            Action::ExportEquirectangular => {
+                let path = sidecar::baked_path(self.playlist.current());
+                let message = match state.render_equirectangular().and_then(|image| image.save(&path).map_err(|why| why.to_string())) {
+                    Ok(()) => format!("Panorama saved to \"{}\"", path.display()),
+                    Err(why) => format!("Export failed : {why}"),
+                };
+                self.report(&message);
+            }
+            Action::ToggleHud => state.toggle_hud(),
+            Action::ToggleHotspotEditing => self.toggle_hotspot_editing(),
+            Action::ToggleMeasuring => self.toggle_measuring(),
+            Action::RemoveMeasurePoint => {
+                if self.measurement.remove_last().is_some() {
+                    self.show_measurement();
+                }
+            }
+            Action::ClearMeasurement => {
+                self.measurement.clear();
+                self.show_measurement();
+            }
+            Action::ExportMeasurement => self.export_measurement(),
+            Action::AddRegion => self.add_region(),
+            Action::CycleRegionEffect => {
+                let Some(index) = state.region_at_cursor() else {
+                    self.report("No region under the cursor");
+                    return;
+                };
+                match self.regions.cycle_effect(index) {
+                    Ok(region) => {
+                        let message = format!("\"{}\" : {:?}", region.name, region.effect);
+                        self.mark_written(self.regions.path().to_path_buf());
+                        self.show_regions();
+                        self.report(&message);
+                    }
+                    Err(why) => self.report(&format!("Unable to change the region : {why}")),
+                }
+            }
+            Action::RemoveRegion => {
+                let Some(index) = state.region_at_cursor() else {
+                    self.report("No region under the cursor");
+                    return;
+                };
+                match self.regions.remove(index) {
+                    Ok(region) => {
+                        self.mark_written(self.regions.path().to_path_buf());
+                        self.show_regions();
+                        self.report(&format!("Deleted \"{}\"", region.name));
+                    }
+                    Err(why) => self.report(&format!("Unable to delete the region : {why}")),
+                }
+            }
+            Action::ToggleRegionHighlights => {
+                let shown = state.toggle_region_highlights();
+                self.report(&format!("Region highlights {}", if shown { "shown" } else { "hidden" }));
+            }
+            Action::ToggleMinimap => {
+                let shown = state.toggle_minimap();
+                self.report(&format!("Minimap {}", if shown { "shown" } else { "hidden" }));
+            }
+            Action::ToggleMinimapMode => {
+                let mode = state.toggle_minimap_mode();
+                self.report(&format!("Minimap : {mode:?}"));
+            }
+            Action::ToggleComparison | Action::CycleComparisonMode | Action::FlipComparison if self.comparison_image.is_none() => {
+                self.report("No panorama to compare with, open one with --compare FILE");
+            }
+            Action::ToggleComparison => {
+                let shown = state.toggle_comparison();
+                self.report(&format!("Comparison {}", if shown { "shown" } else { "hidden" }));
+            }
+            Action::CycleComparisonMode => {
+                let mode = state.cycle_comparison_mode();
+                self.report(&format!("Comparison : {mode:?}"));
+            }
+            Action::FlipComparison => state.flip_comparison(),
+            Action::ToggleHorizonLine | Action::ToggleGrid | Action::ToggleCompass | Action::ToggleCrosshair => {
+                if let Some((name, shown)) = state.toggle_overlay(action) {
+                    self.report(&format!("{name} {}", if shown { "shown" } else { "hidden" }));
+                }
+            }
+            Action::NextImage => self.step_image(true),
+            Action::PreviousImage => self.step_image(false),
+            Action::PastePath => self.paste_path(),
+            Action::CopyView => self.copy_view(),
+            Action::ToggleTour => self.toggle_tour(),
+            Action::StopTour => self.stop_tour(),
+            Action::ToggleTourRecording => self.toggle_tour_recording(),
+            Action::RecordKeyframe => self.record_keyframe(),
+            _ => match action.bookmark() {
+                Some((slot, true)) => self.save_bookmark(slot),
+                Some((slot, false)) => self.go_to_bookmark(slot),
+                None => (),
+            },
+        }
+    }
+
+    fn step_image(&mut self, forward: bool) {
+        if self.playlist.len() < 2 {
+            self.report("No other image to open");
+            return;
+        }
+        self.reloading = false;
+        self.pending_view = None;
+        self.scene_transition = false;
+        if forward {
+            self.playlist.next();
+        }
+        else {
+            self.playlist.previous();
+        }
+        self.show_current_image();
+    }
+
+    /// Display the current image of the playlist if it is decoded, else wait for it.
+    fn show_current_image(&mut self) {
+        let state = self.state.as_mut().unwrap();
+        let path = self.playlist.current().to_path_buf();
+
+        match self.loader.take(&path) {
+            Some(Ok(image)) => {
+                self.resolve_config(&path);
+                let transition = self.project.as_ref()
+                    .filter(|_| std::mem::take(&mut self.scene_transition))
+                    .map(|project| (project.transition, project.transition_duration));
+                let state = self.state.as_mut().unwrap();
+                if let Some((transition, duration)) = transition {
+                    state.begin_scene_transition(transition, duration);
+                }
+                state.apply_config(&self.config);
+                self.slideshow.apply_config(&self.config.viewer);
+                self.load_patches();
+                self.load_comparison();
+                let state = self.state.as_mut().unwrap();
+                state.set_image(&image);
+                if self.config.viewer.reset_view_on_open && !self.reloading {
+                    state.reset_view();
+                }
+                state.set_title(&self.playlist.title(&self.window_settings.title));
+                state.set_image_name(&self.playlist.name());
+                self.waiting_image = None;
+                self.slideshow.shown_at = Instant::now();
+                self.load_bookmarks();
+                self.load_hotspots();
+                self.load_regions();
+                if !self.reloading && !self.measurement.is_empty() {
+                    self.measurement.clear();
+                    self.show_measurement();
+                }
+                let state = self.state.as_mut().unwrap();
+                if let Some(view) = self.pending_view.take() {
+                    state.set_view(&view, 0.0);
+                }
+                if let Some((SceneTransition::ZoomBlend, duration)) = transition {
+                    // the next scene comes from the depth of the link
+                    let view = state.view();
+                    state.set_view(&View { fov: view.fov / 2.0, ..view }, 0.0);
+                    state.animate_view(&view, duration, Easing::EaseOut);
+                }
+            }
+            // an image being rewritten keeps its previous version on screen
+            Some(Err(why)) if self.reloading => {
+                println!("Unable to reload image : {why}");
+                state.show_message(&format!("Unable to reload image : {why}"));
+                self.waiting_image = None;
+            }
+            Some(Err(why)) => {
+                self.pending_view = None;
+                self.scene_transition = false;
+                println!("Unable to open image : {why}");
+                state.show_message(&format!("Unable to open image : {why}"));
+                state.set_title(&format!("{} - unable to open image", self.playlist.title(&self.window_settings.title)));
+                self.waiting_image = None;
+                self.slideshow.shown_at = Instant::now();
+            }
+            None => {
+                state.set_title(&format!("{} - loading", self.playlist.title(&self.window_settings.title)));
+                self.loader.request(&path);
+                self.waiting_image = Some(path.clone());
+            }
+        }
+
+        if self.waiting_image.is_none() {
+            self.reloading = false;
+        }
+
+        self.preload_around(path);
+    }
+
+    /// Decode the images next to `path` in the playlist and the project, watch its files
+    /// and forget the other decoded images.
+    fn preload_around(&mut self, path: PathBuf) {
+        let mut keep = self.playlist.neighbours();
+        if let Some(project) = self.project.as_ref() {
+            keep.extend(project.linked_images(&path));
+        }
+        for neighbour in &keep {
+            self.loader.request(neighbour);
+        }
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.watch(&[path.clone(), sidecar::sidecar_path(&path), sidecar::hotspots_path(&path), sidecar::regions_path(&path)]);
+        }
+        keep.push(path);
+        self.loader.retain(&keep);
+    }
+
+    /// Configuration of the image at `path`, from the layers and the project.
+    fn resolve_config(&mut self, path: &Path) {
+        let (config, error) = self.config_layers.resolve_panorama(path);
+        self.config = config;
+        if let Some(why) = error {
+            self.report(&why);
+        }
+        if let Some(north) = self.project.as_ref().and_then(|project| project.north(path)) {
+            self.config.viewer.north = north;
+        }
+    }
+
+    /// Reload what depends on the watched files which changed, the image is decoded again
+    /// only when it changed itself.
+    fn reload_changed(&mut self, changed: &[PathBuf]) {
+        let path = self.playlist.current().to_path_buf();
+        if changed.contains(&path) {
+            self.reload_current_image();
+            return;
+        }
+        for file in changed {
+            if *file == sidecar::sidecar_path(&path) {
+                self.resolve_config(&path);
+                self.state.as_mut().unwrap().apply_config(&self.config);
+                self.slideshow.apply_config(&self.config.viewer);
+                self.load_patches();
+                self.load_comparison();
+                self.load_bookmarks();
+            }
+            else if *file == sidecar::hotspots_path(&path) {
+                self.load_hotspots();
+            }
+            else if *file == sidecar::regions_path(&path) {
+                self.load_regions();
+            }
+        }
+    }
+
+    /// Files written by the application are not reloaded by the watcher.
+    fn mark_written(&mut self, path: PathBuf) {
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.written(&path);
+        }
+    }
+
+    fn reload_current_image(&mut self) {
+        let path = self.playlist.current().to_path_buf();
+        println!("Reloading \"{}\"", path.display());
+        self.loader.reload(&path);
+        self.waiting_image = Some(path);
+        self.reloading = true;
+    }
+
+    /// Add an image to the playlist and display it in place of the current one.
+    fn open_path(&mut self, path: PathBuf) {
+        if !path.is_file() || !Playlist::is_image(&path) {
+            self.report(&format!("Unsupported file \"{}\"", path.display()));
+            return;
+        }
+        self.playlist.insert(path);
+        self.reloading = false;
+        self.show_current_image();
+    }
+
+    fn clipboard(&mut self) -> Result<&mut arboard::Clipboard, arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+
+    /// Open the path in the clipboard, or go to the view it describes.
+    fn paste_path(&mut self) {
+        let text = self.clipboard().and_then(|clipboard| clipboard.get_text());
+        match text {
+            Ok(text) => {
+                if let Ok(view) = text.parse::<View>() {
+                    self.state.as_mut().unwrap().set_view(&view, self.config.viewer.transition_duration);
+                    return;
+                }
+                // file managers copy paths as quoted strings or uris
+                let path = text.trim().trim_matches(['"', '\'']);
+                let path = path.strip_prefix("file://").unwrap_or(path);
+                self.open_path(PathBuf::from(path));
+            }
+            Err(why) => self.report(&format!("Nothing to paste : {why}")),
+        }
+    }
+
+    fn copy_view(&mut self) {
+        let view = self.state.as_ref().unwrap().view().to_string();
+        match self.clipboard().and_then(|clipboard| clipboard.set_text(view.clone())) {
+            Ok(()) => self.report(&format!("View {view} copied")),
+            Err(why) => self.report(&format!("Unable to copy the view : {why}")),
+        }
+    }
+
+    fn load_bookmarks(&mut self) {
+        let path = self.playlist.current().to_path_buf();
+        self.bookmarks = match Bookmarks::load(&path) {
+            Ok(bookmarks) => bookmarks,
+            Err(why) => {
+                self.report(&format!("Unable to read the bookmarks of \"{}\" : {why}", path.display()));
+                Bookmarks::empty(&path)
+            }
+        };
+    }
+
+    fn go_to_bookmark(&mut self, slot: u8) {
+        let Some(bookmark) = self.bookmarks.get(slot) else {
+            self.report(&format!("No bookmark {slot}, save one with Ctrl+{slot}"));
+            return;
+        };
+        let message = format!("{slot} : {}", bookmark.name);
+        self.state.as_mut().unwrap().set_view(&bookmark.view, self.config.viewer.transition_duration);
+        self.report(&message);
+    }
+
+    fn save_bookmark(&mut self, slot: u8) {
+        let view = self.state.as_ref().unwrap().view();
+        match self.bookmarks.save(slot, view) {
+            Ok(bookmark) => {
+                let message = format!("Saved {slot} : {}", bookmark.name);
+                self.mark_written(sidecar::sidecar_path(self.playlist.current()));
+                self.report(&message);
+            }
+            Err(why) => self.report(&format!("Unable to save bookmark {slot} : {why}")),
+        }
+    }
+
+    fn load_hotspots(&mut self) {
+        let path = self.playlist.current().to_path_buf();
+        self.hotspots = match Hotspots::load(&path) {
+            Ok(hotspots) => hotspots,
+            Err(why) => {
+                self.report(&format!("Unable to read the hotspots of \"{}\" : {why}", path.display()));
+                Hotspots::empty(&path)
+            }
+        };
+        self.dragged_hotspot = None;
+        self.links = self.project.as_ref()
+            .map(|project| project.link_hotspots(&path))
+            .unwrap_or_default();
+        self.show_hotspots();
+    }
+
+    /// Upload the nadir and zenith images of the configuration when they change.
+    fn load_patches(&mut self) {
+        let images = [
+            (patches::NADIR, self.config.nadir.image.clone()),
+            (patches::ZENITH, self.config.zenith.image.clone()),
+        ];
+        for (layer, path) in images {
+            if path == self.patch_images[layer] {
+                continue;
+            }
+            self.patch_images[layer] = path.clone();
+            let image = match path.as_deref().map(patches::load).transpose() {
+                Ok(image) => image,
+                Err(why) => {
+                    self.report(&format!("Unable to read the patch : {why}"));
+                    None
+                }
+            };
+            self.state.as_mut().unwrap().set_patch(layer, image.as_ref());
+        }
+    }
+
+    /// Upload the panorama compared with the displayed one when it changes.
+    fn load_comparison(&mut self) {
+        if self.config.comparison.image == self.comparison_image {
+            return;
+        }
+        self.comparison_image = self.config.comparison.image.clone();
+        let image = match self.comparison_image.as_deref().map(ImageData::new).transpose() {
+            Ok(image) => image,
+            Err(why) => {
+                self.report(&format!("Unable to read the compared panorama : {why}"));
+                None
+            }
+        };
+        self.state.as_mut().unwrap().set_comparison_image(image.as_ref());
+    }
+
+    fn load_regions(&mut self) {
+        let path = self.playlist.current().to_path_buf();
+        self.regions = match Regions::load(&path) {
+            Ok(regions) => regions,
+            Err(why) => {
+                self.report(&format!("Unable to read the regions of \"{}\" : {why}", path.display()));
+                Regions::empty(&path)
+            }
+        };
+        self.show_regions();
+    }
+
+    fn show_regions(&mut self) {
+        self.state.as_mut().unwrap().set_regions(self.regions.all());
+    }
+
+    /// Turn the measured polygon into a region.
+    fn add_region(&mut self) {
+        if !self.measurement.is_polygon() {
+            self.report("Measure a polygon of at least 3 points first");
+            return;
+        }
+        let points = self.measurement.points().iter().map(|point| [point.azimuth, point.elevation]).collect();
+        match self.regions.add(points) {
+            Ok(index) => {
+                let message = format!("Added \"{}\" to \"{}\"", self.regions.all()[index].name, self.regions.path().display());
+                self.measurement.clear();
+                self.show_measurement();
+                self.mark_written(self.regions.path().to_path_buf());
+                self.show_regions();
+                self.report(&message);
+            }
+            Err(why) => self.report(&format!("Unable to add the region : {why}")),
+        }
+    }
+
+    /// Hotspots of the sidecar file followed by the links of the scene.
+    fn show_hotspots(&mut self) {
+        let hotspots: Vec<Hotspot> = self.hotspots.all().iter().chain(&self.links).cloned().collect();
+        self.state.as_mut().unwrap().set_hotspots(&hotspots);
+    }
+
+    /// Hotspot of the combined list and the panorama it leads to.
+    fn hotspot(&self, index: usize) -> Option<(Hotspot, Option<PathBuf>)> {
+        match self.hotspots.get(index) {
+            Some(hotspot) => Some((hotspot.clone(), self.hotspots.target(index))),
+            None => {
+                let link = self.links.get(index - self.hotspots.all().len())?;
+                Some((link.clone(), link.target.clone()))
+            }
+        }
+    }
+
+    fn toggle_hotspot_editing(&mut self) {
+        if self.measuring {
+            self.toggle_measuring();
+        }
+        self.hotspot_editing = !self.hotspot_editing;
+        self.dragged_hotspot = None;
+        self.state.as_mut().unwrap().set_hotspot_editing(self.hotspot_editing);
+        self.report(match self.hotspot_editing {
+            true => "Editing hotspots",
+            false => "Hotspot editing done",
+        });
+    }
+
+    fn toggle_measuring(&mut self) {
+        if self.hotspot_editing {
+            self.toggle_hotspot_editing();
+        }
+        self.measuring = !self.measuring;
+        self.state.as_mut().unwrap().set_measuring(self.measuring);
+        self.report(match self.measuring {
+            true => "Measuring",
+            false => "Measuring done",
+        });
+    }
+
+    fn show_measurement(&mut self) {
+        self.state.as_mut().unwrap().set_measurement(&self.measurement);
+    }
+
+    fn export_measurement(&mut self) {
+        if self.measurement.is_empty() {
+            self.report("Nothing to export, pick points in measurement mode first");
+            return;
+        }
+        match self.measurement.export(self.playlist.current()) {
+            Ok(paths) => {
+                let paths: Vec<String> = paths.iter().map(|path| format!("\"{}\"", path.display())).collect();
+                self.report(&format!("Measurement saved to {}", paths.join(" and ")));
+            }
+            Err(why) => self.report(&format!("Unable to export the measurement : {why}")),
+        }
+    }
+
+    // Pick a point under the cursor, or remove the last one
+    fn measure_input(&mut self, button: MouseButton) {
+        match button {
+            MouseButton::Left => {
+                let Some((azimuth, elevation)) = self.state.as_ref().unwrap().cursor_direction() else {
+                    return;
+                };
+                self.measurement.add(azimuth, elevation);
+                let count = self.measurement.points().len();
+                self.report(&format!("Point {count} : {azimuth:.2}° {elevation:+.2}°"));
+            }
+            MouseButton::Right => {
+                self.measurement.remove_last();
+            }
+            _ => return,
+        }
+        self.show_measurement();
+    }
+
+    fn mouse_input(&mut self, button: MouseButton, pressed: bool) {
+        let state = self.state.as_mut().unwrap();
+        if button == MouseButton::Left && (self.dragging_divider || state.is_over_divider()) {
+            self.dragging_divider = pressed;
+            return;
+        }
+        // clicking the minimap turns the camera to the point under the cursor
+        if let Some((azimuth, elevation)) = state.minimap_direction() {
+            if button == MouseButton::Left && pressed {
+                let view = View { azimuth, elevation, ..state.view() };
+                state.set_view(&view, self.config.viewer.transition_duration);
+            }
+            return;
+        }
+        if self.measuring {
+            if pressed {
+                self.measure_input(button);
+            }
+            return;
+        }
+        let hotspot = state.hotspot_at_cursor();
+        if self.hotspot_editing && pressed && hotspot.is_some_and(|index| index >= self.hotspots.all().len()) {
+            self.report("Links between scenes are edited in the project file");
+            return;
+        }
+        match (self.hotspot_editing, button, pressed, hotspot) {
+            (false, MouseButton::Left, true, Some(index)) => self.activate_hotspot(index),
+            (true, MouseButton::Left, true, Some(index)) => self.dragged_hotspot = Some(index),
+            (true, MouseButton::Left, true, None) => {
+                let Some((azimuth, elevation)) = state.cursor_direction() else {
+                    return;
+                };
+                match self.hotspots.add(azimuth, elevation) {
+                    Ok(index) => {
+                        let message = format!("Added \"{}\" to \"{}\"", self.hotspots.all()[index].title, self.hotspots.path().display());
+                        self.report(&message);
+                    }
+                    Err(why) => self.report(&format!("Unable to add the hotspot : {why}")),
+                }
+            }
+            (true, MouseButton::Left, false, _) => {
+                let Some(index) = self.dragged_hotspot.take() else {
+                    return;
+                };
+                let Some(hotspot) = self.hotspots.get(index) else {
+                    return;
+                };
+                if let Err(why) = self.hotspots.move_to(index, hotspot.azimuth, hotspot.elevation, true) {
+                    self.report(&format!("Unable to move the hotspot : {why}"));
+                }
+            }
+            (true, MouseButton::Right, true, Some(index)) => {
+                match self.hotspots.remove(index) {
+                    Ok(hotspot) => self.report(&format!("Deleted \"{}\"", hotspot.title)),
+                    Err(why) => self.report(&format!("Unable to delete the hotspot : {why}")),
+                }
+            }
+            _ => return,
+        }
+        self.mark_written(self.hotspots.path().to_path_buf());
+        self.show_hotspots();
+    }
+
+    fn cursor_moved(&mut self) {
+        let state = self.state.as_mut().unwrap();
+        if self.dragging_divider {
+            state.drag_divider();
+            return;
+        }
+        let Some(index) = self.dragged_hotspot else {
+            state.hover_hotspot();
+            return;
+        };
+        if let Some((azimuth, elevation)) = state.cursor_direction() {
+            // saved on release, the file is not rewritten at each move
+            let _ = self.hotspots.move_to(index, azimuth, elevation, false);
+            self.show_hotspots();
+        }
+    }
+
+    /// Show the description of a hotspot and follow its link.
+    fn activate_hotspot(&mut self, index: usize) {
+        let Some((hotspot, target)) = self.hotspot(index) else {
+            return;
+        };
+        let message = match hotspot.description.is_empty() {
+            true => hotspot.title.clone(),
+            false => format!("{}\n{}", hotspot.title, hotspot.description),
+        };
+        self.report(&message);
+
+        match target {
+            Some(target) if !target.is_file() => self.report(&format!("Missing panorama \"{}\"", target.display())),
+            Some(target) => {
+                // scenes of a project keep the heading of the user and blend into each other
+                let current = self.playlist.current();
+                let view = self.state.as_ref().unwrap().view();
+                let heading = self.project.as_ref().and_then(|project| project.keep_heading(current, &target, view));
+                self.scene_transition = heading.is_some();
+                self.pending_view = hotspot.target_view.or(heading);
+                self.playlist.select(&target);
+                self.reloading = false;
+                self.show_current_image();
+            }
+            None => {
+                if let Some(view) = hotspot.target_view {
+                    self.state.as_mut().unwrap().set_view(&view, self.config.viewer.transition_duration);
+                }
+            }
+        }
+    }
+
+    /// Start `tour` from its first keyframe, the tour is kept to be played again.
+    fn play_tour(&mut self, tour: Tour) {
+        self.tour = Some(tour.clone());
+        self.tour_player = Some(TourPlayer::new(tour));
+        self.tour_tick = Instant::now();
+        if let Some(state) = self.state.as_mut() {
+            self.mode_before_tour.get_or_insert(state.orientation_mode());
+            state.set_orientation_mode(OrientationMode::Free);
+            state.pause_animation(false);
+        }
+    }
+
+    fn toggle_tour(&mut self) {
+        match self.tour_player.as_mut() {
+            Some(player) => {
+                let paused = !player.is_paused();
+                player.set_paused(paused);
+                self.tour_tick = Instant::now();
+                self.state.as_mut().unwrap().pause_animation(paused);
+                self.report(if paused { "Tour paused" } else { "Tour resumed" });
+            }
+            None => match self.tour.clone() {
+                Some(tour) => self.play_tour(tour),
+                None => self.report("No tour, open one with --tour or record one with R"),
+            },
+        }
+    }
+
+    fn stop_tour(&mut self) {
+        if self.tour_player.take().is_none() {
+            return;
+        }
+        let state = self.state.as_mut().unwrap();
+        state.pause_animation(false);
+        if let Some(mode) = self.mode_before_tour.take() {
+            state.set_orientation_mode(mode);
+        }
+        println!("Tour stopped");
+    }
+
+    fn update_tour(&mut self) {
+        let elapsed = self.tour_tick.elapsed().as_secs_f32();
+        self.tour_tick = Instant::now();
+        let Some(player) = self.tour_player.as_mut() else {
+            return;
+        };
+        if self.mode_before_tour.is_none() {
+            // the tour was given on the command line, before the window existed
+            let state = self.state.as_mut().unwrap();
+            self.mode_before_tour = Some(state.orientation_mode());
+            state.set_orientation_mode(OrientationMode::Free);
+        }
+
+        // a playing tour is not idle
+        self.state.as_mut().unwrap().reset_idle();
+        let current_image = self.waiting_image.is_none().then(|| self.playlist.current().to_path_buf());
+        let commands = player.update(elapsed, current_image.as_deref());
+        if let Some(next_image) = player.next_image() {
+            self.loader.request(next_image);
+        }
+        for command in commands {
+            let state = self.state.as_mut().unwrap();
+            match command {
+                TourCommand::Open(path) => {
+                    self.playlist.select(&path);
+                    self.reloading = false;
+                    self.show_current_image();
+                }
+                TourCommand::Jump(view) => state.set_view(&view, 0.0),
+                TourCommand::Animate { view, duration, easing } => state.animate_view(&view, duration, easing),
+                TourCommand::Finished => self.stop_tour(),
+            }
+        }
+    }
+
+    fn toggle_tour_recording(&mut self) {
+        let Some(recorder) = self.tour_recorder.take() else {
+            self.tour_recorder = Some(TourRecorder::new());
+            self.report("Recording a tour, add keyframes with Enter and stop with R");
+            return;
+        };
+        if recorder.len() == 0 {
+            self.report("Tour recording cancelled, no keyframes");
+            return;
+        }
+
+        let tour = recorder.finish();
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let filename = format!("tour_{}.toml", timestamp.as_millis());
+        match tour.save(Path::new(&filename)) {
+            Ok(()) => self.report(&format!("Tour of {} keyframes saved to \"{filename}\"", tour.keyframes.len())),
+            Err(why) => self.report(&format!("Unable to save the tour : {why}")),
+        }
+        self.tour = Some(tour);
+    }
+
+    fn record_keyframe(&mut self) {
+        let view = self.state.as_ref().unwrap().view();
+        let Some(recorder) = self.tour_recorder.as_mut() else {
+            self.report("Not recording a tour, start with R");
+            return;
+        };
+        recorder.add(self.playlist.current(), view);
+        let message = format!("Keyframe {} : {view}", recorder.len());
+        self.report(&message);
+    }
+
+    /// Show a message over the view for a few seconds.
+    fn report(&mut self, message: &str) {
+        println!("{message}");
+        if let Some(state) = self.state.as_mut() {
+            state.show_message(message);
+        }
+    }
+
+    fn reload_shader(&mut self) {
+        let Some(dir) = self.shader_dir.as_ref() else {
+            return;
+        };
+        let state = self.state.as_mut().unwrap();
+        match shaders::read_shader(dir).and_then(|source| state.reload_shader(&source)) {
+            Ok(()) => {
+                let message = format!("Shader loaded from \"{}\"", dir.display());
+                self.report(&message);
+            }
+            Err(why) => {
+                println!("Shader error, keeping the previous shader :\n{why}");
+                let summary = why.lines().find(|line| !line.trim().is_empty()).unwrap_or_default().to_string();
+                self.report(&format!("Shader error : {summary}"));
+            }
+        }
+    }
+
+    fn quit(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window_settings.kiosk {
+            self.report("Quitting is disabled in kiosk mode");
+        }
+        else {
+            println!("Closing window");
+            event_loop.exit();
+        }
+    }
+
+    fn set_window_mode(&mut self, event_loop: &ActiveEventLoop, mode: WindowMode) {
+        let state = self.state.as_mut().unwrap();
+        if self.window_mode == WindowMode::Windowed {
+            self.windowed_geometry = Some(WindowGeometry::of(state.window()));
+        }
+        let monitor = self.window_settings.select_monitor(event_loop, self.windowed_geometry.as_ref());
+        state.window().set_fullscreen(window_settings::fullscreen(mode, monitor));
+        self.window_mode = mode;
+        println!("Window mode : {mode:?}");
+    }
+
+    fn open_window(&mut self, event_loop: &ActiveEventLoop, settings: &ExtraWindowSettings) {
+        let title = format!("{} - window {}", self.window_settings.title, self.extra_windows.len() + 2);
+        let window = match event_loop.create_window(settings.window_attributes(&title)) {
+            Ok(window) => window,
+            Err(why) => {
+                self.report(&format!("Unable to open a window : {why}"));
+                return;
+            }
+        };
+        if self.window_settings.kiosk {
+            window.set_cursor_visible(false);
+        }
+        if settings.mode != WindowMode::Windowed {
+            let monitor = window_settings::select_monitor(event_loop, settings.monitor.as_ref(), None);
+            window.set_fullscreen(window_settings::fullscreen(settings.mode, monitor));
+        }
+        match self.state.as_ref().unwrap().open_window(window, settings, &self.config) {
+            Ok(extra_window) => {
+                println!("Opened \"{title}\", {}", if extra_window.is_linked() { "linked to the main view" } else { "with its own view" });
+                self.extra_windows.push(extra_window);
+            }
+            Err(why) => self.report(&format!("Unable to open a window : {why}")),
+        }
+    }
+
+    fn extra_window_event(&mut self, event_loop: &ActiveEventLoop, index: usize, event: WindowEvent) {
+        let state = self.state.as_mut().unwrap();
+        let extra_window = &mut self.extra_windows[index];
+        let actions = match event {
+            WindowEvent::CloseRequested => {
+                if self.window_settings.kiosk {
+                    self.report("Closing windows is disabled in kiosk mode");
+                }
+                else {
+                    self.extra_windows.remove(index);
+                }
+                return;
+            }
+            WindowEvent::Resized(new_size) => {
+                state.resize_window(extra_window, new_size);
+                return;
+            }
+            WindowEvent::RedrawRequested => {
+                if self.export.is_none() {
+                    extra_window.update(&state.view());
+                }
+                if let Err(why) = state.render_window(extra_window) {
+                    println!("Unable to draw the window : {why}");
+                }
+                return;
+            }
+            WindowEvent::DroppedFile(path) => {
+                self.open_path(path);
+                return;
+            }
+            // the rendered tour must not depend on the user
+            _ if self.export.is_some() => return,
+            // the cursor picks in the main window only
+            WindowEvent::CursorMoved { .. } | WindowEvent::MouseInput { .. } if extra_window.is_linked() => return,
+            // navigating in a linked window moves the main view, which it follows
+            _ if extra_window.is_linked() => {
+                if !state.input(&event) {
+                    return;
+                }
+                state.window().request_redraw();
+                state.take_actions()
+            }
+            _ => {
+                if !extra_window.input(&event) {
+                    return;
+                }
+                extra_window.window().request_redraw();
+                extra_window.take_actions()
+            }
+        };
+        for action in actions {
+            self.process_action(event_loop, action);
         }
     }
 
@@ -54,13 +1179,80 @@ impl ApplicationHandler for Application {
 
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = event_loop.create_window(WindowAttributes::default()
-                .with_title("Cosmos360")
-                .with_inner_size(winit::dpi::LogicalSize::new(1920.0, 1080.0))
-                //.with_fullscreen(Some(Fullscreen::Borderless(None)))
+        let Some(image) = self.initial_image.take() else {
+            return;
+        };
+
+        let window = event_loop.create_window(
+            self.window_settings.window_attributes(self.windowed_geometry.as_ref())
         ).unwrap();
 
-        self.state = Some(State::new(window));
+        if self.window_settings.kiosk {
+            window.set_cursor_visible(false);
+        }
+
+        self.state = Some(State::new(window, &image, &self.config));
+        self.state.as_ref().unwrap().set_title(&self.playlist.title(&self.window_settings.title));
+        self.state.as_mut().unwrap().set_image_name(&self.playlist.name());
+        self.load_bookmarks();
+        self.load_hotspots();
+        self.load_regions();
+        self.load_patches();
+        self.load_comparison();
+        if let Some(view) = self.initial_view.take() {
+            self.state.as_mut().unwrap().set_view(&view, 0.0);
+        }
+        if self.export_request.is_none() {
+            for settings in self.window_settings.extra.clone() {
+                self.open_window(event_loop, &settings);
+            }
+        }
+        self.preload_around(self.playlist.current().to_path_buf());
+        self.slideshow.shown_at = Instant::now();
+
+        if self.window_mode != WindowMode::Windowed {
+            let mode = self.window_mode;
+            self.window_mode = WindowMode::Windowed;
+            self.set_window_mode(event_loop, mode);
+        }
+
+        if let Some(dir) = self.shader_dir.clone() {
+            self.shader_watcher.watch(&[shaders::shader_path(&dir)]);
+            self.reload_shader();
+        }
+
+        if let Some((tour, dir)) = self.export_request.take() {
+            let state = self.state.as_mut().unwrap();
+            match TourExport::new(tour, self.config.export.clone(), dir.clone(), self.playlist.current(), state) {
+                Ok(export) => {
+                    println!("Rendering the tour to \"{}\"", dir.display());
+                    self.export = Some(export);
+                }
+                Err(why) => {
+                    println!("Unable to render the tour : {why}");
+                    event_loop.exit();
+                }
+            }
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(state) = self.state.as_ref() else {
+            return;
+        };
+        if self.window_mode == WindowMode::Windowed {
+            self.windowed_geometry = Some(WindowGeometry::of(state.window()));
+        }
+        if self.save_session {
+            Session {
+                images: self.playlist.entries().iter()
+                    .map(|path| path.canonicalize().unwrap_or(path.clone()))
+                    .collect(),
+                index: self.playlist.index(),
+                view: Some(state.view()),
+                window: self.windowed_geometry.clone().filter(|_| self.window_settings.remember_geometry),
+            }.save();
+        }
     }
 
     fn window_event(
@@ -71,31 +1263,171 @@ impl ApplicationHandler for Application {
     ) {
         let state = self.state.as_mut().unwrap();
 
-        if state.window().id() == window_id {
+        if let WindowEvent::Focused(true) = event {
+            self.focused_window = Some(window_id);
+        }
+        if let Some(index) = self.extra_windows.iter().position(|extra_window| extra_window.id() == window_id) {
+            self.extra_window_event(event_loop, index, event);
+        }
+        else if state.window().id() == window_id {
             
-            if state.input(&event) {
+            // the rendered tour must not depend on the user
+            if self.export.is_none() && state.input(&event) {
                 state.window().request_redraw();
+                for action in state.take_actions() {
+                    self.process_action(event_loop, action);
+                }
             }
             else {
                 match event {
                     WindowEvent::CloseRequested => {
-                        println!("Closing window");
-                        event_loop.exit();
+                        self.quit(event_loop);
                     }
                     WindowEvent::RedrawRequested => {
-                        self.state.as_mut().unwrap().update();
+                        if self.export.is_none() {
+                            self.state.as_mut().unwrap().update();
+                        }
                         self.state.as_mut().unwrap().render().unwrap();// use unwrap() to panic in case
                                                                        // of render fail
+                        // the extra windows show the same panorama, the linked ones the same view
+                        for extra_window in &self.extra_windows {
+                            extra_window.window().request_redraw();
+                        }
                     }
                     WindowEvent::Resized(new_size) => {
                         self.state.as_mut().unwrap().resize(new_size);
                     }
+                    WindowEvent::DroppedFile(path) => {
+                        self.open_path(path);
+                    }
+                    WindowEvent::MouseInput { state: button_state, button, .. } if self.export.is_none() => {
+                        self.mouse_input(button, button_state == ElementState::Pressed);
+                    }
+                    WindowEvent::CursorMoved { .. } if self.export.is_none() => {
+                        self.cursor_moved();
+                    }
                     _ => (),
                 }
             }
         }
     }
 
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+
+        if let Some(export) = self.export.as_mut() {
+            match export.render_frame(state) {
+                Ok(true) => {
+                    state.set_title(&format!("{} - rendering frame {}", self.window_settings.title, export.frame()));
+                    state.window().request_redraw();
+                    event_loop.set_control_flow(ControlFlow::Poll);
+                    return;
+                }
+                Ok(false) => {
+                    let frames = export.frame();
+                    match self.export.take().unwrap().finish() {
+                        Ok(()) => println!("Tour rendered, {frames} frames"),
+                        Err(why) => println!("Tour rendering failed : {why}"),
+                    }
+                }
+                Err(why) => println!("Tour rendering failed : {why}"),
+            }
+            event_loop.exit();
+            return;
+        }
+
+        let mut redraw = false;
+        let mut wake_at: Option<Instant> = None;
+        if let Some(gamepad) = self.gamepad.as_mut() {
+            for event in gamepad.poll() {
+                redraw |= state.gamepad_input(event);
+            }
+            for action in state.take_actions() {
+                self.process_action(event_loop, action);
+            }
+            wake_at = Some(Instant::now() + POLL_INTERVAL);
+        }
+
+        // images decoded in background
+        if self.loader.poll() && self.waiting_image.as_deref() == Some(self.playlist.current()) {
+            self.show_current_image();
+        }
+        if self.loader.is_loading() {
+            wake_at = Some(Instant::now() + POLL_INTERVAL);
+        }
+
+        if let Some(watcher) = self.watcher.as_mut() {
+            let changed = watcher.poll();
+            let next_poll = watcher.next_poll();
+            self.reload_changed(&changed);
+            wake_at = Some(wake_at.map_or(next_poll, |wake_at| wake_at.min(next_poll)));
+        }
+
+        if self.shader_dir.is_some() {
+            if !self.shader_watcher.poll().is_empty() {
+                self.reload_shader();
+            }
+            let next_poll = self.shader_watcher.next_poll();
+            wake_at = Some(wake_at.map_or(next_poll, |wake_at| wake_at.min(next_poll)));
+        }
+
+        self.update_tour();
+        if self.tour_player.is_some() {
+            wake_at = Some(Instant::now() + POLL_INTERVAL);
+        }
+
+        if let Some(duration) = self.slideshow.duration.filter(|_| self.tour_player.is_none()) {
+            if self.waiting_image.is_none() && self.slideshow.shown_at.elapsed() >= duration {
+                self.step_image(true);
+            }
+            let next_image = self.slideshow.shown_at + duration;
+            wake_at = Some(wake_at.map_or(next_image, |wake_at| wake_at.min(next_image)));
+        }
+
+        let elapsed = self.slideshow.last_tick.elapsed().as_secs_f32();
+        self.slideshow.last_tick = Instant::now();
+        let state = self.state.as_mut().unwrap();
+        if self.slideshow.rotation_speed != 0.0 && self.tour_player.is_none() {
+            state.rotate_camera(self.slideshow.rotation_speed * elapsed);
+        }
+
+        // analog sticks, fling inertia and the attract mode move the camera without new events
+        if redraw || state.is_moving() {
+            state.window().request_redraw();
+        }
+        for extra_window in self.extra_windows.iter().filter(|extra_window| extra_window.is_moving()) {
+            extra_window.window().request_redraw();
+        }
+        if let Some(starts_at) = state.attract_starts_at() {
+            // the next update starts it
+            if starts_at <= Instant::now() {
+                state.window().request_redraw();
+            }
+            wake_at = Some(wake_at.map_or(starts_at, |wake_at| wake_at.min(starts_at)));
+        }
+        // the HUD drops the expired messages when it is drawn
+        if let Some(expiry) = state.hud_wake_at() {
+            if expiry <= Instant::now() {
+                state.window().request_redraw();
+            }
+            wake_at = Some(wake_at.map_or(expiry, |wake_at| wake_at.min(expiry)));
+        }
+        // the next update switches the panoramas in flicker mode
+        if let Some(flip_at) = state.comparison_wake_at() {
+            if flip_at <= Instant::now() {
+                state.window().request_redraw();
+            }
+            wake_at = Some(wake_at.map_or(flip_at, |wake_at| wake_at.min(flip_at)));
+        }
+
+        event_loop.set_control_flow(match wake_at {
+            Some(wake_at) => ControlFlow::WaitUntil(wake_at),
+            None => ControlFlow::Wait,
+        });
+    }
+
     fn device_event(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -105,7 +1437,22 @@ impl ApplicationHandler for Application {
         if let DeviceEvent::MouseMotion {
             delta: (mouse_x, mouse_y)
         } = event {
-            println!("Moved cursor ({};{})", mouse_x, mouse_y);
+            // the cursor points at hotspots while they are edited, at measured points and
+            // drags the wipe divider
+            if self.hotspot_editing || self.measuring || self.dragging_divider {
+                return;
+            }
+            // the rendered tour must not depend on the user
+            if self.export.is_some() {
+                return;
+            }
+            let focused = self.extra_windows.iter_mut()
+                .find(|extra_window| Some(extra_window.id()) == self.focused_window && !extra_window.is_linked());
+            if let Some(extra_window) = focused {
+                extra_window.move_camera_by_cursor(mouse_x, mouse_y);
+                extra_window.window().request_redraw();
+                return;
+            }
             self.state.as_mut().unwrap().move_camera_by_cursor(mouse_x, mouse_y);
             self.state.as_mut().unwrap().window().request_redraw();
         }
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sidecar;
+use crate::view::View;
+
+use std::path::{Path, PathBuf};
+
+/// A named view, stored in the sidecar file of the panorama as
+///
+/// ```toml
+/// [[bookmarks]]
+/// slot = 1
+/// name = "Bell tower"
+/// azimuth = 121.5
+/// elevation = 12.0
+/// roll = 0.0
+/// fov = 40.0
+/// projection = "rectilinear"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub slot: u8,
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    pub view: View,
+}
+
+/// The bookmarks of one panorama.
+pub struct Bookmarks {
+    image: PathBuf,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+
+    pub fn load(image: &Path) -> Result<Self, String> {
+        let bookmarks = match sidecar::read(image)?.remove("bookmarks") {
+            Some(value) => value.try_into().map_err(|why: toml::de::Error| format!("bookmarks: {why}"))?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            image: image.to_path_buf(),
+            bookmarks,
+        })
+    }
+
+    /// An empty list, for panoramas whose sidecar file cannot be read.
+    pub fn empty(image: &Path) -> Self {
+        Self {
+            image: image.to_path_buf(),
+            bookmarks: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, slot: u8) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.slot == slot)
+    }
+
+    /// Store `view` in `slot`, keeping the name of the bookmark it replaces, and write the
+    /// sidecar file.
+    pub fn save(&mut self, slot: u8, view: View) -> Result<&Bookmark, String> {
+        let index = match self.bookmarks.iter().position(|bookmark| bookmark.slot == slot) {
+            Some(index) => {
+                self.bookmarks[index].view = view;
+                index
+            }
+            None => {
+                self.bookmarks.push(Bookmark { slot, name: format!("Bookmark {slot}"), view });
+                self.bookmarks.sort_by_key(|bookmark| bookmark.slot);
+                self.bookmarks.iter().position(|bookmark| bookmark.slot == slot).unwrap()
+            }
+        };
+
+        let value = toml::Value::try_from(&self.bookmarks).map_err(|why| why.to_string())?;
+        sidecar::write_section(&self.image, "bookmarks", value)?;
+        Ok(&self.bookmarks[index])
+    }
+}
@@ -1,15 +1,84 @@
-use cgmath::{Matrix4, Vector3, Deg};
+use cgmath::{Matrix4, Quaternion, Rotation, Rotation3, Vector2, Vector3, Deg, InnerSpace};
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalSize;
 
-#[derive(Debug)]
+/// Orientation of the camera, all angles in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PolarCoordinate {
-    angular: f32,//in degrees from 0 to 360
-    radial: f32,//in degrees from -90 to 90
+    pub azimuth: f32,//in degrees from 0 to 360
+    pub elevation: f32,//in degrees from -90 (nadir) to 90 (zenith)
+    pub roll: f32,//in degrees from -180 to 180
+}
+
+impl PolarCoordinate {
+    /// World direction of a point of the panorama, the one the camera looks at with these
+    /// azimuth and elevation.
+    pub fn direction(azimuth: f32, elevation: f32) -> Vector3<f32> {
+        let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+        Vector3::new(-azimuth.sin() * elevation.cos(), elevation.sin(), azimuth.cos() * elevation.cos())
+    }
+
+    /// Azimuth in [0;360[ and elevation of a world direction.
+    pub fn from_direction(direction: Vector3<f32>) -> (f32, f32) {
+        let direction = direction.normalize();
+        let azimuth = (-direction.x).atan2(direction.z).to_degrees().rem_euclid(360.0);
+        (azimuth, direction.y.clamp(-1.0, 1.0).asin().to_degrees())
+    }
+}
+
+/// How rotation deltas are applied to the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrientationMode {
+    /// Azimuth turns around the world vertical axis, elevation is clamped and roll stays at 0.
+    HorizonLevel,
+    /// Deltas are applied in the camera frame, which allows roll and looping over the poles.
+    Free,
+}
+
+/// Animated move of the camera towards a view.
+struct Transition {
+    from: PolarCoordinate,
+    to: PolarCoordinate,
+    from_orientation: Quaternion<f32>,
+    to_orientation: Quaternion<f32>,
+    focal_length: (f32, f32),
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+/// Speed profile of an animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    /// Slow start.
+    EaseIn,
+    /// Slow end.
+    EaseOut,
+    /// Slow start and slow end.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Progress of the animation at time `t`, both in [0;1].
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
 }
 
 pub struct Camera {
-    coordinates: PolarCoordinate,
+    orientation: Quaternion<f32>,
+    mode: OrientationMode,
     radial_max_range: f32,
+    transition: Option<Transition>,
     pub target: Vector3<f32>,
     pub up: Vector3<f32>,
 }
@@ -17,42 +86,189 @@ pub struct Camera {
 impl Camera {
 
     pub fn new(_screen: PhysicalSize<u32>) -> Self {
-        Self {
-            coordinates: PolarCoordinate{angular:0.0,radial:90.0},
+        let mut camera = Self {
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            mode: OrientationMode::HorizonLevel,
             radial_max_range: 90.0, 
+            transition: None,
             target: (0.0, 0.0, 1.0).into(),
             up: cgmath::Vector3::unit_y(),
-        }
+        };
+        camera.reset();
+        camera
     }
 
     pub fn rotate(&mut self, angular_delta: f32, radial_delta: f32) {
+        // the user takes over from an animation
+        if angular_delta != 0.0 || radial_delta != 0.0 {
+            self.transition = None;
+        }
         
-        self.move_coordinates(angular_delta, radial_delta);
-        self.target = (self.rotation_matrix() * cgmath::Vector4::unit_z()).truncate();
-        self.up = (self.rotation_matrix() * cgmath::Vector4::unit_y()).truncate();
-        
-        println!("coordinates : angular = {:.0} radial = {:.0} ", self.coordinates.angular, self.coordinates.radial);
-        println!("target : [{:.2}, {:.2}, {:.2}] up :  [{:.2}, {:.2}, {:.2}]",
-            self.target.x, self.target.y, self.target.z,
-            self.up.x, self.up.y, self.up.z,
-            );
+        match self.mode {
+            OrientationMode::HorizonLevel => {
+                let mut coordinates = self.coordinates();
+                coordinates.azimuth += angular_delta;
+                coordinates.elevation += radial_delta;
+                coordinates.roll = 0.0;
+                self.set_coordinates(coordinates);
+            }
+            OrientationMode::Free => {
+                let delta = Quaternion::from_angle_y(Deg(-angular_delta))
+                    * Quaternion::from_angle_x(Deg(-radial_delta));
+                self.set_orientation(self.orientation * delta);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.transition = None;
+        self.set_coordinates(PolarCoordinate{azimuth:0.0, elevation:0.0, roll:0.0});
+    }
+
+    /// Roll the camera around its view axis, only in free mode.
+    pub fn roll(&mut self, roll_delta: f32) {
+        if self.mode == OrientationMode::Free && roll_delta != 0.0 {
+            self.transition = None;
+            self.set_orientation(self.orientation * Quaternion::from_angle_z(Deg(roll_delta)));
+        }
+    }
+
+    pub fn set_radial_max_range(&mut self, radial_max_range: f32) {
+        self.radial_max_range = radial_max_range;
+        self.set_coordinates(self.coordinates());
+    }
+
+    pub fn mode(&self) -> OrientationMode {
+        self.mode
+    }
+
+    /// Switching back to horizon level mode levels the horizon again.
+    pub fn set_mode(&mut self, mode: OrientationMode) {
+        self.mode = mode;
+        if mode == OrientationMode::HorizonLevel {
+            let mut coordinates = self.coordinates();
+            coordinates.roll = 0.0;
+            self.set_coordinates(coordinates);
+        }
     }
 
-    fn move_coordinates(&mut self, angular_delta: f32, radial_delta: f32) {
-        self.coordinates.angular += angular_delta;
-        self.coordinates.radial += radial_delta;
+    /// Move to `coordinates` in `duration` seconds while the focal length goes from
+    /// `focal_length.0` to `focal_length.1`, see `advance`.
+    pub fn animate_to(&mut self, coordinates: PolarCoordinate, focal_length: (f32, f32), duration: f32, easing: Easing) {
+        let from = self.coordinates();
+        let to = PolarCoordinate {
+            // take the shortest way around
+            azimuth: from.azimuth + ((coordinates.azimuth - from.azimuth) % 360.0 + 540.0) % 360.0 - 180.0,
+            ..coordinates
+        };
+        let from_orientation = self.orientation;
+        let mut to_orientation = Self::coordinates_to_quaternion(to);
+        if from_orientation.dot(to_orientation) < 0.0 {
+            to_orientation = -to_orientation;
+        }
+        self.transition = Some(Transition {
+            from,
+            to,
+            from_orientation,
+            to_orientation,
+            focal_length,
+            elapsed: 0.0,
+            duration: duration.max(f32::EPSILON),
+            easing,
+        });
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Step the animation by `delta_time` seconds, returning the focal length to use.
+    pub fn advance(&mut self, delta_time: f32) -> Option<f32> {
+        let transition = self.transition.as_mut()?;
+        transition.elapsed = (transition.elapsed + delta_time).min(transition.duration);
+        let t = transition.easing.apply(transition.elapsed / transition.duration);
+        let (from, to) = (transition.from, transition.to);
+        let (from_orientation, to_orientation) = (transition.from_orientation, transition.to_orientation);
+        let (from_focal, to_focal) = transition.focal_length;
+        if transition.elapsed >= transition.duration {
+            self.transition = None;
+        }
+
+        match self.mode {
+            // interpolating the angles keeps the horizon level on the way, slerp would roll
+            OrientationMode::HorizonLevel => self.set_coordinates(PolarCoordinate {
+                azimuth: from.azimuth + (to.azimuth - from.azimuth) * t,
+                elevation: from.elevation + (to.elevation - from.elevation) * t,
+                roll: 0.0,
+            }),
+            OrientationMode::Free => self.set_orientation(from_orientation.slerp(to_orientation, t)),
+        }
+        // the focal length changes geometrically so the zoom speed looks constant
+        Some(from_focal * (to_focal / from_focal).powf(t))
+    }
+
+    pub fn set_orientation(&mut self, orientation: Quaternion<f32>) {
+        self.orientation = orientation.normalize();
+        self.target = self.orientation.rotate_vector(Vector3::unit_z());
+        self.up = self.orientation.rotate_vector(Vector3::unit_y());
+    }
+
+    pub fn coordinates(&self) -> PolarCoordinate {
+        Self::quaternion_to_coordinates(self.orientation)
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: PolarCoordinate) {
+        let elevation = match self.mode {
+            OrientationMode::HorizonLevel => coordinates.elevation.clamp(-self.radial_max_range, self.radial_max_range),
+            OrientationMode::Free => coordinates.elevation,
+        };
+        self.set_orientation(Self::coordinates_to_quaternion(PolarCoordinate{elevation, ..coordinates}));
+    }
+
+    pub fn coordinates_to_quaternion(coordinates: PolarCoordinate) -> Quaternion<f32> {
+        Quaternion::from_angle_y(Deg(-coordinates.azimuth))
+            * Quaternion::from_angle_x(Deg(-coordinates.elevation))
+            * Quaternion::from_angle_z(Deg(coordinates.roll))
+    }
+
+    pub fn quaternion_to_coordinates(orientation: Quaternion<f32>) -> PolarCoordinate {
+        let forward = orientation.rotate_vector(Vector3::unit_z());
+        let up = orientation.rotate_vector(Vector3::unit_y());
+
+        // asin loses the precision near the poles
+        let elevation = forward.y.atan2(forward.x.hypot(forward.z)).to_degrees();
+        // At the poles the azimuth is undefined, take it from the up vector instead
+        let azimuth = if forward.x.hypot(forward.z) < 0.00001 {
+            (up.x * forward.y.signum()).atan2(-up.z * forward.y.signum()).to_degrees()
+        } else {
+            (-forward.x).atan2(forward.z).to_degrees()
+        };
+
+        // Roll is the angle between the actual up vector and the up vector without roll
+        let level = Self::coordinates_to_quaternion(PolarCoordinate{azimuth, elevation, roll:0.0});
+        let level_up = level.rotate_vector(Vector3::unit_y());
+        let level_right = level.rotate_vector(Vector3::unit_x());
+        let roll = (-up.dot(level_right)).atan2(up.dot(level_up)).to_degrees();
 
-        // normalize angular coordinates [0;360]
-        self.coordinates.angular = (self.coordinates.angular % 360.0 + 360.0) % 360.0;
-        // normalize radial coordinates [-max_range;max_range]
-        self.coordinates.radial = self.coordinates.radial.clamp(90.0-self.radial_max_range, 90.0+self.radial_max_range);
+        PolarCoordinate {
+            // normalize azimuth [0;360[
+            azimuth: (azimuth % 360.0 + 360.0) % 360.0,
+            elevation,
+            roll,
+        }
     }
 
     pub fn rotation_matrix(&self) -> Matrix4<f32> {
-        let rotation_elevation = Matrix4::<f32>::from_angle_x(Deg(90.0 - self.coordinates.radial));
-        let rotation_azimuth = Matrix4::<f32>::from_angle_y(Deg(-self.coordinates.angular));
+        Matrix4::from(self.orientation)
+    }
+
+    /// World direction of a camera space ray, as in the shader.
+    pub fn to_world(&self, ray: Vector3<f32>) -> Vector3<f32> {
+        self.orientation.rotate_vector(ray)
+    }
 
-        rotation_azimuth * rotation_elevation
+    pub fn to_camera_space(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        self.orientation.invert().rotate_vector(direction)
     }
 }
 
@@ -79,12 +295,59 @@ impl CameraUniform {
     }
 }
 
+/// How view rays are generated from screen coordinates, mirrored in the shader.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Rectilinear,
+    /// "Little planet" when looking at the nadir.
+    Stereographic,
+    /// Equidistant fisheye.
+    Fisheye,
+}
+
+impl Projection {
+    pub fn next(self) -> Self {
+        match self {
+            Projection::Rectilinear => Projection::Stereographic,
+            Projection::Stereographic => Projection::Fisheye,
+            Projection::Fisheye => Projection::Rectilinear,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Projection::Rectilinear => "rectilinear",
+            Projection::Stereographic => "stereographic",
+            Projection::Fisheye => "fisheye",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Projection::Rectilinear, Projection::Stereographic, Projection::Fisheye]
+            .into_iter()
+            .find(|projection| projection.name() == name)
+    }
+
+    fn from_index(index: u32) -> Self {
+        match index {
+            1 => Projection::Stereographic,
+            2 => Projection::Fisheye,
+            _ => Projection::Rectilinear,
+        }
+    }
+}
+
+// Projection of the exports of the whole panorama, see `EQUIRECTANGULAR` in the shader
+const EQUIRECTANGULAR: u32 = 3;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraSettingsBuffer {
     width: f32,
     height: f32,
     focal_length: f32,
+    projection: u32,
 }
 
 impl CameraSettingsBuffer {
@@ -93,6 +356,18 @@ impl CameraSettingsBuffer {
             width: 2.0,
             height: 2.0,
             focal_length: 1.0,
+            projection: Projection::Rectilinear as u32,
+        }
+    }
+
+    /// Settings drawing the whole panorama with its patches and regions, whatever the
+    /// camera looks at.
+    pub fn equirectangular(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            focal_length: 1.0,
+            projection: EQUIRECTANGULAR,
         }
     }
 
@@ -101,7 +376,203 @@ impl CameraSettingsBuffer {
         self.height = height;
     }
 
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
     pub fn zoom(&mut self, multiplier: f32) {
         self.focal_length = (self.focal_length * multiplier).clamp(0.001,1000.0);
     }
+
+    pub fn focal_length(&self) -> f32 {
+        self.focal_length
+    }
+
+    pub fn set_focal_length(&mut self, focal_length: f32) {
+        self.focal_length = focal_length.clamp(0.001,1000.0);
+    }
+
+    pub fn projection(&self) -> Projection {
+        Projection::from_index(self.projection)
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection as u32;
+    }
+
+    /// Approximate angle covered by one pixel at the center of the screen.
+    pub fn degrees_per_pixel(&self) -> f32 {
+        2.0 * (1.0 / self.focal_length).atan().to_degrees() / self.height
+    }
+
+    /// CPU version of `view_ray` in the shader : camera space direction seen at a position
+    /// on the screen in physical pixels, `None` outside of the projection.
+    pub fn view_ray(&self, x: f32, y: f32) -> Option<Vector3<f32>> {
+        let aspect = self.width / self.height;
+        let screen = Vector2::new((x / self.width * 2.0 - 1.0) * aspect, 1.0 - y / self.height * 2.0);
+
+        if self.projection() == Projection::Rectilinear {
+            return Some(screen.extend(self.focal_length).normalize());
+        }
+
+        let planar = screen / self.focal_length;
+        let radius = planar.magnitude();
+        if radius < 0.00001 {
+            return Some(Vector3::unit_z());
+        }
+
+        let theta = match self.projection() {
+            Projection::Stereographic => 2.0 * (radius / 2.0).atan(),
+            _ if radius > std::f32::consts::PI => return None,
+            _ => radius,
+        };
+        Some((planar * theta.sin() / radius).extend(theta.cos()))
+    }
+
+    /// Inverse of `view_ray` : position in physical pixels where a camera space direction
+    /// is seen, `None` if it is out of the projection.
+    pub fn screen_position(&self, ray: Vector3<f32>) -> Option<(f32, f32)> {
+        let ray = ray.normalize();
+        let screen = match self.projection() {
+            Projection::Rectilinear if ray.z <= 0.00001 => return None,
+            Projection::Rectilinear => ray.truncate() / ray.z * self.focal_length,
+            projection => {
+                let theta = ray.z.clamp(-1.0, 1.0).acos();
+                let radius = match projection {
+                    // the antipode is at infinity
+                    Projection::Stereographic if theta > std::f32::consts::PI - 0.01 => return None,
+                    Projection::Stereographic => 2.0 * (theta / 2.0).tan(),
+                    _ => theta,
+                };
+                let planar = ray.truncate();
+                match planar.magnitude() {
+                    length if length < 0.00001 => Vector2::new(0.0, 0.0),
+                    length => planar / length * radius * self.focal_length,
+                }
+            }
+        };
+        let aspect = self.width / self.height;
+        Some(((screen.x / aspect + 1.0) / 2.0 * self.width, (1.0 - screen.y) / 2.0 * self.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn angle_difference(a: f32, b: f32) -> f32 {
+        ((a - b).rem_euclid(360.0) + 180.0).rem_euclid(360.0) - 180.0
+    }
+
+    fn assert_same_orientation(a: Quaternion<f32>, b: Quaternion<f32>, context: &str) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            let difference = (a.rotate_vector(axis) - b.rotate_vector(axis)).magnitude();
+            assert!(difference < EPSILON, "{context} : {axis:?} rotated {difference} apart");
+        }
+    }
+
+    fn sweep() -> Vec<PolarCoordinate> {
+        let mut coordinates = Vec::new();
+        for azimuth in [-450.0, -90.0, -0.5, 0.0, 0.5, 45.0, 179.5, 180.0, 270.0, 359.5, 360.0, 400.0, 720.0] {
+            for elevation in [-90.0, -89.9, -60.0, -1.0, 0.0, 30.0, 89.9, 90.0] {
+                for roll in [-179.0, -45.0, 0.0, 10.0, 90.0, 179.0] {
+                    coordinates.push(PolarCoordinate { azimuth, elevation, roll });
+                }
+            }
+        }
+        coordinates
+    }
+
+    #[test]
+    fn coordinates_round_trip() {
+        for coordinates in sweep() {
+            let context = format!("{coordinates:?}");
+            let orientation = Camera::coordinates_to_quaternion(coordinates);
+            let back = Camera::quaternion_to_coordinates(orientation);
+
+            assert!((0.0..360.0).contains(&back.azimuth), "{context} : azimuth {}", back.azimuth);
+            assert!((back.elevation - coordinates.elevation).abs() < 0.01, "{context} : {back:?}");
+            assert_same_orientation(Camera::coordinates_to_quaternion(back), orientation, &context);
+            // the azimuth and the roll turn around the same axis at the poles
+            if coordinates.elevation.abs() < 90.0 {
+                assert!(angle_difference(back.azimuth, coordinates.azimuth).abs() < 0.01, "{context} : {back:?}");
+                assert!(angle_difference(back.roll, coordinates.roll).abs() < 0.01, "{context} : {back:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn azimuth_wraps_around() {
+        let coordinates = |azimuth| Camera::quaternion_to_coordinates(
+            Camera::coordinates_to_quaternion(PolarCoordinate { azimuth, elevation: 20.0, roll: 0.0 })
+        );
+        assert!(angle_difference(coordinates(360.0).azimuth, 0.0).abs() < 0.01);
+        assert!((coordinates(-90.0).azimuth - 270.0).abs() < 0.01);
+        assert!((coordinates(450.0).azimuth - 90.0).abs() < 0.01);
+
+        let mut camera = Camera::new(PhysicalSize::new(0, 0));
+        camera.set_coordinates(PolarCoordinate { azimuth: 350.0, elevation: 0.0, roll: 0.0 });
+        camera.rotate(20.0, 0.0);
+        assert!((camera.coordinates().azimuth - 10.0).abs() < 0.01);
+        camera.rotate(-30.0, 0.0);
+        assert!((camera.coordinates().azimuth - 340.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn horizon_level_keeps_the_roll_at_zero() {
+        let mut camera = Camera::new(PhysicalSize::new(0, 0));
+        camera.set_coordinates(PolarCoordinate { azimuth: 30.0, elevation: 120.0, roll: 0.0 });
+        assert!((camera.coordinates().elevation - 90.0).abs() < 0.01);
+        camera.roll(15.0);
+        assert!(camera.coordinates().roll.abs() < 0.01);
+
+        camera.set_mode(OrientationMode::Free);
+        camera.set_coordinates(PolarCoordinate { azimuth: 30.0, elevation: 10.0, roll: 0.0 });
+        camera.roll(15.0);
+        assert!((camera.coordinates().roll - 15.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn center_of_the_screen_looks_at_the_coordinates() {
+        for coordinates in sweep() {
+            let mut camera = Camera::new(PhysicalSize::new(0, 0));
+            camera.set_mode(OrientationMode::Free);
+            camera.set_coordinates(coordinates);
+            let expected = PolarCoordinate::direction(coordinates.azimuth, coordinates.elevation);
+            assert!((camera.to_world(Vector3::unit_z()) - expected).magnitude() < EPSILON, "{coordinates:?}");
+        }
+    }
+
+    #[test]
+    fn view_proj_agrees_with_the_cpu_projection() {
+        let mut uniform = CameraUniform::new();
+        let mut settings = CameraSettingsBuffer::new();
+        settings.resize(1280.0, 720.0);
+        settings.set_focal_length(1.3);
+        let pixels = [(640.0, 360.0), (0.0, 0.0), (1279.0, 719.0), (200.0, 600.0), (1000.0, 100.0)];
+
+        for projection in [Projection::Rectilinear, Projection::Stereographic, Projection::Fisheye] {
+            settings.set_projection(projection);
+            for coordinates in sweep() {
+                let mut camera = Camera::new(PhysicalSize::new(0, 0));
+                camera.set_mode(OrientationMode::Free);
+                camera.set_coordinates(coordinates);
+                uniform.update_view_proj(&camera);
+                let view_proj = Matrix4::from(uniform.view_proj);
+
+                for (x, y) in pixels {
+                    let context = format!("{projection:?} {coordinates:?} at {x},{y}");
+                    let ray = settings.view_ray(x, y).unwrap();
+                    // as the shader turns a view ray into a direction of the panorama
+                    let world = (view_proj * ray.extend(0.0)).truncate().normalize();
+                    assert!((world - camera.to_world(ray)).magnitude() < EPSILON, "{context}");
+
+                    let (screen_x, screen_y) = settings.screen_position(camera.to_camera_space(world)).unwrap();
+                    assert!((screen_x - x).abs() < 0.1 && (screen_y - y).abs() < 0.1, "{context} : {screen_x},{screen_y}");
+                }
+            }
+        }
+    }
 }
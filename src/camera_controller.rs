@@ -3,88 +3,331 @@ use winit::event::{
     KeyEvent,
 };
 
+use winit::event::Touch;
+use winit::dpi::PhysicalPosition;
+
 use winit::keyboard::{
-    KeyCode,
+    ModifiersState,
     PhysicalKey,
 };
 
-use crate::camera::{Camera, CameraSettingsBuffer};
+use crate::camera::{Camera, CameraSettingsBuffer, OrientationMode};
+use crate::config::{AttractConfig, CameraConfig, GamepadConfig};
+use crate::gestures::{Gesture, GestureRecognizer, TouchInput, TouchPhase};
+use crate::gamepad::{AnalogResponse, GamepadAxis, GamepadEvent};
+use crate::key_bindings::{Action, KeyBindings};
+
+use cgmath::Vector3;
+use std::time::{Duration, Instant};
+
+/// Slow rotation of the panorama while nobody uses the viewer.
+struct AttractMode {
+    config: AttractConfig,
+    last_input: Instant,
+    // seconds since the rotation started, None while the user is active
+    running: Option<f32>,
+    start_elevation: f32,
+}
+
+impl AttractMode {
+
+    fn starts_at(&self) -> Option<Instant> {
+        (self.config.idle_time > 0.0 && self.running.is_none())
+            .then(|| self.last_input + Duration::from_secs_f32(self.config.idle_time))
+    }
+
+    fn stop(&mut self) {
+        self.last_input = Instant::now();
+        self.running = None;
+    }
+
+    /// Turn the camera for `delta_time` seconds if the viewer has been idle long enough.
+    fn update(&mut self, camera: &mut Camera, delta_time: f32) {
+        // rotating would cancel a bookmark or tour transition
+        if self.starts_at().is_some_and(|starts_at| Instant::now() >= starts_at) && !camera.is_animating() {
+            self.running = Some(0.0);
+            self.start_elevation = camera.coordinates().elevation;
+            // nothing is redrawn while idle, this frame's delta spans the whole idle time
+            return;
+        }
+        let Some(time) = self.running.as_mut() else {
+            return;
+        };
+        *time += delta_time;
+
+        let config = &self.config;
+        let drift = match config.horizon_time > 0.0 {
+            true => (-*time / config.horizon_time).exp(),
+            false => 1.0,
+        };
+        let swing = config.elevation_amplitude * (std::f32::consts::TAU * *time / config.elevation_period).sin();
+        let elevation = self.start_elevation * drift + swing;
+        camera.rotate(config.azimuth_speed * delta_time, elevation - camera.coordinates().elevation);
+    }
+}
 
 pub struct CameraController {
     speed: f32,
     mouse_sensibility: f32,
+    default_focal_length: f32,
+    key_bindings: KeyBindings,
+    modifiers: ModifiersState,
     is_up_pressed: bool,
     is_down_pressed: bool,
     is_left_pressed: bool,
     is_right_pressed: bool,
     increase_fov: bool,
     decrease_fov: bool,
+    is_roll_left_pressed: bool,
+    is_roll_right_pressed: bool,
+    toggle_orientation_mode: bool,
+    toggle_projection: bool,
+    reset_view: bool,
+    pending_actions: Vec<Action>,
+    analog_response: AnalogResponse,
+    left_stick: (f32, f32),
+    right_stick: (f32, f32),
+    left_trigger: f32,
+    right_trigger: f32,
+    gestures: GestureRecognizer,
+    pending_gestures: Vec<Gesture>,
+    cursor_position: PhysicalPosition<f64>,
+    clock: Instant,
+    attract: AttractMode,
+    last_update: Instant,
 }
 
 impl CameraController {
-    pub fn new(speed: f32, mouse_sensibility: f32) -> Self {
+    pub fn new(speed: f32, mouse_sensibility: f32, key_bindings: KeyBindings) -> Self {
         Self {
             speed,
             mouse_sensibility,
+            default_focal_length: 1.0,
+            key_bindings,
+            modifiers: ModifiersState::empty(),
             is_up_pressed: false,
             is_down_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
             increase_fov: false,
             decrease_fov: false,
+            is_roll_left_pressed: false,
+            is_roll_right_pressed: false,
+            toggle_orientation_mode: false,
+            toggle_projection: false,
+            reset_view: false,
+            pending_actions: Vec::new(),
+            analog_response: AnalogResponse { dead_zone: 0.15, exponent: 2.0 },
+            left_stick: (0.0, 0.0),
+            right_stick: (0.0, 0.0),
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+            gestures: GestureRecognizer::new(),
+            pending_gestures: Vec::new(),
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            clock: Instant::now(),
+            attract: AttractMode {
+                config: AttractConfig::default(),
+                last_input: Instant::now(),
+                running: None,
+                start_elevation: 0.0,
+            },
+            last_update: Instant::now(),
         }
     }
 
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        if matches!(event,
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::PinchGesture { .. }
+            | WindowEvent::KeyboardInput { .. }
+        ) {
+            self.attract.stop();
+        }
+
         match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                false
+            }
+            WindowEvent::Touch(Touch { phase, location, id, .. }) => {
+                let phase = match phase {
+                    winit::event::TouchPhase::Started => TouchPhase::Started,
+                    winit::event::TouchPhase::Moved => TouchPhase::Moved,
+                    winit::event::TouchPhase::Ended => TouchPhase::Ended,
+                    winit::event::TouchPhase::Cancelled => TouchPhase::Cancelled,
+                };
+                let gestures = self.gestures.touch(TouchInput {
+                    id: *id,
+                    phase,
+                    position: (location.x, location.y),
+                    time: self.clock.elapsed().as_secs_f64(),
+                });
+                self.pending_gestures.extend(gestures);
+                true
+            }
+            // trackpad pinch, positive when zooming in
+            WindowEvent::PinchGesture { delta, .. } => {
+                self.pending_gestures.push(Gesture::Zoom {
+                    factor: 1.0 + delta,
+                    center: (self.cursor_position.x, self.cursor_position.y),
+                });
+                true
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
                         state,
                         physical_key: PhysicalKey::Code(keycode),
+                        repeat,
                         ..
                     },
                 ..
             } => {
-                let is_pressed = state.is_pressed();
-                match keycode {KeyCode::KeyW | KeyCode::ArrowUp => {
-                        println!("Press key Up : {}", is_pressed);
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA | KeyCode::ArrowLeft => {
-                        println!("Press key Left : {}", is_pressed);
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS | KeyCode::ArrowDown => {
-                        println!("Press key Down");
-                        self.is_down_pressed = is_pressed;
-                        true
+                if state.is_pressed() {
+                    match self.key_bindings.action(*keycode, self.modifiers) {
+                        Some(action) => {
+                            if !*repeat {
+                                self.process_action(action, true);
+                            }
+                            true
+                        }
+                        None => false,
                     }
-                    KeyCode::KeyD | KeyCode::ArrowRight => {
-                        println!("Press key Right");
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyJ => {
-                        println!("Increase Fov");
-                        self.increase_fov = is_pressed;
-                        true
+                }
+                else {
+                    // release held actions whatever the modifiers are now
+                    let actions = self.key_bindings.actions_for_key(*keycode);
+                    for action in &actions {
+                        self.process_action(*action, false);
                     }
-                    KeyCode::KeyK => {
-                        println!("Decrease Fov");
-                        self.decrease_fov = is_pressed;
+                    !actions.is_empty()
+                }
+            }
+            _ => false,
+        }
+    }
+
+    pub fn process_gamepad_event(&mut self, event: GamepadEvent) -> bool {
+        // sticks at rest may still report noise
+        let is_input = match event {
+            GamepadEvent::Axis(_, value) => self.analog_response.apply(value) != 0.0,
+            GamepadEvent::Button(..) => true,
+        };
+        if is_input {
+            self.attract.stop();
+        }
+
+        match event {
+            GamepadEvent::Axis(axis, value) => {
+                match axis {
+                    GamepadAxis::LeftStickX => self.left_stick.0 = value,
+                    GamepadAxis::LeftStickY => self.left_stick.1 = value,
+                    GamepadAxis::RightStickX => self.right_stick.0 = value,
+                    GamepadAxis::RightStickY => self.right_stick.1 = value,
+                    GamepadAxis::LeftTrigger => self.left_trigger = value,
+                    GamepadAxis::RightTrigger => self.right_trigger = value,
+                }
+                true
+            }
+            GamepadEvent::Button(button, is_pressed) => {
+                match self.key_bindings.gamepad_action(button) {
+                    Some(action) => {
+                        self.process_action(action, is_pressed);
                         true
                     }
-                    _ => false,
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// True while an analog input is held out of its dead zone, the view must then be
+    /// redrawn even without new events.
+    pub fn is_moving(&self) -> bool {
+        self.is_analog_active() || self.attract.running.is_some()
+    }
+
+    fn is_analog_active(&self) -> bool {
+        let (pan_x, pan_y) = self.analog_response.apply_stick(self.left_stick.0, self.left_stick.1);
+        let (roll, _) = self.analog_response.apply_stick(self.right_stick.0, self.right_stick.1);
+        [pan_x, pan_y, roll, self.analog_response.apply(self.left_trigger), self.analog_response.apply(self.right_trigger)]
+            .iter()
+            .any(|value| *value != 0.0)
+            || self.gestures.is_flinging()
+    }
+
+    pub fn process_action(&mut self, action: Action, is_pressed: bool) {
+        match action {
+            Action::PanUp => self.is_up_pressed = is_pressed,
+            Action::PanDown => self.is_down_pressed = is_pressed,
+            Action::PanLeft => self.is_left_pressed = is_pressed,
+            Action::PanRight => self.is_right_pressed = is_pressed,
+            Action::ZoomIn => self.increase_fov = is_pressed,
+            Action::ZoomOut => self.decrease_fov = is_pressed,
+            Action::RollLeft => self.is_roll_left_pressed = is_pressed,
+            Action::RollRight => self.is_roll_right_pressed = is_pressed,
+            Action::ToggleHorizonLock => self.toggle_orientation_mode |= is_pressed,
+            Action::ToggleProjection => self.toggle_projection |= is_pressed,
+            Action::ResetView => self.reset_view |= is_pressed,
+            // handled by the application
+            _ => {
+                if is_pressed {
+                    self.pending_actions.push(action);
                 }
             }
-            _ => false,
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera, settings: &mut CameraSettingsBuffer) {
+    pub fn apply_config(&mut self, config: &CameraConfig, gamepad: &GamepadConfig, attract: &AttractConfig) {
+        self.attract.config = attract.clone();
+        self.speed = config.speed;
+        self.mouse_sensibility = config.mouse_sensibility;
+        self.default_focal_length = config.focal_length;
+        self.analog_response = AnalogResponse {
+            dead_zone: gamepad.dead_zone,
+            exponent: gamepad.exponent,
+        };
+    }
+
+    /// When the attract mode will start if nothing happens, to wake up the event loop.
+    pub fn attract_starts_at(&self) -> Option<Instant> {
+        self.attract.starts_at()
+    }
+
+    /// Postpone the attract mode, as if the user had done something.
+    pub fn reset_idle(&mut self) {
+        self.attract.stop();
+    }
+
+    pub fn reset_camera(&self, camera: &mut Camera, settings: &mut CameraSettingsBuffer) {
+        camera.reset();
+        settings.set_focal_length(self.default_focal_length);
+    }
+
+    /// Actions which concern the application rather than the camera.
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.pending_actions)
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, settings: &mut CameraSettingsBuffer) {
+        let now = Instant::now();
+        let delta_time = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+        // held keys and sticks keep the viewer awake
+        if self.is_analog_active() || self.is_up_pressed || self.is_down_pressed || self.is_left_pressed || self.is_right_pressed
+            || self.increase_fov || self.decrease_fov || self.is_roll_left_pressed || self.is_roll_right_pressed {
+            self.attract.stop();
+        }
+        self.attract.update(camera, delta_time);
+
         let (mut angular_delta, mut radial_delta) = (0.0, 0.0);
 
         if self.is_right_pressed {
@@ -100,19 +343,90 @@ impl CameraController {
             radial_delta -= 1.0;
         }
 
+        let (stick_x, stick_y) = self.analog_response.apply_stick(self.left_stick.0, self.left_stick.1);
+        angular_delta -= stick_x;
+        radial_delta += stick_y;
+
         angular_delta *= self.speed;
         radial_delta *= self.speed;
 
+        if self.reset_view {
+            self.reset_view = false;
+            self.reset_camera(camera, settings);
+        }
+
+        if self.toggle_orientation_mode {
+            self.toggle_orientation_mode = false;
+            camera.set_mode(match camera.mode() {
+                OrientationMode::HorizonLevel => OrientationMode::Free,
+                OrientationMode::Free => OrientationMode::HorizonLevel,
+            });
+        }
+
+        if self.toggle_projection {
+            self.toggle_projection = false;
+            settings.set_projection(settings.projection().next());
+        }
+
         camera.rotate(angular_delta, radial_delta);
 
+        let mut roll_delta = 0.0;
+        if self.is_roll_left_pressed {
+            roll_delta -= 1.0;
+        }
+        if self.is_roll_right_pressed {
+            roll_delta += 1.0;
+        }
+        roll_delta += self.analog_response.apply_stick(self.right_stick.0, self.right_stick.1).0;
+        camera.roll(roll_delta * self.speed);
+
+        if let Some(gesture) = self.gestures.update(self.clock.elapsed().as_secs_f64()) {
+            self.pending_gestures.push(gesture);
+        }
+        for gesture in std::mem::take(&mut self.pending_gestures) {
+            match gesture {
+                Gesture::Pan { delta: (delta_x, delta_y) } => {
+                    // the panorama follows the finger
+                    let degrees_per_pixel = settings.degrees_per_pixel();
+                    camera.rotate(delta_x as f32 * degrees_per_pixel, delta_y as f32 * degrees_per_pixel);
+                }
+                Gesture::Zoom { factor, center } => {
+                    Self::zoom_around(camera, settings, factor as f32, center);
+                }
+                Gesture::DoubleTap { position } => {
+                    Self::zoom_around(camera, settings, 2.0, position);
+                }
+            }
+        }
+
         let zoom_factor = 
             if self.increase_fov {1.1}
             else if self.decrease_fov {0.9}
             else {1.0};
-        settings.zoom(zoom_factor);
+        let trigger_zoom = self.analog_response.apply(self.right_trigger) - self.analog_response.apply(self.left_trigger);
+        settings.zoom(zoom_factor * (1.0 + 0.1 * trigger_zoom));
     }
     
-    pub fn move_cursor(&self, camera: &mut Camera, delta_x: f64, delta_y: f64) {
+    /// Zoom while keeping the direction seen at `center` in place on the screen.
+    fn zoom_around(camera: &mut Camera, settings: &mut CameraSettingsBuffer, factor: f32, center: (f64, f64)) {
+        let (x, y) = (center.0 as f32, center.1 as f32);
+        let before = settings.view_ray(x, y);
+        settings.zoom(factor);
+        let after = settings.view_ray(x, y);
+
+        if let (Some(before), Some(after)) = (before, after) {
+            let yaw = |ray: Vector3<f32>| ray.x.atan2(ray.z).to_degrees();
+            let pitch = |ray: Vector3<f32>| ray.y.clamp(-1.0, 1.0).asin().to_degrees();
+            camera.rotate(yaw(after) - yaw(before), pitch(before) - pitch(after));
+        }
+    }
+
+    pub fn cursor_position(&self) -> (f32, f32) {
+        (self.cursor_position.x as f32, self.cursor_position.y as f32)
+    }
+
+    pub fn move_cursor(&mut self, camera: &mut Camera, delta_x: f64, delta_y: f64) {
+        self.attract.stop();
         camera.rotate((-delta_x as f32) * self.mouse_sensibility, (-delta_y as f32) * self.mouse_sensibility);
     }
 }
@@ -120,3 +434,57 @@ impl CameraController {
  
 
  
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Easing, PolarCoordinate};
+    use winit::dpi::PhysicalSize;
+
+    fn idle_attract_mode() -> AttractMode {
+        AttractMode {
+            config: AttractConfig { idle_time: 60.0, ..AttractConfig::default() },
+            last_input: Instant::now() - Duration::from_secs(61),
+            running: None,
+            start_elevation: 0.0,
+        }
+    }
+
+    fn camera_at(elevation: f32) -> Camera {
+        let mut camera = Camera::new(PhysicalSize::new(0, 0));
+        camera.set_coordinates(PolarCoordinate { azimuth: 10.0, elevation, roll: 0.0 });
+        camera
+    }
+
+    #[test]
+    fn first_attract_frame_does_not_jump() {
+        let mut attract = idle_attract_mode();
+        let mut camera = camera_at(30.0);
+
+        // the first update comes after the whole idle period
+        attract.update(&mut camera, 61.0);
+        assert!(attract.running.is_some());
+        assert!((camera.coordinates().azimuth - 10.0).abs() < 0.01, "{:?}", camera.coordinates());
+        assert!((camera.coordinates().elevation - 30.0).abs() < 0.01, "{:?}", camera.coordinates());
+
+        attract.update(&mut camera, 0.1);
+        let coordinates = camera.coordinates();
+        assert!((coordinates.azimuth - 10.5).abs() < 0.01, "{coordinates:?}");
+        assert!(coordinates.elevation < 30.0 && coordinates.elevation > 29.0, "{coordinates:?}");
+    }
+
+    #[test]
+    fn attract_mode_waits_for_the_transition() {
+        let mut attract = idle_attract_mode();
+        let mut camera = camera_at(0.0);
+        camera.animate_to(PolarCoordinate { azimuth: 90.0, elevation: 0.0, roll: 0.0 }, (1.0, 1.0), 2.0, Easing::Linear);
+
+        attract.update(&mut camera, 0.1);
+        assert!(attract.running.is_none());
+        assert!(camera.is_animating());
+
+        camera.advance(2.0);
+        attract.update(&mut camera, 0.1);
+        assert!(attract.running.is_some());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::view::View;
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage : rust360 [OPTIONS] [IMAGE | DIRECTORY | GLOB | PLAYLIST]...
+
+Options :
+  --slideshow SECONDS   go to the next panorama every SECONDS (viewer.slideshow)
+  --rotate SPEED        turn the camera at SPEED degrees per second (viewer.rotation_speed)
+  --watch               reload the panorama when it changes on disk (viewer.watch)
+  --set KEY=VALUE       override a configuration key, such as camera.speed=3
+  --print-config        print the configuration of the first panorama and exit
+  --view VIEW           start from VIEW, \"azimuth,elevation,roll,fov,projection\"
+  --tour FILE           play the camera tour described in FILE
+  --project FILE        walk through the linked panoramas of the virtual tour FILE
+  --compare FILE        show FILE next to the panorama, with the same view (comparison.image)
+  --render-tour DIR     render the tour to a PNG sequence in DIR and exit, see [export]
+  --encoder COMMAND     also pipe the rendered frames to COMMAND (export.encoder)
+  --no-session          neither restore nor save the images and view of the last session
+  --shader-dir DIR      load the shaders from DIR and rebuild them when they change
+  -h, --help            print this help
+
+Environment :
+  COSMOS360_GAMEPAD_SCRIPT   replay the gamepad events of this TOML file instead of a controller";
+
+/// Command line of the viewer.
+#[derive(Debug, Default)]
+pub struct Arguments {
+    pub paths: Vec<String>,
+    /// Configuration overrides, "section.key=value".
+    pub overrides: Vec<String>,
+    pub print_config: bool,
+    pub view: Option<View>,
+    pub tour: Option<PathBuf>,
+    pub render_tour: Option<PathBuf>,
+    pub project: Option<PathBuf>,
+    pub no_session: bool,
+    pub shader_dir: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Arguments {
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut arguments = Self::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--slideshow" => arguments.overrides.push(format!("viewer.slideshow={}", Self::number(&arg, args.next())?)),
+                "--rotate" => arguments.overrides.push(format!("viewer.rotation_speed={}", Self::number(&arg, args.next())?)),
+                "--watch" => arguments.overrides.push("viewer.watch=true".to_string()),
+                "--set" => arguments.overrides.push(args.next().ok_or(format!("Option \"{arg}\" expects KEY=VALUE"))?),
+                "--print-config" => arguments.print_config = true,
+                "--view" => arguments.view = Some(args.next()
+                    .ok_or(format!("Option \"{arg}\" expects a view"))?
+                    .parse()?),
+                "--tour" => arguments.tour = Some(args.next().map(PathBuf::from)
+                    .ok_or(format!("Option \"{arg}\" expects a file"))?),
+                "--render-tour" => arguments.render_tour = Some(args.next().map(PathBuf::from)
+                    .ok_or(format!("Option \"{arg}\" expects a directory"))?),
+                "--project" => arguments.project = Some(args.next().map(PathBuf::from)
+                    .ok_or(format!("Option \"{arg}\" expects a file"))?),
+                "--compare" => arguments.overrides.push(format!("comparison.image={}", toml::Value::String(args.next()
+                    .ok_or(format!("Option \"{arg}\" expects a file"))?))),
+                "--encoder" => arguments.overrides.push(format!("export.encoder={}", toml::Value::String(args.next()
+                    .ok_or(format!("Option \"{arg}\" expects a command"))?))),
+                "--no-session" => arguments.no_session = true,
+                "--shader-dir" => arguments.shader_dir = Some(args.next().map(PathBuf::from)
+                    .ok_or(format!("Option \"{arg}\" expects a directory"))?),
+                "-h" | "--help" => arguments.help = true,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option \"{arg}\", see --help")),
+                _ => arguments.paths.push(arg),
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    fn number(option: &str, value: Option<String>) -> Result<f32, String> {
+        value.as_deref()
+            .and_then(|value| value.parse().ok())
+            .ok_or(format!("Option \"{option}\" expects a number"))
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::{ComparisonConfig, ComparisonMode};
+
+use std::time::{Duration, Instant};
+
+// Pixels on each side of the wipe divider where it can be grabbed
+const DIVIDER_GRAB: f32 = 8.0;
+
+/// The comparison as the shader reads it, see `Comparison` in the shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ComparisonUniform {
+    /// 0 without comparison, else the `ComparisonMode` plus 1.
+    mode: u32,
+    divider: f32,
+    /// The second panorama is shown in flicker mode.
+    second: u32,
+    difference_gain: f32,
+}
+
+/// A second panorama seen through the camera of the first one.
+pub struct Comparison {
+    mode: ComparisonMode,
+    loaded: bool,
+    shown: bool,
+    /// Position of the wipe divider, from 0 at the left of the window to 1 at its right.
+    divider: f32,
+    second: bool,
+    flicker_interval: Option<Duration>,
+    flipped_at: Instant,
+    difference_gain: f32,
+}
+
+impl Comparison {
+
+    pub fn new(config: &ComparisonConfig) -> Self {
+        let mut comparison = Self {
+            mode: config.mode,
+            loaded: false,
+            shown: true,
+            divider: 0.5,
+            second: false,
+            flicker_interval: None,
+            flipped_at: Instant::now(),
+            difference_gain: 1.0,
+        };
+        comparison.apply_config(config);
+        comparison
+    }
+
+    pub fn apply_config(&mut self, config: &ComparisonConfig) {
+        self.mode = config.mode;
+        self.flicker_interval = (config.flicker_interval > 0.0).then(|| Duration::from_secs_f32(config.flicker_interval));
+        self.difference_gain = config.difference_gain;
+    }
+
+    /// Whether a second panorama is loaded, the comparison is drawn only then.
+    pub fn set_loaded(&mut self, loaded: bool) {
+        self.loaded = loaded;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.loaded && self.shown
+    }
+
+    pub fn mode(&self) -> ComparisonMode {
+        self.mode
+    }
+
+    pub fn cycle_mode(&mut self) -> ComparisonMode {
+        self.mode = self.mode.next();
+        self.flipped_at = Instant::now();
+        self.mode
+    }
+
+    /// Show or hide the second panorama, and return whether it is shown.
+    pub fn toggle(&mut self) -> bool {
+        self.shown = !self.shown;
+        self.shown
+    }
+
+    /// Show the other panorama in flicker mode.
+    pub fn flip(&mut self) {
+        self.second = !self.second;
+        self.flipped_at = Instant::now();
+    }
+
+    /// Each panorama is drawn in a half of the window.
+    pub fn is_side_by_side(&self) -> bool {
+        self.is_active() && self.mode == ComparisonMode::SideBySide
+    }
+
+    /// Whether the wipe divider is under the cursor, `x` and `width` in pixels.
+    pub fn is_near_divider(&self, x: f32, width: f32) -> bool {
+        self.is_active() && self.mode == ComparisonMode::Wipe && (x - self.divider * width).abs() <= DIVIDER_GRAB
+    }
+
+    pub fn move_divider(&mut self, x: f32, width: f32) {
+        self.divider = (x / width).clamp(0.0, 1.0);
+    }
+
+    /// Switch the panoramas once the flicker interval is over.
+    pub fn advance(&mut self, now: Instant) {
+        if self.next_flip().is_some_and(|flip_at| flip_at <= now) {
+            self.second = !self.second;
+            self.flipped_at = now;
+        }
+    }
+
+    /// When the panoramas are next switched in flicker mode.
+    pub fn next_flip(&self) -> Option<Instant> {
+        if !self.is_active() || self.mode != ComparisonMode::Flicker {
+            return None;
+        }
+        self.flicker_interval.map(|interval| self.flipped_at + interval)
+    }
+
+    pub fn uniform(&self) -> ComparisonUniform {
+        ComparisonUniform {
+            mode: if self.is_active() { self.mode as u32 + 1 } else { 0 },
+            divider: self.divider,
+            second: self.second as u32,
+            difference_gain: self.difference_gain,
+        }
+    }
+}
+
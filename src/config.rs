@@ -0,0 +1,691 @@
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{OrientationMode, Projection};
+use crate::sidecar;
+use crate::window_settings::WindowSettings;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_CONFIG_HOME/cosmos360`, or `~/.config/cosmos360`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("cosmos360"))
+}
+
+/// Behaviour of the viewer, the `[viewer]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewerConfig {
+    /// Go back to the default view when another panorama is opened.
+    pub reset_view_on_open: bool,
+    /// Seconds before going to the next panorama, 0 disables the slideshow.
+    pub slideshow: f32,
+    /// Automatic rotation of the camera in degrees per second, 0 disables it.
+    pub rotation_speed: f32,
+    /// Reload the panorama and its sidecar file when they change on disk.
+    pub watch: bool,
+    /// Seconds taken to move to a bookmark, 0 jumps to it.
+    pub transition_duration: f32,
+    /// Azimuth of the north in the panorama, for the compass. Usually set in the sidecar
+    /// file of each panorama.
+    pub north: f32,
+}
+
+impl Default for ViewerConfig {
+    fn default() -> Self {
+        Self {
+            reset_view_on_open: false,
+            slideshow: 0.0,
+            rotation_speed: 0.0,
+            watch: false,
+            transition_duration: 1.0,
+            north: 0.0,
+        }
+    }
+}
+
+/// Navigation, the `[camera]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Degrees turned per frame while a pan key is held.
+    pub speed: f32,
+    /// Degrees turned per pixel of mouse motion.
+    pub mouse_sensibility: f32,
+    /// Highest elevation reachable in horizon level mode, in degrees from 0 to 90.
+    pub radial_max_range: f32,
+    /// Initial focal length, the vertical field of view is 2 * atan(1 / focal_length).
+    pub focal_length: f32,
+    /// "rectilinear", "stereographic" or "fisheye".
+    pub projection: Projection,
+    /// "horizon_level" or "free".
+    pub orientation_mode: OrientationMode,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            speed: 2.0,
+            mouse_sensibility: 0.05,
+            radial_max_range: 90.0,
+            focal_length: 1.0,
+            projection: Projection::Rectilinear,
+            orientation_mode: OrientationMode::HorizonLevel,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    Default,
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::Default => wgpu::PowerPreference::default(),
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// Rendering, the `[render]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    /// Red, green, blue and alpha in [0;1] around the panorama.
+    pub clear_color: [f64; 4],
+    /// "auto_vsync", "auto_no_vsync", "fifo", "mailbox" or "immediate", read at startup.
+    pub present_mode: PresentMode,
+    /// "default", "low_power" or "high_performance", read at startup.
+    pub power_preference: PowerPreference,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            clear_color: [0.1, 0.2, 0.3, 1.0],
+            present_mode: PresentMode::AutoNoVsync,
+            power_preference: PowerPreference::Default,
+        }
+    }
+}
+
+/// Analog inputs, the `[gamepad]` section. Buttons are bound in `bindings.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadConfig {
+    /// Part of the stick and trigger travel ignored around the rest position, in [0;1[.
+    pub dead_zone: f32,
+    /// Response curve of the analog inputs, 1 is linear.
+    pub exponent: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.15,
+            exponent: 2.0,
+        }
+    }
+}
+
+/// Automatic rotation of idle kiosks, the `[attract]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttractConfig {
+    /// Seconds without input before the attract mode starts, 0 disables it.
+    pub idle_time: f32,
+    /// Rotation in degrees per second, negative to turn the other way.
+    pub azimuth_speed: f32,
+    /// Degrees of the elevation swing around the horizon, 0 disables it.
+    pub elevation_amplitude: f32,
+    /// Seconds of one elevation swing.
+    pub elevation_period: f32,
+    /// Seconds for the view to get about two thirds of the way back to the horizon,
+    /// 0 keeps the elevation.
+    pub horizon_time: f32,
+}
+
+impl Default for AttractConfig {
+    fn default() -> Self {
+        Self {
+            idle_time: 0.0,
+            azimuth_speed: 5.0,
+            elevation_amplitude: 0.0,
+            elevation_period: 20.0,
+            horizon_time: 5.0,
+        }
+    }
+}
+
+/// Offline rendering of tours with `--render-tour`, the `[export]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Frames per second of the rendered sequence.
+    pub fps: f32,
+    /// Size of the frames in pixels, independent of the window.
+    pub width: u32,
+    pub height: u32,
+    /// Images averaged into each frame for motion blur, 1 disables it.
+    pub motion_blur_samples: u32,
+    /// Part of the frame interval covered by the motion blur samples, in ]0;1].
+    pub shutter: f32,
+    /// Command receiving the frames as PNG images on its standard input, such as
+    /// "ffmpeg -f image2pipe -framerate 30 -i - tour.mp4".
+    pub encoder: Option<String>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            fps: 30.0,
+            width: 1920,
+            height: 1080,
+            motion_blur_samples: 1,
+            shutter: 0.5,
+            encoder: None,
+        }
+    }
+}
+
+/// Text overlay over the panorama, the `[hud]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HudConfig {
+    /// Whether the view information is shown at startup, messages are always shown.
+    pub visible: bool,
+    /// Size of the font pixels in screen pixels.
+    pub scale: u32,
+    /// Seconds a message stays on screen.
+    pub message_duration: f32,
+    /// Opacity of the panels behind the text, in [0;1].
+    pub background_opacity: f32,
+}
+
+impl Default for HudConfig {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            scale: 2,
+            message_duration: 4.0,
+            background_opacity: 0.5,
+        }
+    }
+}
+
+/// Guides drawn over the panorama by the shader, the `[overlays]` section. Colors are
+/// RGBA in [0;1].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayConfig {
+    pub horizon: bool,
+    /// Lines of constant azimuth and elevation, labelled in degrees.
+    pub grid: bool,
+    /// Rose in the bottom right corner, its needle points to the north.
+    pub compass: bool,
+    pub crosshair: bool,
+    /// Degrees between two grid lines.
+    pub grid_step: f32,
+    /// Width of the lines in pixels.
+    pub line_width: f32,
+    /// Radius of the compass rose in pixels.
+    pub compass_radius: f32,
+    pub horizon_color: [f32; 4],
+    pub grid_color: [f32; 4],
+    pub compass_color: [f32; 4],
+    pub crosshair_color: [f32; 4],
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            horizon: false,
+            grid: false,
+            compass: false,
+            crosshair: false,
+            grid_step: 15.0,
+            line_width: 1.5,
+            compass_radius: 40.0,
+            horizon_color: [0.2, 0.8, 1.0, 0.8],
+            grid_color: [1.0, 1.0, 1.0, 0.35],
+            compass_color: [1.0, 0.85, 0.3, 0.9],
+            crosshair_color: [1.0, 1.0, 1.0, 0.8],
+        }
+    }
+}
+
+/// How the minimap shows the whole panorama.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MinimapMode {
+    #[default]
+    Equirectangular,
+    /// Stereographic projection centered on the nadir, the horizon halfway to the edge.
+    LittlePlanet,
+}
+
+/// Picture in picture of the whole panorama in the top right corner, with the outline of
+/// the view, the `[minimap]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MinimapConfig {
+    pub visible: bool,
+    pub mode: MinimapMode,
+    /// Width of the minimap in pixels.
+    pub width: u32,
+    /// RGBA in [0;1].
+    pub outline_color: [f32; 4],
+}
+
+impl Default for MinimapConfig {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            mode: MinimapMode::Equirectangular,
+            width: 320,
+            outline_color: [1.0, 0.35, 0.2, 1.0],
+        }
+    }
+}
+
+/// Image laid on a cap around the nadir or the zenith, to hide a tripod, the `[nadir]`
+/// and `[zenith]` sections. The image is centered on the pole and its inscribed disk
+/// covers the cap.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatchConfig {
+    /// Logo or patch, none by default.
+    pub image: Option<PathBuf>,
+    /// Degrees from the pole to the edge of the cap.
+    pub radius: f32,
+    /// Degrees the image is turned by, counterclockwise as seen on screen.
+    pub rotation: f32,
+    pub opacity: f32,
+}
+
+impl Default for PatchConfig {
+    fn default() -> Self {
+        Self {
+            image: None,
+            radius: 20.0,
+            rotation: 0.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// How the second panorama is shown next to the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComparisonMode {
+    /// Each panorama in a half of the window.
+    #[default]
+    SideBySide,
+    /// The second panorama right of a divider dragged with the mouse.
+    Wipe,
+    /// One panorama then the other.
+    Flicker,
+    /// The differences between the panoramas, amplified.
+    Difference,
+}
+
+impl ComparisonMode {
+    pub fn next(self) -> Self {
+        match self {
+            ComparisonMode::SideBySide => ComparisonMode::Wipe,
+            ComparisonMode::Wipe => ComparisonMode::Flicker,
+            ComparisonMode::Flicker => ComparisonMode::Difference,
+            ComparisonMode::Difference => ComparisonMode::SideBySide,
+        }
+    }
+}
+
+/// Second panorama seen through the same camera as the first one, such as the same place
+/// at another date, the `[comparison]` section.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComparisonConfig {
+    /// Panorama compared with the displayed one, none by default.
+    pub image: Option<PathBuf>,
+    pub mode: ComparisonMode,
+    /// Seconds between the panoramas in flicker mode, 0 switches them only by key.
+    pub flicker_interval: f32,
+    /// Multiplier of the differences, small ones are hard to see.
+    pub difference_gain: f32,
+}
+
+impl Default for ComparisonConfig {
+    fn default() -> Self {
+        Self {
+            image: None,
+            mode: ComparisonMode::SideBySide,
+            flicker_interval: 0.5,
+            difference_gain: 4.0,
+        }
+    }
+}
+
+/// Every tunable of the viewer. Each layer overrides the previous one :
+/// the built-in defaults, `config.toml` in the config directory, the `[config]` table of
+/// the sidecar file of the panorama, and the `--set section.key=value` options.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub viewer: ViewerConfig,
+    pub camera: CameraConfig,
+    pub render: RenderConfig,
+    pub window: WindowSettings,
+    pub gamepad: GamepadConfig,
+    pub attract: AttractConfig,
+    pub export: ExportConfig,
+    pub hud: HudConfig,
+    pub overlays: OverlayConfig,
+    pub minimap: MinimapConfig,
+    pub nadir: PatchConfig,
+    pub zenith: PatchConfig,
+    pub comparison: ComparisonConfig,
+}
+
+impl Config {
+
+    fn validate(&self) -> Result<(), String> {
+        let checks = [
+            (self.viewer.slideshow >= 0.0, "viewer.slideshow must be positive"),
+            (self.viewer.transition_duration >= 0.0, "viewer.transition_duration must be positive"),
+            (self.camera.speed >= 0.0, "camera.speed must be positive"),
+            (self.camera.mouse_sensibility >= 0.0, "camera.mouse_sensibility must be positive"),
+            ((0.0..=90.0).contains(&self.camera.radial_max_range), "camera.radial_max_range must be in [0;90]"),
+            ((0.001..=1000.0).contains(&self.camera.focal_length), "camera.focal_length must be in [0.001;1000]"),
+            (self.render.clear_color.iter().all(|channel| (0.0..=1.0).contains(channel)), "render.clear_color channels must be in [0;1]"),
+            (self.window.width > 0 && self.window.height > 0, "window.width and window.height must not be 0"),
+            (self.window.extra.iter().all(|extra| extra.width > 0 && extra.height > 0), "window.extra width and height must not be 0"),
+            ((0.0..1.0).contains(&self.gamepad.dead_zone), "gamepad.dead_zone must be in [0;1["),
+            (self.gamepad.exponent > 0.0, "gamepad.exponent must be greater than 0"),
+            (self.attract.idle_time >= 0.0, "attract.idle_time must be positive"),
+            (self.attract.elevation_amplitude >= 0.0, "attract.elevation_amplitude must be positive"),
+            (self.attract.elevation_period > 0.0, "attract.elevation_period must be greater than 0"),
+            (self.attract.horizon_time >= 0.0, "attract.horizon_time must be positive"),
+            (self.export.fps > 0.0, "export.fps must be greater than 0"),
+            (self.export.width > 0 && self.export.height > 0, "export.width and export.height must not be 0"),
+            (self.export.motion_blur_samples > 0, "export.motion_blur_samples must not be 0"),
+            (self.export.shutter > 0.0 && self.export.shutter <= 1.0, "export.shutter must be in ]0;1]"),
+            (self.hud.scale > 0, "hud.scale must not be 0"),
+            (self.hud.message_duration >= 0.0, "hud.message_duration must be positive"),
+            ((0.0..=1.0).contains(&self.hud.background_opacity), "hud.background_opacity must be in [0;1]"),
+            (self.overlays.grid_step > 0.0 && self.overlays.grid_step <= 90.0, "overlays.grid_step must be in ]0;90]"),
+            (self.overlays.line_width > 0.0, "overlays.line_width must be greater than 0"),
+            (self.overlays.compass_radius > 0.0, "overlays.compass_radius must be greater than 0"),
+            ([self.overlays.horizon_color, self.overlays.grid_color, self.overlays.compass_color, self.overlays.crosshair_color]
+                .iter().flatten().all(|channel| (0.0..=1.0).contains(channel)), "overlays colors channels must be in [0;1]"),
+            (self.minimap.width >= 16, "minimap.width must be at least 16"),
+            (self.nadir.radius > 0.0 && self.nadir.radius <= 90.0, "nadir.radius must be in ]0;90]"),
+            ((0.0..=1.0).contains(&self.nadir.opacity), "nadir.opacity must be in [0;1]"),
+            (self.zenith.radius > 0.0 && self.zenith.radius <= 90.0, "zenith.radius must be in ]0;90]"),
+            ((0.0..=1.0).contains(&self.zenith.opacity), "zenith.opacity must be in [0;1]"),
+            (self.minimap.outline_color.iter().all(|channel| (0.0..=1.0).contains(channel)), "minimap.outline_color channels must be in [0;1]"),
+            (self.comparison.flicker_interval >= 0.0, "comparison.flicker_interval must be positive"),
+            (self.comparison.difference_gain > 0.0, "comparison.difference_gain must be greater than 0"),
+        ];
+
+        match checks.iter().find(|(valid, _)| !valid) {
+            Some((_, message)) => Err(message.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut value = toml::Value::try_from(self).unwrap_or(toml::Value::Table(toml::Table::new()));
+        shorten_floats(&mut value);
+        toml::to_string_pretty(&value).unwrap_or_default()
+    }
+}
+
+/// Print the `f32` values as written, 0.05 rather than 0.05000000074505806.
+fn shorten_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(float) if (*float as f32) as f64 == *float => {
+            *float = (*float as f32).to_string().parse().unwrap_or(*float);
+        }
+        toml::Value::Array(array) => array.iter_mut().for_each(shorten_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, value)| shorten_floats(value)),
+        _ => {}
+    }
+}
+
+/// Copy the keys of `layer` into `base`, recursing into tables.
+fn merge(base: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(layer_table)) => merge(base_table, layer_table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Tables of the `section.key=value` overrides, a value which is not valid TOML is taken
+/// as a string.
+fn parse_overrides(overrides: &[String]) -> Result<toml::Table, String> {
+    let mut layer = toml::Table::new();
+    for assignment in overrides {
+        let (key, value) = assignment.split_once('=')
+            .ok_or(format!("Invalid override \"{assignment}\", expected section.key=value"))?;
+        let value = format!("value = {value}").parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or(toml::Value::String(value.to_string()));
+
+        let mut path: Vec<&str> = key.trim().split('.').collect();
+        let last = path.pop().unwrap();
+        let mut table = toml::Table::new();
+        table.insert(last.to_string(), value);
+        for section in path.iter().rev() {
+            let mut parent = toml::Table::new();
+            parent.insert(section.to_string(), toml::Value::Table(table));
+            table = parent;
+        }
+        merge(&mut layer, table);
+    }
+    Ok(layer)
+}
+
+/// The configuration layers which do not depend on the panorama.
+pub struct ConfigLayers {
+    user: toml::Table,
+    overrides: toml::Table,
+}
+
+impl ConfigLayers {
+
+    pub fn user_config_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("config.toml"))
+    }
+
+    /// Read the user config file and parse the `section.key=value` overrides.
+    pub fn load(overrides: &[String]) -> Result<Self, String> {
+        let user = match Self::user_config_path() {
+            Some(path) if path.exists() => {
+                let content = fs::read_to_string(&path).map_err(|why| format!("{}: {why}", path.display()))?;
+                content.parse::<toml::Table>().map_err(|why| format!("{}: {why}", path.display()))?
+            }
+            _ => toml::Table::new(),
+        };
+
+        let layers = Self {
+            user,
+            overrides: parse_overrides(overrides)?,
+        };
+
+        // report errors of the global layers now rather than for each panorama
+        layers.resolve(None)?;
+        Ok(layers)
+    }
+
+    /// The configuration for a panorama, with its sidecar file if there is one.
+    pub fn resolve(&self, image: Option<&Path>) -> Result<Config, String> {
+        let mut table = toml::Table::new();
+        merge(&mut table, self.user.clone());
+
+        if let Some(image) = image {
+            if let Some(toml::Value::Table(config)) = sidecar::read(image)?.remove("config") {
+                merge(&mut table, config);
+            }
+        }
+
+        merge(&mut table, self.overrides.clone());
+
+        let config: Config = toml::Value::Table(table).try_into().map_err(|why: toml::de::Error| why.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The configuration for a panorama, without its sidecar layer if that one is invalid,
+    /// along with the reason it was left out.
+    pub fn resolve_panorama(&self, image: &Path) -> (Config, Option<String>) {
+        match self.resolve(Some(image)) {
+            Ok(config) => (config, None),
+            Err(why) => {
+                // the other layers were checked by `load`
+                let config = self.resolve(None).unwrap_or_default();
+                (config, Some(format!("Invalid configuration for \"{}\", ignoring its sidecar : {why}", image.display())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers(user: &str, overrides: &[&str]) -> ConfigLayers {
+        ConfigLayers {
+            user: user.parse().unwrap(),
+            overrides: parse_overrides(&overrides.iter().map(|assignment| assignment.to_string()).collect::<Vec<String>>()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn later_layers_override_the_keys_they_set() {
+        let mut base: toml::Table = "[camera]\nspeed = 1.0\nfocal_length = 2.0".parse().unwrap();
+        merge(&mut base, "[camera]\nspeed = 3.0\n[hud]\nscale = 2".parse().unwrap());
+        assert_eq!(base["camera"]["speed"].as_float(), Some(3.0));
+        assert_eq!(base["camera"]["focal_length"].as_float(), Some(2.0));
+        assert_eq!(base["hud"]["scale"].as_integer(), Some(2));
+
+        let config = layers("[camera]\nspeed = 1.0\nmouse_sensibility = 0.1", &["camera.speed=3.0"]).resolve(None).unwrap();
+        assert_eq!(config.camera.speed, 3.0);
+        assert_eq!(config.camera.mouse_sensibility, 0.1);
+        assert_eq!(config.camera.focal_length, CameraConfig::default().focal_length);
+    }
+
+    #[test]
+    fn overrides_are_parsed() {
+        let overrides = parse_overrides(&[
+            "camera.speed=3.5".to_string(),
+            "window.title=My viewer".to_string(),
+            " hud.scale = 2".to_string(),
+            "render.clear_color=[0.0, 0.5, 1.0, 1.0]".to_string(),
+        ]).unwrap();
+        assert_eq!(overrides["camera"]["speed"].as_float(), Some(3.5));
+        // not valid TOML, taken as a string
+        assert_eq!(overrides["window"]["title"].as_str(), Some("My viewer"));
+        assert_eq!(overrides["hud"]["scale"].as_integer(), Some(2));
+        assert_eq!(overrides["render"]["clear_color"].as_array().map(Vec::len), Some(4));
+
+        assert!(parse_overrides(&["camera.speed".to_string()]).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(layers("", &["camera.sped=3.0"]).resolve(None).is_err());
+        assert!(layers("", &["cameras.speed=3.0"]).resolve(None).is_err());
+        assert!(layers("[hud]\nsize = 3", &[]).resolve(None).is_err());
+        // wrong type
+        assert!(layers("", &["hud.scale=big"]).resolve(None).is_err());
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        assert!(Config::default().validate().is_ok());
+        for (assignment, message) in [
+            ("camera.focal_length=0.0", "camera.focal_length"),
+            ("camera.radial_max_range=91.0", "camera.radial_max_range"),
+            ("gamepad.dead_zone=1.0", "gamepad.dead_zone"),
+            ("export.shutter=0.0", "export.shutter"),
+            ("render.clear_color=[0.0, 0.0, 2.0, 1.0]", "render.clear_color"),
+            ("window.width=0", "window.width"),
+        ] {
+            let why = layers("", &[assignment]).resolve(None).err();
+            assert!(why.as_ref().is_some_and(|why| why.starts_with(message)), "{assignment} : {why:?}");
+        }
+    }
+
+    #[test]
+    fn to_toml_round_trips() {
+        let config = layers("", &["camera.speed=3.5", "window.title=Round trip", "overlays.grid=true", "nadir.radius=20.0"])
+            .resolve(None)
+            .unwrap();
+        let text = config.to_toml();
+        let parsed: Config = toml::from_str(&text).unwrap();
+        assert_eq!(parsed.camera.speed, 3.5);
+        assert_eq!(parsed.window.title, "Round trip");
+        assert_eq!(parsed.to_toml(), text);
+    }
+
+    #[test]
+    fn invalid_sidecar_falls_back_to_the_other_layers() {
+        let dir = std::env::temp_dir().join(format!("cosmos360-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("pano.jpg");
+        let layers = layers("[camera]\nspeed = 4.0", &["hud.scale=3"]);
+
+        fs::write(sidecar::sidecar_path(&image), "[config.camera]\nspeed = 1.5\nfocal_length = 2.0\n[config.hud]\nscale = 1").unwrap();
+        let (config, error) = layers.resolve_panorama(&image);
+        assert!(error.is_none());
+        assert_eq!(config.camera.speed, 1.5);
+        assert_eq!(config.camera.focal_length, 2.0);
+        assert_eq!(config.hud.scale, 3);
+
+        fs::write(sidecar::sidecar_path(&image), "[config.camera]\nspeed = -1.0\nfocal_length = 2.0").unwrap();
+        let (config, error) = layers.resolve_panorama(&image);
+        assert!(error.is_some_and(|why| why.contains("camera.speed")));
+        assert_eq!(config.camera.speed, 4.0);
+        assert_eq!(config.camera.focal_length, CameraConfig::default().focal_length);
+        assert_eq!(config.hud.scale, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use image::{ImageFormat, RgbaImage};
+
+use crate::camera::OrientationMode;
+use crate::config::ExportConfig;
+use crate::image_data::ImageData;
+use crate::state::State;
+use crate::tour::{Tour, TourCommand, TourPlayer};
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Offline rendering of a tour, one frame after the other at a fixed time step so the
+/// result does not depend on the speed of the machine.
+pub struct TourExport {
+    player: TourPlayer,
+    config: ExportConfig,
+    dir: PathBuf,
+    encoder: Option<Child>,
+    // panorama on screen, the tour opens the others synchronously
+    image: PathBuf,
+    frame: u32,
+    finished: bool,
+}
+
+impl TourExport {
+
+    pub fn new(tour: Tour, config: ExportConfig, dir: PathBuf, image: &Path, state: &mut State) -> Result<Self, String> {
+        state.check_render_size(config.width, config.height)?;
+        fs::create_dir_all(&dir).map_err(|why| format!("{}: {why}", dir.display()))?;
+        let encoder = match &config.encoder {
+            Some(command) => {
+                let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+                let child = Command::new(shell)
+                    .args([flag, command])
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|why| format!("Unable to start the encoder \"{command}\" : {why}"))?;
+                Some(child)
+            }
+            None => None,
+        };
+
+        // as when a tour is played, for roll and slerp
+        state.set_orientation_mode(OrientationMode::Free);
+        let mut export = Self {
+            // a looping tour is rendered once
+            player: TourPlayer::new(Tour { looping: false, ..tour }),
+            config,
+            dir,
+            encoder,
+            image: image.to_path_buf(),
+            frame: 0,
+            finished: false,
+        };
+        export.advance(state, 0.0)?;
+        Ok(export)
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Render and write the next frame, false once the tour is over.
+    pub fn render_frame(&mut self, state: &mut State) -> Result<bool, String> {
+        if self.finished {
+            return Ok(false);
+        }
+
+        let (width, height) = (self.config.width, self.config.height);
+        let samples = self.config.motion_blur_samples;
+        let frame_time = 1.0 / self.config.fps;
+        let sample_time = self.config.shutter * frame_time / samples as f32;
+
+        let image = if samples == 1 {
+            let image = state.render_offscreen(width, height)?;
+            self.advance(state, frame_time)?;
+            image
+        } else {
+            // temporal supersampling, the samples are spread over the shutter interval and
+            // averaged in linear light
+            let to_linear: Vec<f32> = (0..=255).map(|value| srgb_to_linear(value as f32 / 255.0)).collect();
+            let mut sums = vec![0f32; (width * height * 4) as usize];
+            for _ in 0..samples {
+                let sample = state.render_offscreen(width, height)?;
+                for (index, (sum, value)) in sums.iter_mut().zip(sample.as_raw()).enumerate() {
+                    *sum += match index % 4 {
+                        3 => *value as f32 / 255.0,
+                        _ => to_linear[*value as usize],
+                    };
+                }
+                self.advance(state, sample_time)?;
+            }
+            self.advance(state, (frame_time - sample_time * samples as f32).max(0.0))?;
+            let pixels = sums.iter().enumerate().map(|(index, sum)| {
+                let mean = sum / samples as f32;
+                let value = match index % 4 {
+                    3 => mean,
+                    _ => linear_to_srgb(mean),
+                };
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8
+            }).collect();
+            RgbaImage::from_raw(width, height, pixels).ok_or("Invalid frame size".to_string())?
+        };
+
+        self.frame += 1;
+        self.write_frame(&image)?;
+        Ok(true)
+    }
+
+    fn write_frame(&mut self, image: &RgbaImage) -> Result<(), String> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|why| why.to_string())?;
+
+        let path = self.dir.join(format!("frame_{:06}.png", self.frame));
+        fs::write(&path, &png).map_err(|why| format!("{}: {why}", path.display()))?;
+        if let Some(stdin) = self.encoder.as_mut().and_then(|encoder| encoder.stdin.as_mut()) {
+            stdin.write_all(&png).map_err(|why| format!("Unable to write to the encoder : {why}"))?;
+        }
+        Ok(())
+    }
+
+    /// Move the tour and the camera forward by `delta_time` seconds.
+    fn advance(&mut self, state: &mut State, delta_time: f32) -> Result<(), String> {
+        let mut commands = self.player.update(delta_time, Some(&self.image));
+        let mut animated = false;
+        while !commands.is_empty() {
+            let mut opened = false;
+            for command in commands {
+                match command {
+                    TourCommand::Open(path) => {
+                        state.set_image(&ImageData::new(&path)?);
+                        self.image = path;
+                        opened = true;
+                    }
+                    TourCommand::Jump(view) => state.set_view(&view, 0.0),
+                    TourCommand::Animate { view, duration, easing } => {
+                        // the previous segment ended during this step
+                        state.advance_animation(delta_time);
+                        state.animate_view(&view, duration, easing);
+                        animated = true;
+                    }
+                    TourCommand::Finished => self.finished = true,
+                }
+            }
+            // the player waits for the panorama before going on
+            commands = match opened {
+                true => self.player.update(0.0, Some(&self.image)),
+                false => Vec::new(),
+            };
+        }
+        // a segment which started during this step only runs for the rest of it
+        let elapsed = match animated {
+            true => self.player.segment_elapsed().unwrap_or(delta_time),
+            false => delta_time,
+        };
+        state.advance_animation(elapsed);
+        Ok(())
+    }
+
+    /// Wait for the encoder to write the video.
+    pub fn finish(mut self) -> Result<(), String> {
+        let Some(mut encoder) = self.encoder.take() else {
+            return Ok(());
+        };
+        drop(encoder.stdin.take());
+        let status = encoder.wait().map_err(|why| why.to_string())?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("The encoder failed with {status}")),
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
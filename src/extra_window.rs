@@ -0,0 +1,136 @@
+use crate::camera::{Camera, CameraSettingsBuffer};
+use crate::camera_controller::CameraController;
+use crate::config::Config;
+use crate::key_bindings::{Action, KeyBindings};
+use crate::view::View;
+use crate::window_settings::ExtraWindowSettings;
+
+use winit::event::WindowEvent;
+use winit::window::{Window, WindowId};
+
+use std::sync::Arc;
+
+/// A window next to the main one. It has its own surface and camera, and is drawn by
+/// `State` with the device, pipeline and textures of the main window.
+pub struct ExtraWindow {
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    camera: Camera,
+    settings: CameraSettingsBuffer,
+    // degrees of azimuth and elevation from the main view, none when navigated on its own
+    offsets: Option<(f32, f32)>,
+    camera_controller: CameraController,
+}
+
+impl ExtraWindow {
+
+    /// Window showing `view`, or following it when it is linked.
+    pub fn new(
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+        settings: &ExtraWindowSettings,
+        viewer_config: &Config,
+        view: &View,
+    ) -> Self {
+        let mut camera_controller = CameraController::new(
+            viewer_config.camera.speed,
+            viewer_config.camera.mouse_sensibility,
+            KeyBindings::load(),
+        );
+        camera_controller.apply_config(&viewer_config.camera, &viewer_config.gamepad, &viewer_config.attract);
+        let mut camera_settings = CameraSettingsBuffer::new();
+        camera_settings.resize(config.width as f32, config.height as f32);
+        let mut extra_window = Self {
+            window,
+            surface,
+            config,
+            camera: Camera::new(winit::dpi::PhysicalSize::new(0, 0)),
+            settings: camera_settings,
+            offsets: settings.linked.then_some((settings.azimuth_offset, settings.elevation_offset)),
+            camera_controller,
+        };
+        extra_window.camera.set_mode(viewer_config.camera.orientation_mode);
+        extra_window.follow(view, extra_window.offsets.unwrap_or_default());
+        extra_window
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn surface(&self) -> &wgpu::Surface<'static> {
+        &self.surface
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn settings(&self) -> &CameraSettingsBuffer {
+        &self.settings
+    }
+
+    /// The window follows the main view, the input of the user goes to the main window.
+    pub fn is_linked(&self) -> bool {
+        self.offsets.is_some()
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(device, &self.config);
+        self.settings.resize(width as f32, height as f32);
+    }
+
+    /// Configure the surface again after it was lost.
+    pub fn reconfigure(&self, device: &wgpu::Device) {
+        self.surface.configure(device, &self.config);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        self.camera_controller.process_events(event)
+    }
+
+    pub fn move_camera_by_cursor(&mut self, delta_x: f64, delta_y: f64) {
+        self.camera_controller.move_cursor(&mut self.camera, delta_x, delta_y);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.is_linked() && self.camera_controller.is_moving()
+    }
+
+    /// Actions of the keys pressed in the window, which concern the application.
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        self.camera_controller.take_actions()
+    }
+
+    /// Follow the main view when linked, else move the camera with the user input.
+    pub fn update(&mut self, main_view: &View) {
+        match self.offsets {
+            Some(offsets) => self.follow(main_view, offsets),
+            None => self.camera_controller.update_camera(&mut self.camera, &mut self.settings),
+        }
+    }
+
+    fn follow(&mut self, view: &View, (azimuth_offset, elevation_offset): (f32, f32)) {
+        let mut coordinates = view.coordinates();
+        coordinates.azimuth += azimuth_offset;
+        coordinates.elevation += elevation_offset;
+        self.camera.set_coordinates(coordinates);
+        self.settings.set_focal_length(view.focal_length());
+        self.settings.set_projection(view.projection);
+    }
+}
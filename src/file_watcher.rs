@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// editors write big files in several steps, wait for the file to stay the same
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+struct WatchedFile {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    changed_at: Option<Instant>,
+}
+
+impl WatchedFile {
+    fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
+
+/// Polls the modification time and size of files.
+pub struct FileWatcher {
+    files: Vec<WatchedFile>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Replace the watched files, their current state is not reported as a change.
+    pub fn watch(&mut self, paths: &[PathBuf]) {
+        self.files = paths.iter()
+            .map(|path| WatchedFile {
+                path: path.clone(),
+                stamp: WatchedFile::stamp(path),
+                changed_at: None,
+            })
+            .collect();
+    }
+
+    /// Files which changed and then stayed untouched for the debounce delay.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for file in &mut self.files {
+            let stamp = WatchedFile::stamp(&file.path);
+            if stamp != file.stamp {
+                file.stamp = stamp;
+                file.changed_at = Some(Instant::now());
+            }
+            else if file.changed_at.is_some_and(|changed_at| changed_at.elapsed() >= DEBOUNCE) {
+                println!("File changed : \"{}\"", file.path.display());
+                file.changed_at = None;
+                changed.push(file.path.clone());
+            }
+        }
+        changed
+    }
+
+    /// The application wrote `path` itself, its current state is not reported as a change.
+    pub fn written(&mut self, path: &Path) {
+        if let Some(file) = self.files.iter_mut().find(|file| file.path == path) {
+            file.stamp = WatchedFile::stamp(path);
+            file.changed_at = None;
+        }
+    }
+
+    pub fn next_poll(&self) -> Instant {
+        self.last_poll + POLL_INTERVAL
+    }
+}
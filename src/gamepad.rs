@@ -0,0 +1,306 @@
+use serde::Deserialize;
+
+use std::collections::VecDeque;
+use std::fs;
+
+/// Analog inputs, sticks are in [-1;1] and triggers in [0;1].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// Digital buttons, named after their position on the controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum GamepadEvent {
+    Axis(GamepadAxis, f32),
+    Button(GamepadButton, bool),
+}
+
+/// Where gamepad events come from : a real controller, or any simulated source
+/// implementing this trait to drive the viewer without hardware.
+pub trait GamepadSource {
+    fn poll(&mut self) -> Vec<GamepadEvent>;
+}
+
+#[derive(Deserialize)]
+struct SimulatedGamepadFile {
+    events: Vec<GamepadEvent>,
+}
+
+/// Replays a scripted list of events, one per poll, to simulate a controller.
+///
+/// ```toml
+/// events = [
+///     { Axis = ["LeftStickX", 0.8] },
+///     { Axis = ["LeftStickX", 0.0] },
+///     { Button = ["South", true] },
+///     { Button = ["South", false] },
+/// ]
+/// ```
+pub struct SimulatedGamepad {
+    events: VecDeque<GamepadEvent>,
+}
+
+impl SimulatedGamepad {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let file: SimulatedGamepadFile = toml::from_str(content).map_err(|why| why.to_string())?;
+        Ok(Self {
+            events: file.events.into(),
+        })
+    }
+}
+
+impl GamepadSource for SimulatedGamepad {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        self.events.pop_front().into_iter().collect()
+    }
+}
+
+/// Dead zone and response curve of the analog inputs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnalogResponse {
+    pub dead_zone: f32,
+    /// 1 is linear, higher values give more precision around the center.
+    pub exponent: f32,
+}
+
+impl AnalogResponse {
+
+    /// Remove the dead zone of a single axis and apply the response curve.
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+        let rescaled = ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+        rescaled.powf(self.exponent).copysign(value)
+    }
+
+    /// Same as `apply` with a radial dead zone, so diagonals are not snapped to the axes.
+    pub fn apply_stick(&self, x: f32, y: f32) -> (f32, f32) {
+        let magnitude = x.hypot(y);
+        if magnitude <= self.dead_zone {
+            return (0.0, 0.0);
+        }
+        let scale = self.apply(magnitude) / magnitude;
+        (x * scale, y * scale)
+    }
+}
+
+#[cfg(feature = "gamepad")]
+pub struct GilrsGamepad {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl GilrsGamepad {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = gilrs::Gilrs::new().map_err(|why| why.to_string())?;
+        for (_id, gamepad) in gilrs.gamepads() {
+            println!("Gamepad connected : {}", gamepad.name());
+        }
+        Ok(Self { gilrs })
+    }
+
+    fn button(button: gilrs::Button) -> Option<GamepadButton> {
+        match button {
+            gilrs::Button::South => Some(GamepadButton::South),
+            gilrs::Button::East => Some(GamepadButton::East),
+            gilrs::Button::North => Some(GamepadButton::North),
+            gilrs::Button::West => Some(GamepadButton::West),
+            gilrs::Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+            gilrs::Button::RightTrigger => Some(GamepadButton::RightBumper),
+            gilrs::Button::Select => Some(GamepadButton::Select),
+            gilrs::Button::Start => Some(GamepadButton::Start),
+            gilrs::Button::LeftThumb => Some(GamepadButton::LeftThumb),
+            gilrs::Button::RightThumb => Some(GamepadButton::RightThumb),
+            gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+            gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+            gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+            gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+            _ => None,
+        }
+    }
+
+    fn axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+        match axis {
+            gilrs::Axis::LeftStickX => Some(GamepadAxis::LeftStickX),
+            gilrs::Axis::LeftStickY => Some(GamepadAxis::LeftStickY),
+            gilrs::Axis::RightStickX => Some(GamepadAxis::RightStickX),
+            gilrs::Axis::RightStickY => Some(GamepadAxis::RightStickY),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadSource for GilrsGamepad {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        while let Some(gilrs::Event { event, .. }) = self.gilrs.next_event() {
+            match event {
+                gilrs::EventType::ButtonPressed(button, _) => {
+                    events.extend(Self::button(button).map(|button| GamepadEvent::Button(button, true)));
+                }
+                gilrs::EventType::ButtonReleased(button, _) => {
+                    events.extend(Self::button(button).map(|button| GamepadEvent::Button(button, false)));
+                }
+                // analog triggers are reported as buttons with a value
+                gilrs::EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                    events.push(GamepadEvent::Axis(GamepadAxis::LeftTrigger, value));
+                }
+                gilrs::EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                    events.push(GamepadEvent::Axis(GamepadAxis::RightTrigger, value));
+                }
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    events.extend(Self::axis(axis).map(|axis| GamepadEvent::Axis(axis, value)));
+                }
+                gilrs::EventType::Connected => println!("Gamepad connected"),
+                gilrs::EventType::Disconnected => println!("Gamepad disconnected"),
+                _ => (),
+            }
+        }
+        events
+    }
+}
+
+/// The script given by `COSMOS360_GAMEPAD_SCRIPT` if any, else the controller when the
+/// viewer is built with the `gamepad` feature.
+pub fn default_source() -> Option<Box<dyn GamepadSource>> {
+    if let Some(path) = std::env::var_os("COSMOS360_GAMEPAD_SCRIPT") {
+        println!("Simulating gamepad with \"{}\"", path.to_string_lossy());
+        match fs::read_to_string(&path).map_err(|why| why.to_string()).and_then(|content| SimulatedGamepad::from_toml(&content)) {
+            Ok(gamepad) => return Some(Box::new(gamepad)),
+            Err(why) => println!("Invalid gamepad script : {why}"),
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    match GilrsGamepad::new() {
+        Ok(gamepad) => return Some(Box::new(gamepad)),
+        Err(why) => println!("No gamepad support : {why}"),
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_bindings::{Action, KeyBindings};
+
+    use winit::keyboard::{KeyCode, ModifiersState};
+
+    const RESPONSE: AnalogResponse = AnalogResponse { dead_zone: 0.2, exponent: 2.0 };
+
+    #[test]
+    fn dead_zone_zeroes_small_values() {
+        for value in [0.0, 0.05, -0.1, 0.2, -0.2] {
+            assert_eq!(RESPONSE.apply(value), 0.0, "{value}");
+        }
+        assert!(RESPONSE.apply(0.21) > 0.0);
+        assert!(RESPONSE.apply(-0.21) < 0.0);
+    }
+
+    #[test]
+    fn response_curve_is_monotonic_and_reaches_one() {
+        for exponent in [1.0, 2.0, 3.0] {
+            let response = AnalogResponse { exponent, ..RESPONSE };
+            let mut previous = 0.0;
+            for step in 0..=100 {
+                let value = response.apply(step as f32 / 100.0);
+                assert!(value >= previous, "exponent {exponent} at {step}");
+                assert_eq!(response.apply(-(step as f32) / 100.0), -value);
+                previous = value;
+            }
+            assert_eq!(response.apply(1.0), 1.0);
+            assert_eq!(response.apply(-1.0), -1.0);
+            // sticks report a bit more than 1 on some controllers
+            assert_eq!(response.apply(1.2), 1.0);
+        }
+    }
+
+    #[test]
+    fn stick_dead_zone_is_radial() {
+        assert_eq!(RESPONSE.apply_stick(0.14, 0.14), (0.0, 0.0));
+        assert_eq!(RESPONSE.apply_stick(0.0, -0.2), (0.0, 0.0));
+
+        // each axis alone is in the dead zone, the stick is not
+        assert_eq!(RESPONSE.apply(0.15), 0.0);
+        let (x, y) = RESPONSE.apply_stick(0.15, 0.15);
+        assert!(x > 0.0 && y > 0.0);
+
+        // a diagonal keeps its direction instead of snapping to an axis
+        let (x, y) = RESPONSE.apply_stick(0.5, 0.5);
+        assert!(x > 0.0 && (x - y).abs() < 1e-6);
+        let (x, y) = RESPONSE.apply_stick(0.6, -0.3);
+        assert!((y / x + 0.5).abs() < 1e-5);
+
+        let (x, y) = RESPONSE.apply_stick(std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2);
+        assert!((x.hypot(y) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scripted_buttons_trigger_the_keyboard_actions() {
+        let mut gamepad = SimulatedGamepad::from_toml(r#"
+            events = [
+                { Axis = ["LeftStickX", 0.8] },
+                { Button = ["South", true] },
+                { Button = ["South", false] },
+                { Button = ["RightBumper", true] },
+                { Button = ["DPadLeft", true] },
+                { Button = ["East", true] },
+            ]
+        "#).unwrap();
+        let bindings = KeyBindings::default_bindings();
+
+        let mut pressed = Vec::new();
+        loop {
+            let events = gamepad.poll();
+            if events.is_empty() {
+                break;
+            }
+            for event in events {
+                if let GamepadEvent::Button(button, true) = event {
+                    pressed.push(bindings.gamepad_action(button));
+                }
+            }
+        }
+
+        let keyboard = |key| bindings.action(key, ModifiersState::empty());
+        assert_eq!(pressed, [
+            keyboard(KeyCode::Home),
+            keyboard(KeyCode::PageDown),
+            keyboard(KeyCode::ArrowLeft),
+            None,
+        ]);
+        assert_eq!(pressed[0], Some(Action::ResetView));
+    }
+
+    #[test]
+    fn invalid_script_is_an_error() {
+        assert!(SimulatedGamepad::from_toml("events = [{ Button = [\"Turbo\", true] }]").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+/// Touch input independent from winit, positions in physical pixels and time in seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchInput {
+    pub id: u64,
+    pub phase: TouchPhase,
+    pub position: (f64, f64),
+    pub time: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+/// Navigation recognized from the touches.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gesture {
+    /// Drag in pixels, also emitted by the fling inertia.
+    Pan { delta: (f64, f64) },
+    /// Focal length multiplier keeping `center` under the fingers.
+    Zoom { factor: f64, center: (f64, f64) },
+    DoubleTap { position: (f64, f64) },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Finger {
+    id: u64,
+    position: (f64, f64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum GestureState {
+    Idle,
+    Drag {
+        finger: Finger,
+        start: (f64, f64),
+        start_time: f64,
+        last_time: f64,
+        velocity: (f64, f64),
+    },
+    Pinch {
+        first: Finger,
+        second: Finger,
+    },
+    Fling {
+        velocity: (f64, f64),
+        last_time: f64,
+    },
+}
+
+// A tap is a short touch which barely moves
+const TAP_MAX_DURATION: f64 = 0.25;
+const TAP_MAX_DISTANCE: f64 = 10.0;
+// Two taps make a double tap if close in time and space
+const DOUBLE_TAP_MAX_DELAY: f64 = 0.35;
+const DOUBLE_TAP_MAX_DISTANCE: f64 = 40.0;
+// Fling inertia, velocities in pixels per second
+const FLING_MIN_VELOCITY: f64 = 50.0;
+const FLING_FRICTION: f64 = 4.0;
+// Weight of the newest sample in the smoothed drag velocity
+const VELOCITY_SMOOTHING: f64 = 0.6;
+
+/// State machine turning touch events into gestures, without any access to the display.
+pub struct GestureRecognizer {
+    state: GestureState,
+    last_tap: Option<((f64, f64), f64)>,
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+fn middle(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+}
+
+impl GestureRecognizer {
+
+    pub fn new() -> Self {
+        Self {
+            state: GestureState::Idle,
+            last_tap: None,
+        }
+    }
+
+    pub fn touch(&mut self, input: TouchInput) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+        let finger = Finger { id: input.id, position: input.position };
+
+        self.state = match (self.state, input.phase) {
+            // a new finger stops any inertia
+            (GestureState::Idle | GestureState::Fling { .. }, TouchPhase::Started) => GestureState::Drag {
+                finger,
+                start: input.position,
+                start_time: input.time,
+                last_time: input.time,
+                velocity: (0.0, 0.0),
+            },
+            (GestureState::Drag { finger: first, .. }, TouchPhase::Started) => GestureState::Pinch {
+                first,
+                second: finger,
+            },
+            (GestureState::Drag { finger: previous, start, start_time, last_time, velocity }, TouchPhase::Moved)
+                if previous.id == input.id => {
+                let delta = (input.position.0 - previous.position.0, input.position.1 - previous.position.1);
+                let elapsed = (input.time - last_time).max(0.001);
+                let sample = (delta.0 / elapsed, delta.1 / elapsed);
+                gestures.push(Gesture::Pan { delta });
+                GestureState::Drag {
+                    finger,
+                    start,
+                    start_time,
+                    last_time: input.time,
+                    velocity: (
+                        VELOCITY_SMOOTHING * sample.0 + (1.0 - VELOCITY_SMOOTHING) * velocity.0,
+                        VELOCITY_SMOOTHING * sample.1 + (1.0 - VELOCITY_SMOOTHING) * velocity.1,
+                    ),
+                }
+            }
+            (GestureState::Drag { finger: previous, start, start_time, velocity, .. }, TouchPhase::Ended)
+                if previous.id == input.id => {
+                if input.time - start_time <= TAP_MAX_DURATION && distance(start, input.position) <= TAP_MAX_DISTANCE {
+                    self.tap(input.position, input.time, &mut gestures);
+                    GestureState::Idle
+                }
+                else if velocity.0.hypot(velocity.1) >= FLING_MIN_VELOCITY {
+                    GestureState::Fling { velocity, last_time: input.time }
+                }
+                else {
+                    GestureState::Idle
+                }
+            }
+            (GestureState::Drag { finger: previous, .. }, TouchPhase::Cancelled) if previous.id == input.id => {
+                GestureState::Idle
+            }
+            (GestureState::Pinch { first, second }, TouchPhase::Moved) if first.id == input.id || second.id == input.id => {
+                let (new_first, new_second) = if first.id == input.id { (finger, second) } else { (first, finger) };
+                let old_distance = distance(first.position, second.position);
+                let new_distance = distance(new_first.position, new_second.position);
+                if old_distance > 0.0 && new_distance > 0.0 {
+                    gestures.push(Gesture::Zoom {
+                        factor: new_distance / old_distance,
+                        center: middle(new_first.position, new_second.position),
+                    });
+                }
+                GestureState::Pinch { first: new_first, second: new_second }
+            }
+            // lifting one finger of a pinch goes on dragging with the other one
+            (GestureState::Pinch { first, second }, TouchPhase::Ended | TouchPhase::Cancelled)
+                if first.id == input.id || second.id == input.id => {
+                let remaining = if first.id == input.id { second } else { first };
+                GestureState::Drag {
+                    finger: remaining,
+                    start: remaining.position,
+                    // never a tap
+                    start_time: f64::NEG_INFINITY,
+                    last_time: input.time,
+                    velocity: (0.0, 0.0),
+                }
+            }
+            (state, _) => state,
+        };
+
+        gestures
+    }
+
+    fn tap(&mut self, position: (f64, f64), time: f64, gestures: &mut Vec<Gesture>) {
+        match self.last_tap {
+            Some((last_position, last_time))
+                if time - last_time <= DOUBLE_TAP_MAX_DELAY && distance(position, last_position) <= DOUBLE_TAP_MAX_DISTANCE => {
+                gestures.push(Gesture::DoubleTap { position });
+                self.last_tap = None;
+            }
+            _ => self.last_tap = Some((position, time)),
+        }
+    }
+
+    /// Advance the fling inertia to `time`.
+    pub fn update(&mut self, time: f64) -> Option<Gesture> {
+        let GestureState::Fling { velocity, last_time } = self.state else {
+            return None;
+        };
+
+        let elapsed = (time - last_time).max(0.0);
+        let decay = (-FLING_FRICTION * elapsed).exp();
+        let new_velocity = (velocity.0 * decay, velocity.1 * decay);
+        // integral of the velocity over the elapsed time
+        let travelled = if elapsed > 0.0 { (1.0 - decay) / FLING_FRICTION } else { 0.0 };
+
+        self.state = if new_velocity.0.hypot(new_velocity.1) < FLING_MIN_VELOCITY {
+            GestureState::Idle
+        }
+        else {
+            GestureState::Fling { velocity: new_velocity, last_time: time }
+        };
+
+        Some(Gesture::Pan { delta: (velocity.0 * travelled, velocity.1 * travelled) })
+    }
+
+    pub fn is_flinging(&self) -> bool {
+        matches!(self.state, GestureState::Fling { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(id: u64, phase: TouchPhase, position: (f64, f64), time: f64) -> TouchInput {
+        TouchInput { id, phase, position, time }
+    }
+
+    fn tap(recognizer: &mut GestureRecognizer, position: (f64, f64), time: f64) -> Vec<Gesture> {
+        recognizer.touch(input(0, TouchPhase::Started, position, time));
+        recognizer.touch(input(0, TouchPhase::Ended, position, time + 0.1))
+    }
+
+    fn pan(gestures: &[Gesture]) -> (f64, f64) {
+        match gestures {
+            [Gesture::Pan { delta }] => *delta,
+            _ => panic!("expected a pan, got {gestures:?}"),
+        }
+    }
+
+    #[test]
+    fn one_finger_drag_pans() {
+        let mut recognizer = GestureRecognizer::new();
+        assert!(recognizer.touch(input(0, TouchPhase::Started, (100.0, 100.0), 0.0)).is_empty());
+        assert_eq!(pan(&recognizer.touch(input(0, TouchPhase::Moved, (110.0, 95.0), 0.1))), (10.0, -5.0));
+        assert_eq!(pan(&recognizer.touch(input(0, TouchPhase::Moved, (130.0, 95.0), 0.2))), (20.0, 0.0));
+        // another finger id than the dragging one is ignored
+        assert!(recognizer.touch(input(7, TouchPhase::Moved, (0.0, 0.0), 0.3)).is_empty());
+    }
+
+    #[test]
+    fn two_finger_pinch_zooms_around_the_middle() {
+        let mut recognizer = GestureRecognizer::new();
+        recognizer.touch(input(0, TouchPhase::Started, (100.0, 100.0), 0.0));
+        recognizer.touch(input(1, TouchPhase::Started, (200.0, 100.0), 0.0));
+        let gestures = recognizer.touch(input(1, TouchPhase::Moved, (300.0, 100.0), 0.1));
+        assert_eq!(gestures, [Gesture::Zoom { factor: 2.0, center: (200.0, 100.0) }]);
+        let gestures = recognizer.touch(input(0, TouchPhase::Moved, (200.0, 100.0), 0.2));
+        assert_eq!(gestures, [Gesture::Zoom { factor: 0.5, center: (250.0, 100.0) }]);
+    }
+
+    #[test]
+    fn double_tap_within_the_window() {
+        let mut recognizer = GestureRecognizer::new();
+        assert!(tap(&mut recognizer, (100.0, 100.0), 0.0).is_empty());
+        assert_eq!(tap(&mut recognizer, (110.0, 105.0), 0.3), [Gesture::DoubleTap { position: (110.0, 105.0) }]);
+        // the double tap is consumed, a third tap starts again
+        assert!(tap(&mut recognizer, (110.0, 105.0), 0.6).is_empty());
+    }
+
+    #[test]
+    fn double_tap_outside_the_window() {
+        let mut recognizer = GestureRecognizer::new();
+        tap(&mut recognizer, (100.0, 100.0), 0.0);
+        assert!(tap(&mut recognizer, (100.0, 100.0), 1.0).is_empty(), "too late");
+        assert!(tap(&mut recognizer, (200.0, 100.0), 1.2).is_empty(), "too far");
+
+        // a long press is not a tap
+        let mut recognizer = GestureRecognizer::new();
+        tap(&mut recognizer, (100.0, 100.0), 0.0);
+        recognizer.touch(input(0, TouchPhase::Started, (100.0, 100.0), 0.1));
+        assert!(recognizer.touch(input(0, TouchPhase::Ended, (100.0, 100.0), 0.6)).is_empty());
+    }
+
+    #[test]
+    fn fling_after_release_decays() {
+        let mut recognizer = GestureRecognizer::new();
+        recognizer.touch(input(0, TouchPhase::Started, (0.0, 0.0), 0.0));
+        for step in 1..=5 {
+            recognizer.touch(input(0, TouchPhase::Moved, (step as f64 * 20.0, 0.0), step as f64 * 0.02));
+        }
+        assert!(!recognizer.is_flinging());
+        assert!(recognizer.touch(input(0, TouchPhase::Ended, (100.0, 0.0), 0.1)).is_empty());
+        assert!(recognizer.is_flinging());
+
+        let mut previous = f64::INFINITY;
+        let mut time = 0.1;
+        while recognizer.is_flinging() {
+            time += 0.05;
+            let Some(Gesture::Pan { delta }) = recognizer.update(time) else {
+                panic!("a fling pans");
+            };
+            assert!(delta.0 > 0.0 && delta.0 < previous, "{delta:?} after {previous}");
+            assert_eq!(delta.1, 0.0);
+            previous = delta.0;
+            assert!(time < 5.0, "the fling never stops");
+        }
+        assert_eq!(recognizer.update(time + 0.05), None);
+
+        // a new touch stops the fling
+        recognizer.touch(input(0, TouchPhase::Started, (0.0, 0.0), 0.0));
+        for step in 1..=5 {
+            recognizer.touch(input(0, TouchPhase::Moved, (step as f64 * 20.0, 0.0), step as f64 * 0.02));
+        }
+        recognizer.touch(input(0, TouchPhase::Ended, (100.0, 0.0), 0.1));
+        recognizer.touch(input(1, TouchPhase::Started, (50.0, 50.0), 0.15));
+        assert!(!recognizer.is_flinging());
+        assert_eq!(recognizer.update(0.2), None);
+    }
+
+    #[test]
+    fn slow_release_does_not_fling() {
+        let mut recognizer = GestureRecognizer::new();
+        recognizer.touch(input(0, TouchPhase::Started, (0.0, 0.0), 0.0));
+        recognizer.touch(input(0, TouchPhase::Moved, (20.0, 0.0), 1.0));
+        recognizer.touch(input(0, TouchPhase::Ended, (20.0, 0.0), 2.0));
+        assert!(!recognizer.is_flinging());
+    }
+
+    #[test]
+    fn lifting_a_finger_of_a_pinch_drags() {
+        let mut recognizer = GestureRecognizer::new();
+        recognizer.touch(input(0, TouchPhase::Started, (100.0, 100.0), 0.0));
+        recognizer.touch(input(1, TouchPhase::Started, (200.0, 100.0), 0.0));
+        recognizer.touch(input(1, TouchPhase::Moved, (250.0, 100.0), 0.1));
+        assert!(recognizer.touch(input(0, TouchPhase::Ended, (100.0, 100.0), 0.15)).is_empty());
+        assert_eq!(pan(&recognizer.touch(input(1, TouchPhase::Moved, (240.0, 110.0), 0.2))), (-10.0, 10.0));
+        // the remaining finger is never taken for a tap
+        assert!(recognizer.touch(input(1, TouchPhase::Ended, (240.0, 110.0), 0.25)).is_empty());
+        assert!(tap(&mut recognizer, (240.0, 110.0), 0.3).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::PolarCoordinate;
+use crate::sidecar;
+use crate::view::View;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Symbol drawn at the position of a hotspot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Icon {
+    #[default]
+    Info,
+    /// Way to another panorama.
+    Link,
+    Arrow,
+    Star,
+}
+
+impl Icon {
+    pub fn symbol(self) -> char {
+        match self {
+            Icon::Info => 'i',
+            Icon::Link => '>',
+            Icon::Arrow => '^',
+            Icon::Star => '*',
+        }
+    }
+
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            Icon::Info => [0.15, 0.45, 0.85, 0.9],
+            Icon::Link => [0.15, 0.65, 0.3, 0.9],
+            Icon::Arrow => [0.9, 0.5, 0.1, 0.9],
+            Icon::Star => [0.8, 0.65, 0.05, 0.9],
+        }
+    }
+}
+
+/// A label pinned to a point of the panorama, angles in degrees as in `View`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hotspot {
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default)]
+    pub icon: Icon,
+    /// Panorama opened when the hotspot is clicked, relative to the hotspot file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    /// View shown on the target panorama, or on this one without a target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_view: Option<View>,
+}
+
+impl Hotspot {
+    pub fn direction(&self) -> Vector3<f32> {
+        PolarCoordinate::direction(self.azimuth, self.elevation)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HotspotFile {
+    hotspots: Vec<Hotspot>,
+}
+
+/// The hotspots of a panorama, stored next to it in "pano.hotspots.json" :
+///
+/// ```json
+/// {
+///   "hotspots": [
+///     {
+///       "azimuth": 42.0,
+///       "elevation": -5.0,
+///       "title": "Kitchen",
+///       "description": "Open to the garden",
+///       "icon": "link",
+///       "target": "kitchen.jpg",
+///       "target_view": { "azimuth": 180.0, "elevation": 0.0, "fov": 90.0 }
+///     }
+///   ]
+/// }
+/// ```
+pub struct Hotspots {
+    path: PathBuf,
+    hotspots: Vec<Hotspot>,
+}
+
+impl Hotspots {
+
+    pub fn empty(image: &Path) -> Self {
+        Self {
+            path: sidecar::hotspots_path(image),
+            hotspots: Vec::new(),
+        }
+    }
+
+    /// Read the hotspots of `image`, none if it has no hotspot file.
+    pub fn load(image: &Path) -> Result<Self, String> {
+        let mut hotspots = Self::empty(image);
+        if !hotspots.path.exists() {
+            return Ok(hotspots);
+        }
+        let path = &hotspots.path;
+        let content = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        let file: HotspotFile = serde_json::from_str(&content).map_err(|why| format!("{}: {why}", path.display()))?;
+        hotspots.hotspots = file.hotspots;
+        Ok(hotspots)
+    }
+
+    /// Write the hotspots, the file is removed once the last one is deleted.
+    fn save(&self) -> Result<(), String> {
+        let path = &self.path;
+        if self.hotspots.is_empty() {
+            return match path.exists() {
+                true => fs::remove_file(path).map_err(|why| format!("{}: {why}", path.display())),
+                false => Ok(()),
+            };
+        }
+        let file = HotspotFile { hotspots: self.hotspots.clone() };
+        let content = serde_json::to_string_pretty(&file).map_err(|why| why.to_string())?;
+        fs::write(path, content + "\n").map_err(|why| format!("{}: {why}", path.display()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn all(&self) -> &[Hotspot] {
+        &self.hotspots
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Hotspot> {
+        self.hotspots.get(index)
+    }
+
+    /// Target panorama of a hotspot, resolved against the directory of the hotspot file.
+    pub fn target(&self, index: usize) -> Option<PathBuf> {
+        let target = self.hotspots.get(index)?.target.as_ref()?;
+        Some(self.path.parent().unwrap_or(Path::new("")).join(target))
+    }
+
+    /// Place a new hotspot and return its index.
+    pub fn add(&mut self, azimuth: f32, elevation: f32) -> Result<usize, String> {
+        self.hotspots.push(Hotspot {
+            azimuth,
+            elevation,
+            title: format!("Hotspot {}", self.hotspots.len() + 1),
+            description: String::new(),
+            icon: Icon::default(),
+            target: None,
+            target_view: None,
+        });
+        self.save()?;
+        Ok(self.hotspots.len() - 1)
+    }
+
+    /// Move a hotspot, `save` is false while it is being dragged.
+    pub fn move_to(&mut self, index: usize, azimuth: f32, elevation: f32, save: bool) -> Result<(), String> {
+        if let Some(hotspot) = self.hotspots.get_mut(index) {
+            hotspot.azimuth = azimuth;
+            hotspot.elevation = elevation;
+        }
+        match save {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<Hotspot, String> {
+        if index >= self.hotspots.len() {
+            return Err(format!("No hotspot {index}"));
+        }
+        let hotspot = self.hotspots.remove(index);
+        self.save()?;
+        Ok(hotspot)
+    }
+}
+
+/// Index of the hotspot closest to a world direction, within `tolerance` degrees.
+pub fn pick(hotspots: &[Hotspot], direction: Vector3<f32>, tolerance: f32) -> Option<usize> {
+    let direction = direction.normalize();
+    hotspots.iter()
+        .enumerate()
+        .map(|(index, hotspot)| (index, hotspot.direction().dot(direction).clamp(-1.0, 1.0).acos().to_degrees()))
+        .filter(|(_, angle)| *angle <= tolerance)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
@@ -0,0 +1,444 @@
+use wgpu::util::DeviceExt;
+use wgpu::{Device, Queue};
+
+use crate::config::HudConfig;
+use crate::font::{self, GLYPHS, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Messages kept on screen at once, the oldest go first
+const MAX_MESSAGES: usize = 6;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Glyph of the atlas which is fully lit, for the panels behind the text
+const SOLID_GLYPH: u32 = GLYPHS.len() as u32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HudVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl HudVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A symbol pinned to the panorama, with its label on the right.
+pub struct Marker {
+    /// Center in physical pixels.
+    pub position: (f32, f32),
+    pub symbol: char,
+    pub color: [f32; 4],
+    pub label: String,
+    /// Highlighted, under the cursor or being moved.
+    pub selected: bool,
+}
+
+/// Text centered on a point of the screen.
+pub struct Label {
+    /// Center in physical pixels.
+    pub position: (f32, f32),
+    pub text: String,
+}
+
+/// Connected line segments, ends in physical pixels.
+pub struct Polyline {
+    pub points: Vec<(f32, f32)>,
+    pub color: [f32; 4],
+}
+
+/// What is pinned to the panorama, drawn under the text of the HUD.
+#[derive(Default)]
+pub struct Annotations {
+    pub markers: Vec<Marker>,
+    pub labels: Vec<Label>,
+    pub lines: Vec<Polyline>,
+}
+
+/// Text overlay drawn over the panorama : view information in the top left corner and
+/// transient messages in the bottom left one.
+pub struct Hud {
+    render_pipeline: wgpu::RenderPipeline,
+    font_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    visible: bool,
+    scale: u32,
+    background: [f32; 4],
+    message_duration: Duration,
+    messages: VecDeque<(String, Instant)>,
+    frames: VecDeque<Instant>,
+}
+
+impl Hud {
+
+    pub fn new(device: &Device, queue: &Queue, format: wgpu::TextureFormat, config: &HudConfig) -> Self {
+        let (font_bind_group_layout, font_bind_group) = Self::create_font_texture(device, queue);
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[&font_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("HUD Shader"),
+            source: wgpu::ShaderSource::Wgsl(crate::shaders::HUD_SHADER.into()),
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[HudVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let mut hud = Self {
+            render_pipeline,
+            font_bind_group,
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            num_vertices: 0,
+            visible: true,
+            scale: 1,
+            background: [0.0; 4],
+            message_duration: Duration::ZERO,
+            messages: VecDeque::new(),
+            frames: VecDeque::new(),
+        };
+        hud.apply_config(config);
+        hud
+    }
+
+    pub fn apply_config(&mut self, config: &HudConfig) {
+        self.visible = config.visible;
+        self.scale = config.scale;
+        self.background = [0.0, 0.0, 0.0, config.background_opacity];
+        self.message_duration = Duration::from_secs_f32(config.message_duration);
+    }
+
+    // The glyphs side by side in one row, followed by the solid glyph
+    fn create_font_texture(device: &Device, queue: &Queue) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let width = (SOLID_GLYPH + 1) * GLYPH_WIDTH;
+        let mut pixels = vec![0u8; (width * GLYPH_HEIGHT) as usize];
+        for (index, glyph) in GLYPHS.iter().chain(std::iter::once(&[0b11111; 8])).enumerate() {
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        pixels[y * width as usize + index * GLYPH_WIDTH as usize + x as usize] = 255;
+                    }
+                }
+            }
+        }
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("hud_font_texture"),
+                size: wgpu::Extent3d { width, height: GLYPH_HEIGHT, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &pixels,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // the glyphs are scaled by whole factors, they stay sharp
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("hud_font_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("hud_font_bind_group"),
+        });
+        (layout, bind_group)
+    }
+
+    fn create_vertex_buffer(device: &Device, vertices: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Vertex Buffer"),
+            size: (vertices.max(1024) * std::mem::size_of::<HudVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Side of the square of a marker in pixels.
+    pub fn marker_size(&self) -> u32 {
+        (GLYPH_HEIGHT + 4) * self.scale
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn show_message(&mut self, message: &str) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((message.to_string(), Instant::now() + self.message_duration));
+    }
+
+    /// When the oldest message disappears, the view must then be redrawn.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.messages.iter().map(|(_, expiry)| *expiry).min()
+    }
+
+    /// Count a rendered frame.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        self.frames.push_back(now);
+        while self.frames.front().is_some_and(|frame| now - *frame > Duration::from_secs(1)) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Frames rendered during the last second.
+    pub fn fps(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Lay out the annotations, `info` if the HUD is visible, and the messages for the next
+    /// `draw`.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, screen: (u32, u32), annotations: &Annotations, info: &[String]) {
+        let now = Instant::now();
+        self.messages.retain(|(_, expiry)| *expiry > now);
+
+        let mut vertices = Vec::new();
+        let line_height = (GLYPH_HEIGHT + 2) * self.scale;
+        let margin = 2 * line_height / 3;
+        for line in &annotations.lines {
+            for pair in line.points.windows(2) {
+                self.segment(&mut vertices, screen, pair[0], pair[1], line.color);
+            }
+        }
+        for marker in &annotations.markers {
+            self.marker(&mut vertices, screen, marker);
+        }
+        for label in &annotations.labels {
+            let width = label.text.chars().count() as f32 * ((GLYPH_WIDTH + 1) * self.scale) as f32;
+            let (x, y) = (label.position.0 - width / 2.0, label.position.1 - (GLYPH_HEIGHT * self.scale) as f32 / 2.0);
+            if x >= 0.0 && y >= 0.0 {
+                self.text_block(&mut vertices, screen, (x as u32, y as u32), std::slice::from_ref(&label.text));
+            }
+        }
+        if self.visible && !info.is_empty() {
+            self.text_block(&mut vertices, screen, (margin, margin), info);
+        }
+        let messages: Vec<String> = self.messages.iter()
+            .flat_map(|(message, _)| message.lines().map(str::to_string).collect::<Vec<String>>())
+            .collect();
+        if !messages.is_empty() {
+            let height = messages.len() as u32 * line_height;
+            let top = screen.1.saturating_sub(margin + height);
+            self.text_block(&mut vertices, screen, (margin, top), &messages);
+        }
+
+        self.num_vertices = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+        let size = (vertices.len() * std::mem::size_of::<HudVertex>()) as wgpu::BufferAddress;
+        if size > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_vertex_buffer(device, vertices.len() * 2);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    /// Draw what `prepare` laid out over the content of `view`.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if self.num_vertices == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("HUD Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.font_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..(self.num_vertices as usize * std::mem::size_of::<HudVertex>()) as wgpu::BufferAddress));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+
+    // Lines of text over a translucent panel, `origin` is the top left corner in pixels
+    fn text_block(&self, vertices: &mut Vec<HudVertex>, screen: (u32, u32), origin: (u32, u32), lines: &[String]) {
+        let advance = (GLYPH_WIDTH + 1) * self.scale;
+        let line_height = (GLYPH_HEIGHT + 2) * self.scale;
+        let padding = self.scale * 2;
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u32;
+
+        let panel = (
+            origin.0 - padding.min(origin.0),
+            origin.1 - padding.min(origin.1),
+            columns * advance + 2 * padding,
+            lines.len() as u32 * line_height + 2 * padding,
+        );
+        Self::quad(vertices, screen, panel, SOLID_GLYPH, self.background);
+
+        for (row, line) in lines.iter().enumerate() {
+            let y = origin.1 + row as u32 * line_height + self.scale;
+            for (column, character) in line.chars().enumerate() {
+                if character == ' ' {
+                    continue;
+                }
+                let x = origin.0 + column as u32 * advance;
+                let rect = (x, y, GLYPH_WIDTH * self.scale, GLYPH_HEIGHT * self.scale);
+                Self::quad(vertices, screen, rect, Self::glyph_index(character), TEXT_COLOR);
+            }
+        }
+    }
+
+    // Colored square holding the symbol, the label is hidden with the HUD except for the
+    // selected marker
+    fn marker(&self, vertices: &mut Vec<HudVertex>, screen: (u32, u32), marker: &Marker) {
+        let size = self.marker_size();
+        let (x, y) = (marker.position.0 - size as f32 / 2.0, marker.position.1 - size as f32 / 2.0);
+        if x < 0.0 || y < 0.0 || x + size as f32 > screen.0 as f32 || y + size as f32 > screen.1 as f32 {
+            return;
+        }
+        let (x, y) = (x.round() as u32, y.round() as u32);
+
+        if marker.selected {
+            let border = self.scale;
+            let outline = (x.saturating_sub(border), y.saturating_sub(border), size + 2 * border, size + 2 * border);
+            Self::quad(vertices, screen, outline, SOLID_GLYPH, TEXT_COLOR);
+        }
+        Self::quad(vertices, screen, (x, y, size, size), SOLID_GLYPH, marker.color);
+        let glyph = (x + (size - GLYPH_WIDTH * self.scale) / 2, y + 2 * self.scale, GLYPH_WIDTH * self.scale, GLYPH_HEIGHT * self.scale);
+        Self::quad(vertices, screen, glyph, Self::glyph_index(marker.symbol), TEXT_COLOR);
+
+        if (self.visible || marker.selected) && !marker.label.is_empty() {
+            let origin = (x + size + 3 * self.scale, y + self.scale);
+            self.text_block(vertices, screen, origin, std::slice::from_ref(&marker.label));
+        }
+    }
+
+    // Line as a quad as wide as a font pixel
+    fn segment(&self, vertices: &mut Vec<HudVertex>, screen: (u32, u32), start: (f32, f32), end: (f32, f32), color: [f32; 4]) {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = dx.hypot(dy);
+        if length < 0.001 {
+            return;
+        }
+        let half_width = self.scale as f32 / 2.0;
+        let (nx, ny) = (-dy / length * half_width, dx / length * half_width);
+        let atlas_width = ((SOLID_GLYPH + 1) * GLYPH_WIDTH) as f32;
+        let u = (SOLID_GLYPH as f32 + 0.5) * GLYPH_WIDTH as f32 / atlas_width;
+        let vertex = |x: f32, y: f32| HudVertex {
+            position: [x / screen.0 as f32 * 2.0 - 1.0, 1.0 - y / screen.1 as f32 * 2.0],
+            tex_coords: [u, 0.5],
+            color,
+        };
+        let (a, b) = (vertex(start.0 + nx, start.1 + ny), vertex(start.0 - nx, start.1 - ny));
+        let (c, d) = (vertex(end.0 + nx, end.1 + ny), vertex(end.0 - nx, end.1 - ny));
+        vertices.extend_from_slice(&[a, b, c, c, b, d]);
+    }
+
+    fn glyph_index(character: char) -> u32 {
+        let character = match character {
+            '°' => font::DEGREE_SIGN,
+            ' '..='~' => character,
+            _ => '?',
+        };
+        character as u32 - font::FIRST_CHAR as u32
+    }
+
+    // Two triangles covering `rect` (x, y, width, height) in pixels, textured with a glyph
+    fn quad(vertices: &mut Vec<HudVertex>, screen: (u32, u32), rect: (u32, u32, u32, u32), glyph: u32, color: [f32; 4]) {
+        let (x, y, width, height) = rect;
+        let to_ndc = |px: u32, py: u32| [
+            px as f32 / screen.0 as f32 * 2.0 - 1.0,
+            1.0 - py as f32 / screen.1 as f32 * 2.0,
+        ];
+        let atlas_width = ((SOLID_GLYPH + 1) * GLYPH_WIDTH) as f32;
+        let u0 = (glyph * GLYPH_WIDTH) as f32 / atlas_width;
+        let u1 = ((glyph + 1) * GLYPH_WIDTH) as f32 / atlas_width;
+
+        let corner = |px: u32, py: u32, u: f32, v: f32| HudVertex { position: to_ndc(px, py), tex_coords: [u, v], color };
+        let top_left = corner(x, y, u0, 0.0);
+        let top_right = corner(x + width, y, u1, 0.0);
+        let bottom_left = corner(x, y + height, u0, 1.0);
+        let bottom_right = corner(x + width, y + height, u1, 1.0);
+        vertices.extend_from_slice(&[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+    }
+}
@@ -4,6 +4,8 @@ use image::{
     RgbaImage,
 };
 
+use std::path::Path;
+
 pub struct ImageData {
     //image: DynamicImage,
     diffuse_rgba: RgbaImage,
@@ -12,15 +14,17 @@ pub struct ImageData {
 
 impl ImageData {
 
-    pub fn new(filename: &str) -> Result<Self, &'static str> { 
+    pub fn new(path: &Path) -> Result<Self, String> { 
         //let diffuse_bytes = include_bytes!(filename);
         //let diffuse_image = image::load_from_memory(diffuse_bytes).unwrap();
 
-        println!("Opening image \"{filename}\"");
-        let image = match ImageReader::open(filename).unwrap().decode() {
-            Err(why) => {panic!("{}", why)}
-            Ok(value) => value
-        };
+        println!("Opening image \"{}\"", path.display());
+        let image = ImageReader::open(path)
+            .map_err(|why| format!("{}: {why}", path.display()))?
+            .with_guessed_format()
+            .map_err(|why| format!("{}: {why}", path.display()))?
+            .decode()
+            .map_err(|why| format!("{}: {why}", path.display()))?;
 
         let diffuse_rgba = image.to_rgba8();
         let dimensions = image.dimensions();
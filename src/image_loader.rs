@@ -0,0 +1,77 @@
+use crate::image_data::ImageData;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+type LoadResult = Result<ImageData, String>;
+
+/// Decodes images on background threads and keeps them until they are displayed.
+pub struct ImageLoader {
+    sender: Sender<(PathBuf, LoadResult)>,
+    receiver: Receiver<(PathBuf, LoadResult)>,
+    pending: HashSet<PathBuf>,
+    cache: HashMap<PathBuf, LoadResult>,
+}
+
+impl ImageLoader {
+
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            pending: HashSet::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn request(&mut self, path: &Path) {
+        if self.cache.contains_key(path) || self.pending.contains(path) {
+            return;
+        }
+        self.pending.insert(path.to_path_buf());
+
+        let sender = self.sender.clone();
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let image = ImageData::new(&path);
+            let _ = sender.send((path, image));
+        });
+    }
+
+    /// Decode again an image which may have changed.
+    pub fn reload(&mut self, path: &Path) {
+        self.cache.remove(path);
+        self.pending.remove(path);
+        self.request(path);
+    }
+
+    /// Collect the finished images, returns true if there was any.
+    pub fn poll(&mut self) -> bool {
+        let mut received = false;
+        while let Ok((path, image)) = self.receiver.try_recv() {
+            // forgotten in the meantime
+            if self.pending.remove(&path) {
+                self.cache.insert(path, image);
+                received = true;
+            }
+        }
+        received
+    }
+
+    pub fn is_loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn take(&mut self, path: &Path) -> Option<LoadResult> {
+        self.cache.remove(path)
+    }
+
+    /// Drop the images which are not in `paths`.
+    pub fn retain(&mut self, paths: &[PathBuf]) {
+        self.cache.retain(|path, _| paths.contains(path));
+        self.pending.retain(|path| paths.contains(path));
+    }
+}
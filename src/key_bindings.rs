@@ -0,0 +1,436 @@
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+
+use winit::keyboard::{
+    KeyCode,
+    ModifiersState,
+};
+
+use crate::config;
+use crate::gamepad::GamepadButton;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Everything a key can trigger in the viewer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    ZoomIn,
+    ZoomOut,
+    RollLeft,
+    RollRight,
+    ToggleHorizonLock,
+    ResetView,
+    NextImage,
+    PreviousImage,
+    /// Open the image whose path is in the clipboard.
+    PastePath,
+    Screenshot,
+    /// Save the whole panorama with its patches and regions baked in.
+    ExportEquirectangular,
+    /// Open another window on the panorama, navigated on its own.
+    OpenWindow,
+    ToggleFullscreen,
+    ToggleExclusiveFullscreen,
+    ToggleProjection,
+    Quit,
+    /// The only way to quit in kiosk mode.
+    KioskExit,
+    /// Put the view string in the clipboard.
+    CopyView,
+    /// Play or pause the tour given with `--tour` or the last recorded one.
+    ToggleTour,
+    StopTour,
+    /// Start recording a tour, or stop and save it.
+    ToggleTourRecording,
+    /// Add the current view to the tour being recorded.
+    RecordKeyframe,
+    /// Show or hide the view information overlay.
+    ToggleHud,
+    /// Place, move and delete hotspots with the mouse.
+    ToggleHotspotEditing,
+    ToggleHorizonLine,
+    ToggleGrid,
+    ToggleCompass,
+    ToggleCrosshair,
+    ToggleMinimap,
+    /// Switch the minimap between the equirectangular image and the little planet.
+    ToggleMinimapMode,
+    /// Pick points with the mouse and measure the angles between them.
+    ToggleMeasuring,
+    RemoveMeasurePoint,
+    ClearMeasurement,
+    /// Write the measurement next to the panorama as CSV and JSON.
+    ExportMeasurement,
+    /// Save the measured polygon as a region of the panorama.
+    AddRegion,
+    /// Switch the region under the cursor to its next effect.
+    CycleRegionEffect,
+    RemoveRegion,
+    /// Show or hide the tinted and outlined regions, blurs and masks stay.
+    ToggleRegionHighlights,
+    /// Show or hide the panorama given with `--compare`.
+    ToggleComparison,
+    /// Switch between side by side, wipe, flicker and difference.
+    CycleComparisonMode,
+    /// Show the other panorama in flicker mode.
+    FlipComparison,
+    GoToBookmark1,
+    GoToBookmark2,
+    GoToBookmark3,
+    GoToBookmark4,
+    GoToBookmark5,
+    GoToBookmark6,
+    GoToBookmark7,
+    GoToBookmark8,
+    GoToBookmark9,
+    SaveBookmark1,
+    SaveBookmark2,
+    SaveBookmark3,
+    SaveBookmark4,
+    SaveBookmark5,
+    SaveBookmark6,
+    SaveBookmark7,
+    SaveBookmark8,
+    SaveBookmark9,
+}
+
+/// Go to and save actions of each bookmark slot, from 1.
+const BOOKMARK_ACTIONS: [(Action, Action); 9] = [
+    (Action::GoToBookmark1, Action::SaveBookmark1),
+    (Action::GoToBookmark2, Action::SaveBookmark2),
+    (Action::GoToBookmark3, Action::SaveBookmark3),
+    (Action::GoToBookmark4, Action::SaveBookmark4),
+    (Action::GoToBookmark5, Action::SaveBookmark5),
+    (Action::GoToBookmark6, Action::SaveBookmark6),
+    (Action::GoToBookmark7, Action::SaveBookmark7),
+    (Action::GoToBookmark8, Action::SaveBookmark8),
+    (Action::GoToBookmark9, Action::SaveBookmark9),
+];
+
+impl Action {
+    /// Slot of a bookmark action, and whether it saves the view rather than going to it.
+    pub fn bookmark(self) -> Option<(u8, bool)> {
+        BOOKMARK_ACTIONS.iter()
+            .enumerate()
+            .find_map(|(index, (go_to, save))| match self {
+                action if action == *go_to => Some((index as u8 + 1, false)),
+                action if action == *save => Some((index as u8 + 1, true)),
+                _ => None,
+            })
+    }
+}
+
+/// A key with the exact modifiers which must be held, written "Ctrl+Shift+KeyS" in the config file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyBinding {
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = ModifiersState::empty();
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().filter(|name| !name.is_empty())
+            .ok_or(format!("missing key in binding \"{text}\""))?;
+
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CONTROL,
+                "shift" => ModifiersState::SHIFT,
+                "alt" => ModifiersState::ALT,
+                "super" | "meta" | "cmd" => ModifiersState::SUPER,
+                _ => return Err(format!("unknown modifier \"{part}\" in binding \"{text}\"")),
+            };
+        }
+
+        Ok(Self {
+            key: Self::parse_key(key_name).ok_or(format!("unknown key \"{key_name}\" in binding \"{text}\""))?,
+            modifiers,
+        })
+    }
+
+    // Accept winit names ("KeyW", "ArrowUp", "F11") and the short forms "W" and "1"
+    fn parse_key(name: &str) -> Option<KeyCode> {
+        let name = match name.len() {
+            1 if name.chars().all(|c| c.is_ascii_alphabetic()) => format!("Key{}", name.to_uppercase()),
+            1 if name.chars().all(|c| c.is_ascii_digit()) => format!("Digit{name}"),
+            _ => name.to_string(),
+        };
+        let deserializer: serde::de::value::StringDeserializer<serde::de::value::Error> = name.into_deserializer();
+        KeyCode::deserialize(deserializer).ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyBindingsFile {
+    #[serde(default)]
+    bindings: BTreeMap<Action, Vec<String>>,
+    #[serde(default)]
+    gamepad: BTreeMap<Action, Vec<GamepadButton>>,
+}
+
+/// Keys bound to each action, overridable with a `bindings.toml` file such as
+///
+/// ```toml
+/// [bindings]
+/// zoom_in = ["J", "Ctrl+ArrowUp"]
+/// screenshot = ["F12"]
+///
+/// [gamepad]
+/// next_image = ["RightBumper"]
+/// ```
+pub struct KeyBindings {
+    bindings: BTreeMap<Action, Vec<KeyBinding>>,
+    gamepad: BTreeMap<Action, Vec<GamepadButton>>,
+}
+
+impl KeyBindings {
+
+    pub fn default_bindings() -> Self {
+        let defaults: &[(Action, &[&str])] = &[
+            (Action::PanLeft, &["A", "ArrowLeft"]),
+            (Action::PanRight, &["D", "ArrowRight"]),
+            (Action::PanUp, &["W", "ArrowUp"]),
+            (Action::PanDown, &["S", "ArrowDown"]),
+            (Action::ZoomIn, &["J", "Equal"]),
+            (Action::ZoomOut, &["K", "Minus"]),
+            (Action::RollLeft, &["Q"]),
+            (Action::RollRight, &["E"]),
+            (Action::ToggleHorizonLock, &["L"]),
+            (Action::ResetView, &["Home"]),
+            (Action::NextImage, &["PageDown", "N"]),
+            (Action::PreviousImage, &["PageUp", "B"]),
+            (Action::PastePath, &["Ctrl+V"]),
+            (Action::Screenshot, &["F12", "Ctrl+S"]),
+            (Action::ExportEquirectangular, &["Ctrl+Shift+S"]),
+            (Action::OpenWindow, &["Ctrl+N"]),
+            (Action::ToggleFullscreen, &["F11"]),
+            (Action::ToggleExclusiveFullscreen, &["Alt+Enter"]),
+            (Action::ToggleProjection, &["P"]),
+            (Action::Quit, &["Escape", "Ctrl+Q"]),
+            (Action::KioskExit, &["Ctrl+Alt+Shift+Q"]),
+            (Action::CopyView, &["Ctrl+C"]),
+            (Action::ToggleTour, &["Space"]),
+            (Action::StopTour, &["Shift+Space"]),
+            (Action::ToggleTourRecording, &["R"]),
+            (Action::RecordKeyframe, &["Enter"]),
+            (Action::ToggleHud, &["H", "F1"]),
+            (Action::ToggleHotspotEditing, &["Tab"]),
+            (Action::ToggleHorizonLine, &["F2"]),
+            (Action::ToggleGrid, &["G", "F3"]),
+            (Action::ToggleCompass, &["C", "F4"]),
+            (Action::ToggleCrosshair, &["X", "F5"]),
+            (Action::ToggleMinimap, &["M", "F6"]),
+            (Action::ToggleMinimapMode, &["Shift+M"]),
+            (Action::ToggleMeasuring, &["T", "F7"]),
+            (Action::RemoveMeasurePoint, &["Backspace"]),
+            (Action::ClearMeasurement, &["Delete"]),
+            (Action::ExportMeasurement, &["Ctrl+E"]),
+            (Action::AddRegion, &["Ctrl+R"]),
+            (Action::CycleRegionEffect, &["Shift+R"]),
+            (Action::RemoveRegion, &["Ctrl+Delete"]),
+            (Action::ToggleRegionHighlights, &["F8"]),
+            (Action::ToggleComparison, &["F9"]),
+            (Action::CycleComparisonMode, &["V"]),
+            (Action::FlipComparison, &["Shift+V"]),
+            (Action::GoToBookmark1, &["1"]),
+            (Action::GoToBookmark2, &["2"]),
+            (Action::GoToBookmark3, &["3"]),
+            (Action::GoToBookmark4, &["4"]),
+            (Action::GoToBookmark5, &["5"]),
+            (Action::GoToBookmark6, &["6"]),
+            (Action::GoToBookmark7, &["7"]),
+            (Action::GoToBookmark8, &["8"]),
+            (Action::GoToBookmark9, &["9"]),
+            (Action::SaveBookmark1, &["Ctrl+1"]),
+            (Action::SaveBookmark2, &["Ctrl+2"]),
+            (Action::SaveBookmark3, &["Ctrl+3"]),
+            (Action::SaveBookmark4, &["Ctrl+4"]),
+            (Action::SaveBookmark5, &["Ctrl+5"]),
+            (Action::SaveBookmark6, &["Ctrl+6"]),
+            (Action::SaveBookmark7, &["Ctrl+7"]),
+            (Action::SaveBookmark8, &["Ctrl+8"]),
+            (Action::SaveBookmark9, &["Ctrl+9"]),
+        ];
+
+        let gamepad_defaults = [
+            (Action::ResetView, GamepadButton::South),
+            (Action::ToggleProjection, GamepadButton::North),
+            (Action::Screenshot, GamepadButton::West),
+            (Action::ToggleHorizonLock, GamepadButton::Select),
+            (Action::ToggleFullscreen, GamepadButton::Start),
+            (Action::NextImage, GamepadButton::RightBumper),
+            (Action::PreviousImage, GamepadButton::LeftBumper),
+            (Action::PanUp, GamepadButton::DPadUp),
+            (Action::PanDown, GamepadButton::DPadDown),
+            (Action::PanLeft, GamepadButton::DPadLeft),
+            (Action::PanRight, GamepadButton::DPadRight),
+        ];
+
+        Self {
+            bindings: defaults.iter()
+                .map(|(action, keys)| (*action, keys.iter().map(|key| KeyBinding::parse(key).unwrap()).collect()))
+                .collect(),
+            gamepad: gamepad_defaults.iter()
+                .map(|(action, button)| (*action, vec![*button]))
+                .collect(),
+        }
+    }
+
+    /// The actions listed in the file replace their default bindings, the others keep them.
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let file: KeyBindingsFile = toml::from_str(content).map_err(|why| why.to_string())?;
+        let mut key_bindings = Self::default_bindings();
+
+        for (action, keys) in file.bindings {
+            let keys = keys.iter()
+                .map(|key| KeyBinding::parse(key))
+                .collect::<Result<Vec<KeyBinding>, String>>()?;
+            key_bindings.bindings.insert(action, keys);
+        }
+        key_bindings.gamepad.extend(file.gamepad);
+
+        Ok(key_bindings)
+    }
+
+    pub fn config_path() -> Option<PathBuf> {
+        Some(config::config_dir()?.join("bindings.toml"))
+    }
+
+    /// Load the user bindings, falling back to the defaults if the file is missing or invalid.
+    pub fn load() -> Self {
+        let key_bindings = match Self::config_path() {
+            Some(path) if path.exists() => {
+                println!("Loading key bindings from \"{}\"", path.display());
+                match fs::read_to_string(&path).map_err(|why| why.to_string()).and_then(|content| Self::from_toml(&content)) {
+                    Ok(key_bindings) => key_bindings,
+                    Err(why) => {
+                        println!("Invalid key bindings file \"{}\" : {why}, using defaults", path.display());
+                        Self::default_bindings()
+                    }
+                }
+            }
+            _ => Self::default_bindings(),
+        };
+
+        for (binding, actions) in key_bindings.conflicts() {
+            println!("Key binding conflict : {:?} {:?} is bound to {:?}, only {:?} will be triggered",
+                binding.modifiers, binding.key, actions, actions[0]);
+        }
+
+        key_bindings
+    }
+
+    /// Bindings shared by several actions, with the actions in the order they are resolved.
+    pub fn conflicts(&self) -> Vec<(KeyBinding, Vec<Action>)> {
+        let mut conflicts: Vec<(KeyBinding, Vec<Action>)> = Vec::new();
+        for (action, keys) in &self.bindings {
+            for key in keys {
+                match conflicts.iter_mut().find(|(binding, _)| binding == key) {
+                    Some((_, actions)) => actions.push(*action),
+                    None => conflicts.push((*key, vec![*action])),
+                }
+            }
+        }
+        conflicts.retain(|(_, actions)| actions.len() > 1);
+        conflicts
+    }
+
+    /// Action triggered by pressing `key` while holding exactly `modifiers`.
+    pub fn action(&self, key: KeyCode, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings.iter()
+            .find(|(_, keys)| keys.iter().any(|binding| binding.key == key && binding.modifiers == modifiers))
+            .map(|(action, _)| *action)
+    }
+
+    /// Actions bound to `key` whatever the modifiers, used to release held keys.
+    pub fn actions_for_key(&self, key: KeyCode) -> Vec<Action> {
+        self.bindings.iter()
+            .filter(|(_, keys)| keys.iter().any(|binding| binding.key == key))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    pub fn gamepad_action(&self, button: GamepadButton) -> Option<Action> {
+        self.gamepad.iter()
+            .find(|(_, buttons)| buttons.contains(&button))
+            .map(|(action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_are_parsed() {
+        let binding = KeyBinding::parse("Ctrl+Shift+KeyS").unwrap();
+        assert_eq!(binding.key, KeyCode::KeyS);
+        assert_eq!(binding.modifiers, ModifiersState::CONTROL | ModifiersState::SHIFT);
+
+        // short key names, any case for the modifiers and spaces around them
+        let binding = KeyBinding::parse("control + alt + 1").unwrap();
+        assert_eq!(binding.key, KeyCode::Digit1);
+        assert_eq!(binding.modifiers, ModifiersState::CONTROL | ModifiersState::ALT);
+        assert_eq!(KeyBinding::parse("w").unwrap().key, KeyCode::KeyW);
+        assert_eq!(KeyBinding::parse("Cmd+ArrowUp").unwrap().modifiers, ModifiersState::SUPER);
+        assert!(KeyBinding::parse("F11").unwrap().modifiers.is_empty());
+    }
+
+    #[test]
+    fn bad_bindings_are_rejected() {
+        assert!(KeyBinding::parse("NoSuchKey").is_err());
+        assert!(KeyBinding::parse("Hyper+A").is_err());
+        assert!(KeyBinding::parse("Ctrl+").is_err());
+        assert!(KeyBinding::parse("").is_err());
+        assert!(KeyBindings::from_toml("[bindings]\nzoom_in = [\"Ctrl+Nope\"]").is_err());
+        assert!(KeyBindings::from_toml("[bindings]\nno_such_action = [\"A\"]").is_err());
+    }
+
+    #[test]
+    fn file_replaces_only_the_listed_actions() {
+        let key_bindings = KeyBindings::from_toml("[bindings]\nzoom_in = [\"Ctrl+ArrowUp\"]").unwrap();
+        assert_eq!(key_bindings.action(KeyCode::ArrowUp, ModifiersState::CONTROL), Some(Action::ZoomIn));
+        assert_eq!(key_bindings.action(KeyCode::KeyJ, ModifiersState::empty()), None);
+        assert_eq!(key_bindings.action(KeyCode::KeyK, ModifiersState::empty()), Some(Action::ZoomOut));
+        // the modifiers must match exactly
+        assert_eq!(key_bindings.action(KeyCode::ArrowUp, ModifiersState::empty()), Some(Action::PanUp));
+        assert_eq!(key_bindings.action(KeyCode::ArrowUp, ModifiersState::CONTROL | ModifiersState::SHIFT), None);
+    }
+
+    #[test]
+    fn conflicts_are_detected() {
+        assert!(KeyBindings::default_bindings().conflicts().is_empty());
+
+        let key_bindings = KeyBindings::from_toml("[bindings]\nzoom_in = [\"K\"]\nroll_left = [\"Shift+K\"]").unwrap();
+        let conflicts = key_bindings.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        let (binding, actions) = &conflicts[0];
+        assert_eq!(binding.key, KeyCode::KeyK);
+        assert!(binding.modifiers.is_empty());
+        assert_eq!(actions, &vec![Action::ZoomIn, Action::ZoomOut]);
+        // the first action in the resolution order wins
+        assert_eq!(key_bindings.action(KeyCode::KeyK, ModifiersState::empty()), Some(actions[0]));
+    }
+
+    #[test]
+    fn held_key_is_released_whatever_the_modifiers() {
+        let key_bindings = KeyBindings::from_toml("[bindings]\npan_up = [\"Shift+ArrowUp\"]").unwrap();
+        let held = key_bindings.action(KeyCode::ArrowUp, ModifiersState::SHIFT).unwrap();
+        assert_eq!(held, Action::PanUp);
+
+        // Shift is let go before the arrow, the release has no modifiers
+        assert_eq!(key_bindings.action(KeyCode::ArrowUp, ModifiersState::empty()), None);
+        assert!(key_bindings.actions_for_key(KeyCode::ArrowUp).contains(&held));
+        assert!(key_bindings.actions_for_key(KeyCode::KeyZ).is_empty());
+    }
+}
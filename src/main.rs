@@ -1,23 +1,65 @@
 mod application;
+mod bookmarks;
+mod cli;
+mod comparison;
 mod image_data;
+mod image_loader;
 mod state;
 mod camera;
 mod camera_controller;
+mod config;
+mod export;
+mod extra_window;
+mod file_watcher;
+mod font;
+mod gamepad;
+mod gestures;
+mod hotspots;
+mod hud;
+mod key_bindings;
+mod measurement;
+mod minimap;
+mod overlays;
+mod patches;
+mod playlist;
+mod project;
+mod regions;
+mod scene_fade;
+mod session;
+mod shaders;
+mod sidecar;
 mod texture;
+mod tour;
+mod view;
+mod window_settings;
 
 use application::CosmosViewer;
+use cli::Arguments;
 
-pub fn main() -> Result<(), &'static str> {
+pub fn main() -> Result<(), String> {
 
     //let event_loop = EventLoop::new().unwrap();
     //event_loop.set_control_flow(ControlFlow::Poll);
 
+    let arguments = Arguments::parse(std::env::args().skip(1))?;
+    if arguments.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let print_config = arguments.print_config;
+    let mut cosmos_viewer = CosmosViewer::new(arguments);
+    cosmos_viewer.load_config()?;
+    if print_config {
+        println!("{}", cosmos_viewer.config().to_toml());
+        return Ok(());
+    }
+
     // load an image
-    let mut cosmos_viewer = CosmosViewer::new();
     cosmos_viewer.load_image()?;
     
     // start the application
-    let handler = CosmosViewer::run();
+    let handler = cosmos_viewer.run();
 
     let _ = handler.join();
 
@@ -0,0 +1,264 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+
+use crate::camera::PolarCoordinate;
+use crate::sidecar;
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Color of the points and arcs on screen.
+pub const COLOR: [f32; 4] = [1.0, 0.8, 0.1, 1.0];
+
+/// A point picked on the sphere, angles in degrees as in `View`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MeasuredPoint {
+    pub azimuth: f32,
+    pub elevation: f32,
+}
+
+impl MeasuredPoint {
+    pub fn direction(&self) -> Vector3<f32> {
+        PolarCoordinate::direction(self.azimuth, self.elevation)
+    }
+}
+
+// What the JSON export contains
+#[derive(Serialize)]
+struct Report<'a> {
+    points: &'a [MeasuredPoint],
+    /// Degrees between each point and the next one.
+    segments: Vec<f32>,
+    length: f32,
+    /// Degrees between the last point and the first one, with at least 3 points.
+    closing_segment: Option<f32>,
+    /// Area of the polygon of the points, with at least 3 points.
+    solid_angle_steradians: Option<f32>,
+    solid_angle_square_degrees: Option<f32>,
+}
+
+/// Points picked on the panorama in measurement mode, the polyline between them follows
+/// great circles.
+#[derive(Debug, Clone, Default)]
+pub struct Measurement {
+    points: Vec<MeasuredPoint>,
+}
+
+impl Measurement {
+
+    pub fn points(&self) -> &[MeasuredPoint] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn add(&mut self, azimuth: f32, elevation: f32) {
+        self.points.push(MeasuredPoint { azimuth, elevation });
+    }
+
+    pub fn remove_last(&mut self) -> Option<MeasuredPoint> {
+        self.points.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Degrees between each point and the next one.
+    pub fn segments(&self) -> Vec<f32> {
+        self.points.windows(2).map(|pair| angular_distance(pair[0].direction(), pair[1].direction())).collect()
+    }
+
+    pub fn length(&self) -> f32 {
+        self.segments().iter().sum()
+    }
+
+    /// The polygon is closed once it has 3 points.
+    pub fn is_polygon(&self) -> bool {
+        self.points.len() >= 3
+    }
+
+    pub fn closing_segment(&self) -> Option<f32> {
+        let (first, last) = (self.points.first()?, self.points.last()?);
+        self.is_polygon().then(|| angular_distance(last.direction(), first.direction()))
+    }
+
+    /// Steradians covered by the polygon of the points, as a fan of signed spherical
+    /// triangles from the first one (Van Oosterom and Strackee), which also holds for
+    /// concave polygons. The outline splits the sphere in two and the smaller part is
+    /// measured whatever the winding, so a polygon larger than a hemisphere gives the area
+    /// of the rest of the sphere. A self-intersecting outline has no meaningful area.
+    pub fn solid_angle(&self) -> Option<f32> {
+        if !self.is_polygon() {
+            return None;
+        }
+        let directions: Vec<Vector3<f32>> = self.points.iter().map(MeasuredPoint::direction).collect();
+        let a = directions[0];
+        let signed: f32 = directions[1..].windows(2)
+            .map(|pair| {
+                let (b, c) = (pair[0], pair[1]);
+                2.0 * a.dot(b.cross(c)).atan2(1.0 + a.dot(b) + b.dot(c) + c.dot(a))
+            })
+            .sum();
+        // the sum is the area on the left of the outline, up to a whole sphere
+        let sphere = 4.0 * std::f32::consts::PI;
+        let left = signed.rem_euclid(sphere);
+        Some(left.min(sphere - left))
+    }
+
+    /// Lines of the measurement for the HUD.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!("Measure   {} points", self.points.len())];
+        if self.points.len() >= 2 {
+            lines.push(format!("Length    {:7.2}°", self.length()));
+        }
+        if let (Some(closing), Some(solid_angle)) = (self.closing_segment(), self.solid_angle()) {
+            lines.push(format!("Closing   {:7.2}°", closing));
+            lines.push(format!("Area      {:.4} sr ({:.1} sq deg)", solid_angle, square_degrees(solid_angle)));
+        }
+        lines
+    }
+
+    /// One line per point, with the distance from the previous one.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("point,azimuth,elevation,distance_from_previous\n");
+        let distances = std::iter::once(None).chain(self.segments().into_iter().map(Some));
+        for (index, (point, distance)) in self.points.iter().zip(distances).enumerate() {
+            let distance = distance.map(|distance| distance.to_string()).unwrap_or_default();
+            let _ = writeln!(csv, "{},{},{},{}", index + 1, point.azimuth, point.elevation, distance);
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        let solid_angle = self.solid_angle();
+        let report = Report {
+            points: &self.points,
+            segments: self.segments(),
+            length: self.length(),
+            closing_segment: self.closing_segment(),
+            solid_angle_steradians: solid_angle,
+            solid_angle_square_degrees: solid_angle.map(square_degrees),
+        };
+        serde_json::to_string_pretty(&report).map_err(|why| why.to_string())
+    }
+
+    /// Write the measurement next to `image`, as "pano.measurements.csv" and
+    /// "pano.measurements.json", and return the paths.
+    pub fn export(&self, image: &Path) -> Result<Vec<PathBuf>, String> {
+        let files = [
+            (sidecar::measurements_path(image, "csv"), self.to_csv()),
+            (sidecar::measurements_path(image, "json"), self.to_json()? + "\n"),
+        ];
+        files.into_iter()
+            .map(|(path, content)| fs::write(&path, content).map(|_| path.clone()).map_err(|why| format!("{}: {why}", path.display())))
+            .collect()
+    }
+}
+
+/// Degrees between two directions.
+pub fn angular_distance(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    a.cross(b).magnitude().atan2(a.dot(b)).to_degrees()
+}
+
+fn square_degrees(steradians: f32) -> f32 {
+    steradians * (180.0 / std::f32::consts::PI).powi(2)
+}
+
+/// Points along the great circle from `a` to `b`, both included.
+pub fn great_circle(a: Vector3<f32>, b: Vector3<f32>, steps: u32) -> Vec<Vector3<f32>> {
+    let (a, b) = (a.normalize(), b.normalize());
+    let angle = a.cross(b).magnitude().atan2(a.dot(b));
+    if angle.sin().abs() < 0.00001 {
+        return vec![a, b];
+    }
+    (0..=steps)
+        .map(|step| {
+            let t = step as f32 / steps as f32;
+            (a * ((1.0 - t) * angle).sin() + b * (t * angle).sin()) / angle.sin()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    fn measurement(points: &[(f32, f32)]) -> Measurement {
+        let mut measurement = Measurement::default();
+        for (azimuth, elevation) in points {
+            measurement.add(*azimuth, *elevation);
+        }
+        measurement
+    }
+
+    #[test]
+    fn segments_follow_great_circles() {
+        let measurement = measurement(&[(0.0, 0.0), (90.0, 0.0), (90.0, 90.0)]);
+        let segments = measurement.segments();
+        assert_eq!(segments.len(), 2);
+        assert!((segments[0] - 90.0).abs() < 0.01);
+        assert!((segments[1] - 90.0).abs() < 0.01);
+        assert!((measurement.length() - 180.0).abs() < 0.01);
+        assert!((measurement.closing_segment().unwrap() - 90.0).abs() < 0.01);
+
+        let line = self::measurement(&[(350.0, 0.0), (10.0, 0.0)]);
+        assert!((line.segments()[0] - 20.0).abs() < 0.01);
+        assert!(line.closing_segment().is_none());
+        assert!(line.solid_angle().is_none());
+    }
+
+    #[test]
+    fn octant_covers_an_eighth_of_the_sphere() {
+        for points in [[(0.0, 0.0), (90.0, 0.0), (0.0, 90.0)], [(0.0, 90.0), (90.0, 0.0), (0.0, 0.0)]] {
+            let solid_angle = measurement(&points).solid_angle().unwrap();
+            assert!((solid_angle - PI / 2.0).abs() < 0.001, "{points:?} : {solid_angle}");
+        }
+    }
+
+    #[test]
+    fn square_around_the_pole() {
+        // sum of the interior angles minus 2π
+        let square = measurement(&[(0.0, 45.0), (90.0, 45.0), (180.0, 45.0), (270.0, 45.0)]);
+        assert!((square.solid_angle().unwrap() - 1.3593).abs() < 0.001);
+
+        // the equator splits the sphere in two halves
+        let equator = measurement(&[(0.0, 0.0), (90.0, 0.0), (180.0, 0.0), (270.0, 0.0)]);
+        assert!((equator.solid_angle().unwrap() - 2.0 * PI).abs() < 0.001);
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // an L of three squares of one degree, small enough to be almost flat
+        let corner = measurement(&[(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)]);
+        let area = square_degrees(corner.solid_angle().unwrap());
+        assert!((area - 3.0).abs() < 0.01, "{area}");
+    }
+
+    #[test]
+    fn exports_list_the_points_and_distances() {
+        let measurement = measurement(&[(0.0, 0.0), (90.0, 0.0), (0.0, 90.0)]);
+        let csv = measurement.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "point,azimuth,elevation,distance_from_previous");
+        assert_eq!(lines[1], "1,0,0,");
+        assert!(lines[2].starts_with("2,90,0,89.99") || lines[2].starts_with("2,90,0,90"), "{}", lines[2]);
+        assert_eq!(lines.len(), 4);
+
+        let json: serde_json::Value = serde_json::from_str(&measurement.to_json().unwrap()).unwrap();
+        assert_eq!(json["points"].as_array().unwrap().len(), 3);
+        assert_eq!(json["points"][1]["azimuth"].as_f64(), Some(90.0));
+        assert_eq!(json["segments"].as_array().unwrap().len(), 2);
+        assert!((json["solid_angle_steradians"].as_f64().unwrap() - std::f64::consts::PI / 2.0).abs() < 0.001);
+        assert!((json["solid_angle_square_degrees"].as_f64().unwrap() - 5156.62).abs() < 1.0);
+
+        let json: serde_json::Value = serde_json::from_str(&self::measurement(&[(0.0, 0.0)]).to_json().unwrap()).unwrap();
+        assert!(json["closing_segment"].is_null());
+        assert!(json["solid_angle_steradians"].is_null());
+    }
+}
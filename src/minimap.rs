@@ -0,0 +1,307 @@
+use wgpu::{Device, Queue};
+
+use crate::config::{MinimapConfig, MinimapMode};
+
+// Distance between the minimap and the corner of the screen, in pixels
+const MARGIN: f32 = 16.0;
+// Width of the outline of the view, in pixels
+const OUTLINE_WIDTH: f32 = 2.0;
+// Side of the square at the center of the view, in pixels
+const CENTER_SIZE: f32 = 5.0;
+/// tan of half the angle from the nadir at the edge of the little planet, the horizon is
+/// halfway to the edge. Same as in the shader.
+const LITTLE_PLANET_RANGE: f32 = 2.0;
+
+// How the fragment shader colors a vertex, see `EQUIRECTANGULAR` in the shader
+#[repr(u32)]
+#[derive(Clone, Copy)]
+enum Kind {
+    Equirectangular = 0,
+    LittlePlanet = 1,
+    Solid = 2,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MinimapVertex {
+    position: [f32; 2],
+    coords: [f32; 2],
+    color: [f32; 4],
+    kind: u32,
+}
+
+impl MinimapVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Uint32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// The whole panorama in the top right corner, with the outline of what the camera sees.
+pub struct Minimap {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    visible: bool,
+    mode: MinimapMode,
+    width: u32,
+    outline_color: [f32; 4],
+    // left, top, width and height in pixels, as laid out by the last `prepare`
+    rect: (f32, f32, f32, f32),
+}
+
+impl Minimap {
+
+    /// `texture_layout` is the layout of the panorama texture bind group.
+    pub fn new(device: &Device, format: wgpu::TextureFormat, texture_layout: &wgpu::BindGroupLayout, config: &MinimapConfig) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Minimap Pipeline Layout"),
+            bind_group_layouts: &[texture_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Minimap Shader"),
+            source: wgpu::ShaderSource::Wgsl(crate::shaders::MINIMAP_SHADER.into()),
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Minimap Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[MinimapVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let mut minimap = Self {
+            render_pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            num_vertices: 0,
+            visible: false,
+            mode: MinimapMode::Equirectangular,
+            width: 0,
+            outline_color: [0.0; 4],
+            rect: (0.0, 0.0, 0.0, 0.0),
+        };
+        minimap.apply_config(config);
+        minimap
+    }
+
+    pub fn apply_config(&mut self, config: &MinimapConfig) {
+        self.visible = config.visible;
+        self.mode = config.mode;
+        self.width = config.width;
+        self.outline_color = config.outline_color;
+    }
+
+    fn create_vertex_buffer(device: &Device, vertices: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Minimap Vertex Buffer"),
+            size: (vertices.max(256) * std::mem::size_of::<MinimapVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn mode(&self) -> MinimapMode {
+        self.mode
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            MinimapMode::Equirectangular => MinimapMode::LittlePlanet,
+            MinimapMode::LittlePlanet => MinimapMode::Equirectangular,
+        };
+    }
+
+    // Top right corner, never wider than half of the screen
+    fn layout(&self, screen: (u32, u32)) -> (f32, f32, f32, f32) {
+        let width = (self.width as f32).min(screen.0 as f32 / 2.0);
+        let height = match self.mode {
+            MinimapMode::Equirectangular => width / 2.0,
+            MinimapMode::LittlePlanet => width,
+        };
+        (screen.0 as f32 - MARGIN - width, MARGIN, width, height)
+    }
+
+    /// Azimuth and elevation shown at a position of the screen in physical pixels, `None`
+    /// if it is not over the minimap.
+    pub fn direction_at(&self, position: (f32, f32)) -> Option<(f32, f32)> {
+        let (left, top, width, height) = self.rect;
+        if !self.visible || width == 0.0 {
+            return None;
+        }
+        let (x, y) = ((position.0 - left) / width, (position.1 - top) / height);
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+        map_direction(self.mode, (x, y))
+    }
+
+    /// Lay out the panorama and the view for the next `draw`. `outline` is the border of
+    /// the view as azimuths and elevations, `center` the direction of the camera.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, screen: (u32, u32), outline: &[(f32, f32)], center: (f32, f32)) {
+        self.num_vertices = 0;
+        if !self.visible {
+            return;
+        }
+        self.rect = self.layout(screen);
+        let (left, top, width, height) = self.rect;
+        let to_ndc = |x: f32, y: f32| [x / screen.0 as f32 * 2.0 - 1.0, 1.0 - y / screen.1 as f32 * 2.0];
+        let to_screen = |(x, y): (f32, f32)| (left + x * width, top + y * height);
+
+        let mut vertices = Vec::new();
+        let (kind, coords) = match self.mode {
+            MinimapMode::Equirectangular => (Kind::Equirectangular, [0.0, 1.0]),
+            MinimapMode::LittlePlanet => (Kind::LittlePlanet, [-1.0, 1.0]),
+        };
+        let corner = |x: f32, y: f32| MinimapVertex {
+            position: to_ndc(left + x * width, top + y * height),
+            coords: [coords[0] + x * (coords[1] - coords[0]), coords[0] + y * (coords[1] - coords[0])],
+            color: [1.0; 4],
+            kind: kind as u32,
+        };
+        vertices.extend_from_slice(&[
+            corner(0.0, 0.0), corner(0.0, 1.0), corner(1.0, 0.0),
+            corner(1.0, 0.0), corner(0.0, 1.0), corner(1.0, 1.0),
+        ]);
+
+        let points: Vec<Option<(f32, f32)>> = outline.iter()
+            .map(|(azimuth, elevation)| map_position(self.mode, *azimuth, *elevation))
+            .collect();
+        for (index, start) in points.iter().enumerate() {
+            let end = points[(index + 1) % points.len()];
+            let (Some(start), Some(end)) = (*start, end) else {
+                continue;
+            };
+            // the outline leaves one side of the equirectangular image for the other one
+            if (start.0 - end.0).abs() > 0.5 {
+                continue;
+            }
+            self.segment(&mut vertices, &to_ndc, to_screen(start), to_screen(end));
+        }
+        if let Some(position) = map_position(self.mode, center.0, center.1) {
+            let (x, y) = to_screen(position);
+            let half = CENTER_SIZE / 2.0;
+            self.segment(&mut vertices, &to_ndc, (x - half, y), (x + half, y));
+            self.segment(&mut vertices, &to_ndc, (x, y - half), (x, y + half));
+        }
+
+        self.num_vertices = vertices.len() as u32;
+        let size = (vertices.len() * std::mem::size_of::<MinimapVertex>()) as wgpu::BufferAddress;
+        if size > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_vertex_buffer(device, vertices.len() * 2);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    // Line of the outline as a quad, ends in pixels
+    fn segment(&self, vertices: &mut Vec<MinimapVertex>, to_ndc: &dyn Fn(f32, f32) -> [f32; 2], start: (f32, f32), end: (f32, f32)) {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = dx.hypot(dy);
+        if length < 0.001 {
+            return;
+        }
+        let (nx, ny) = (-dy / length * OUTLINE_WIDTH / 2.0, dx / length * OUTLINE_WIDTH / 2.0);
+        let vertex = |x: f32, y: f32| MinimapVertex {
+            position: to_ndc(x, y),
+            coords: [0.0; 2],
+            color: self.outline_color,
+            kind: Kind::Solid as u32,
+        };
+        let (a, b) = (vertex(start.0 + nx, start.1 + ny), vertex(start.0 - nx, start.1 - ny));
+        let (c, d) = (vertex(end.0 + nx, end.1 + ny), vertex(end.0 - nx, end.1 - ny));
+        vertices.extend_from_slice(&[a, b, c, c, b, d]);
+    }
+
+    /// Draw what `prepare` laid out over the content of `view`, `panorama` being the bind
+    /// group of the panorama texture.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, panorama: &wgpu::BindGroup) {
+        if self.num_vertices == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Minimap Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, panorama, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..(self.num_vertices as usize * std::mem::size_of::<MinimapVertex>()) as wgpu::BufferAddress));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+/// Position in the minimap, in [0;1], of a point of the panorama, `None` out of the little
+/// planet.
+fn map_position(mode: MinimapMode, azimuth: f32, elevation: f32) -> Option<(f32, f32)> {
+    match mode {
+        // same texture coordinates as the panorama shader
+        MinimapMode::Equirectangular => Some(((-azimuth).rem_euclid(360.0) / 360.0, (90.0 - elevation) / 180.0)),
+        MinimapMode::LittlePlanet => {
+            let radius = ((elevation + 90.0) / 2.0).to_radians().tan() / LITTLE_PLANET_RANGE;
+            let azimuth = azimuth.to_radians();
+            (radius <= 1.0).then(|| ((1.0 - azimuth.sin() * radius) / 2.0, (1.0 - azimuth.cos() * radius) / 2.0))
+        }
+    }
+}
+
+/// Inverse of `map_position` : azimuth and elevation at a position of the minimap.
+fn map_direction(mode: MinimapMode, (x, y): (f32, f32)) -> Option<(f32, f32)> {
+    match mode {
+        MinimapMode::Equirectangular => Some(((-x * 360.0).rem_euclid(360.0), 90.0 - y * 180.0)),
+        MinimapMode::LittlePlanet => {
+            let (x, y) = (x * 2.0 - 1.0, y * 2.0 - 1.0);
+            let radius = x.hypot(y);
+            if radius > 1.0 {
+                return None;
+            }
+            let polar = 2.0 * (radius * LITTLE_PLANET_RANGE).atan().to_degrees();
+            Some((f32::atan2(-x, -y).to_degrees().rem_euclid(360.0), polar - 90.0))
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::comparison::ComparisonUniform;
+use crate::config::OverlayConfig;
+
+const HORIZON: u32 = 1;
+const GRID: u32 = 2;
+const COMPASS: u32 = 4;
+const CROSSHAIR: u32 = 8;
+// Distance between the compass rose and the corner of the screen, in pixels
+const COMPASS_MARGIN: f32 = 16.0;
+
+/// The overlays of `OverlayConfig` as the shader reads them, see `Overlays` in the shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OverlayUniform {
+    horizon_color: [f32; 4],
+    grid_color: [f32; 4],
+    compass_color: [f32; 4],
+    crosshair_color: [f32; 4],
+    flags: u32,
+    grid_step: f32,
+    line_width: f32,
+    /// Degrees clockwise from the north to the direction the camera looks at.
+    heading: f32,
+    compass_center: [f32; 2],
+    compass_radius: f32,
+    /// Regions drawn by the shader, see `regions::shader_data`.
+    region_count: u32,
+    /// Nadir and zenith patches, see `patches::shader_data`.
+    patches: [[f32; 4]; 2],
+    /// Second panorama, see `comparison::Comparison`.
+    comparison: ComparisonUniform,
+}
+
+impl OverlayUniform {
+
+    pub fn new(config: &OverlayConfig, heading: f32, screen: (f32, f32), region_count: u32, patches: [[f32; 4]; 2], comparison: ComparisonUniform) -> Self {
+        let flags = [(config.horizon, HORIZON), (config.grid, GRID), (config.compass, COMPASS), (config.crosshair, CROSSHAIR)]
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(0, |flags, (_, flag)| flags | flag);
+        Self {
+            horizon_color: config.horizon_color,
+            grid_color: config.grid_color,
+            compass_color: config.compass_color,
+            crosshair_color: config.crosshair_color,
+            flags,
+            grid_step: config.grid_step,
+            line_width: config.line_width,
+            heading,
+            compass_center: compass_center(config, screen).into(),
+            compass_radius: config.compass_radius,
+            region_count,
+            patches,
+            comparison,
+        }
+    }
+}
+
+/// Center of the compass rose in pixels, in the bottom right corner.
+pub fn compass_center(config: &OverlayConfig, screen: (f32, f32)) -> (f32, f32) {
+    let offset = config.compass_radius + COMPASS_MARGIN;
+    (screen.0 - offset, screen.1 - offset)
+}
+
+/// Compass heading of the camera, from its azimuth which grows counterclockwise.
+pub fn heading(azimuth: f32, north: f32) -> f32 {
+    (north - azimuth).rem_euclid(360.0)
+}
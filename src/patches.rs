@@ -0,0 +1,78 @@
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use wgpu::{Device, Queue};
+
+use crate::config::PatchConfig;
+
+use std::path::Path;
+
+/// Side of the layers of the patch texture, the images are resized to it.
+const PATCH_SIZE: u32 = 1024;
+
+/// Layer of the nadir patch in the texture, the zenith one is the next.
+pub const NADIR: usize = 0;
+pub const ZENITH: usize = 1;
+
+/// Read a patch image, resized to a layer of the patch texture.
+pub fn load(path: &Path) -> Result<RgbaImage, String> {
+    let image = image::open(path).map_err(|why| format!("{}: {why}", path.display()))?;
+    Ok(imageops::resize(&image.to_rgba8(), PATCH_SIZE, PATCH_SIZE, FilterType::Triangle))
+}
+
+/// Radius, rotation, opacity and whether the patch is drawn, see `patches` in the
+/// `Overlays` of the shader.
+pub fn shader_data(config: &PatchConfig, loaded: bool) -> [f32; 4] {
+    [config.radius, config.rotation, config.opacity, if loaded { 1.0 } else { 0.0 }]
+}
+
+/// The nadir and zenith images as the two layers of a texture.
+pub struct PatchTexture {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl PatchTexture {
+
+    pub fn new(device: &Device) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("patch_texture"),
+            size: wgpu::Extent3d { width: PATCH_SIZE, height: PATCH_SIZE, depth_or_array_layers: 2 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self { texture, view, sampler }
+    }
+
+    /// Replace the image of a layer, `NADIR` or `ZENITH`.
+    pub fn write(&self, queue: &Queue, layer: usize, image: &RgbaImage) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * PATCH_SIZE),
+                rows_per_image: Some(PATCH_SIZE),
+            },
+            wgpu::Extent3d { width: PATCH_SIZE, height: PATCH_SIZE, depth_or_array_layers: 1 },
+        );
+    }
+}
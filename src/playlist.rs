@@ -0,0 +1,240 @@
+use image::ImageFormat;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether two paths lead to the same file.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Panoramas opened from the command line, browsed with next and previous.
+pub struct Playlist {
+    entries: Vec<PathBuf>,
+    index: usize,
+}
+
+impl Playlist {
+
+    /// Each path can be an image, a directory of images, a glob pattern or a playlist file
+    /// listing one image per line.
+    pub fn from_paths(paths: &[String]) -> Result<Self, String> {
+        let mut entries = Vec::new();
+
+        for path in paths {
+            if Path::new(path).is_dir() {
+                entries.extend(Self::directory(Path::new(path))?);
+            }
+            else if path.contains(['*', '?', '[']) {
+                let matches = glob::glob(path).map_err(|why| format!("Invalid pattern \"{path}\" : {why}"))?;
+                let mut images: Vec<PathBuf> = matches.filter_map(Result::ok).filter(|path| Self::is_image(path)).collect();
+                images.sort();
+                entries.extend(images);
+            }
+            else if Self::is_playlist(Path::new(path)) {
+                entries.extend(Self::playlist_file(Path::new(path))?);
+            }
+            else {
+                entries.push(PathBuf::from(path));
+            }
+        }
+
+        if entries.is_empty() {
+            return Err("No panorama to open".to_string());
+        }
+
+        Ok(Self {
+            entries,
+            index: 0,
+        })
+    }
+
+    /// Playlist of a previous session, without the images deleted since.
+    pub fn from_entries(entries: Vec<PathBuf>, index: usize) -> Option<Self> {
+        let current = entries.get(index).cloned();
+        let entries: Vec<PathBuf> = entries.into_iter().filter(|path| path.is_file()).collect();
+        let index = entries.iter().position(|path| Some(path) == current.as_ref()).unwrap_or(0);
+        (!entries.is_empty()).then_some(Self { entries, index })
+    }
+
+    pub fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(ImageFormat::from_extension)
+            .is_some_and(|format| format.reading_enabled())
+    }
+
+    fn is_playlist(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension.to_lowercase().as_str(), "m3u" | "m3u8" | "txt" | "playlist"))
+    }
+
+    fn directory(path: &Path) -> Result<Vec<PathBuf>, String> {
+        let mut images: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|why| format!("{}: {why}", path.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| Self::is_image(path))
+            .collect();
+        images.sort();
+        Ok(images)
+    }
+
+    // one path per line, relative to the playlist, lines starting with # are comments
+    fn playlist_file(path: &Path) -> Result<Vec<PathBuf>, String> {
+        let content = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Ok(content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| base.join(line))
+            .collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.entries[self.index]
+    }
+
+    /// Add an image after the current one and make it current.
+    pub fn insert(&mut self, path: PathBuf) {
+        self.index = (self.index + 1).min(self.entries.len());
+        self.entries.insert(self.index, path);
+    }
+
+    /// Make `path` current, adding it after the current image if it is not in the list.
+    pub fn select(&mut self, path: &Path) {
+        match self.entries.iter().position(|entry| entry == path) {
+            Some(index) => self.index = index,
+            None => self.insert(path.to_path_buf()),
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.index = (self.index + 1) % self.entries.len();
+    }
+
+    pub fn previous(&mut self) {
+        self.index = (self.index + self.entries.len() - 1) % self.entries.len();
+    }
+
+    /// Images before and after the current one, worth preloading.
+    pub fn neighbours(&self) -> Vec<PathBuf> {
+        let count = self.entries.len();
+        let mut neighbours = vec![self.entries[(self.index + 1) % count].clone()];
+        let previous = &self.entries[(self.index + count - 1) % count];
+        if !neighbours.contains(previous) {
+            neighbours.push(previous.clone());
+        }
+        neighbours.retain(|path| path != self.current());
+        neighbours
+    }
+
+    /// File name of the current image.
+    pub fn name(&self) -> String {
+        self.current().file_name().unwrap_or_default().to_string_lossy().to_string()
+    }
+
+    /// Title of the window, after the title of the application.
+    pub fn title(&self, application: &str) -> String {
+        format!("{application} - {} ({}/{})", self.name(), self.index + 1, self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory in the temporary directory, holding the empty `files`
+    fn scratch_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cosmos360-playlist-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        dir
+    }
+
+    fn names(playlist: &Playlist) -> Vec<String> {
+        playlist.entries().iter().map(|path| path.file_name().unwrap().to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn directory_lists_its_images_sorted() {
+        let dir = scratch_dir("directory", &["c.png", "a.jpg", "notes.txt", "b.jpeg", "pano.toml"]);
+        let playlist = Playlist::from_paths(&[dir.to_string_lossy().to_string()]).unwrap();
+        assert_eq!(names(&playlist), vec!["a.jpg", "b.jpeg", "c.png"]);
+        assert_eq!(playlist.index(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn glob_matches_images_sorted() {
+        let dir = scratch_dir("glob", &["2.jpg", "10.jpg", "1.jpg", "1.png", "3.toml"]);
+        let playlist = Playlist::from_paths(&[dir.join("*").to_string_lossy().to_string()]).unwrap();
+        assert_eq!(names(&playlist), vec!["1.jpg", "1.png", "10.jpg", "2.jpg"]);
+
+        let playlist = Playlist::from_paths(&[dir.join("?.jpg").to_string_lossy().to_string()]).unwrap();
+        assert_eq!(names(&playlist), vec!["1.jpg", "2.jpg"]);
+
+        assert!(Playlist::from_paths(&[dir.join("*.gif").to_string_lossy().to_string()]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sources_are_kept_in_order() {
+        let dir = scratch_dir("sources", &["b.jpg", "a.jpg"]);
+        fs::write(dir.join("list.m3u"), "# tour\nb.jpg\n\n  a.jpg  \n").unwrap();
+        let playlist = Playlist::from_paths(&[
+            "missing.jpg".to_string(),
+            dir.join("list.m3u").to_string_lossy().to_string(),
+            dir.to_string_lossy().to_string(),
+        ]).unwrap();
+        assert_eq!(names(&playlist), vec!["missing.jpg", "b.jpg", "a.jpg", "a.jpg", "b.jpg"]);
+        // relative to the playlist file
+        assert_eq!(playlist.entries()[1], dir.join("b.jpg"));
+
+        assert!(Playlist::from_paths(&[]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn next_and_previous_wrap_around() {
+        let mut playlist = Playlist::from_paths(&["a.jpg".to_string(), "b.jpg".to_string(), "c.jpg".to_string()]).unwrap();
+        playlist.previous();
+        assert_eq!(playlist.current(), Path::new("c.jpg"));
+        playlist.next();
+        assert_eq!(playlist.current(), Path::new("a.jpg"));
+        playlist.next();
+        assert_eq!(playlist.title("Cosmos360"), "Cosmos360 - b.jpg (2/3)");
+        assert_eq!(playlist.neighbours(), vec![PathBuf::from("c.jpg"), PathBuf::from("a.jpg")]);
+
+        let mut single = Playlist::from_paths(&["a.jpg".to_string()]).unwrap();
+        single.next();
+        single.previous();
+        assert_eq!(single.current(), Path::new("a.jpg"));
+        assert!(single.neighbours().is_empty());
+    }
+
+    #[test]
+    fn selected_image_is_inserted_after_the_current_one() {
+        let mut playlist = Playlist::from_paths(&["a.jpg".to_string(), "b.jpg".to_string()]).unwrap();
+        playlist.select(Path::new("new.jpg"));
+        assert_eq!(names(&playlist), vec!["a.jpg", "new.jpg", "b.jpg"]);
+        assert_eq!(playlist.index(), 1);
+        playlist.select(Path::new("b.jpg"));
+        assert_eq!(playlist.index(), 2);
+        assert_eq!(playlist.len(), 3);
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hotspots::{Hotspot, Icon};
+use crate::playlist::same_file;
+use crate::view::View;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How the viewer goes from a scene to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SceneTransition {
+    Cut,
+    #[default]
+    CrossFade,
+    /// The previous scene grows towards the link while it fades out, and the next one
+    /// zooms out from its arrival view.
+    ZoomBlend,
+}
+
+/// Way from a scene to another, drawn as a hotspot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    /// Id of the scene it leads to.
+    pub to: String,
+    pub azimuth: f32,
+    pub elevation: f32,
+    /// Label of the hotspot, the id of the target scene by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// View on arrival, without it the user keeps their heading.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<View>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub id: String,
+    /// Panorama of the scene, relative to the project file.
+    pub image: PathBuf,
+    /// Azimuth of the north in this panorama, so that walking between scenes keeps the
+    /// heading of the user. Overrides `north` of the viewer configuration for the compass.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub north: Option<f32>,
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+/// Connected panoramas of a virtual tour, opened with `--project` :
+///
+/// ```toml
+/// start = "hall"
+/// transition = "zoom_blend"
+/// transition_duration = 0.8
+///
+/// [[scenes]]
+/// id = "hall"
+/// image = "hall.jpg"
+/// north = 90.0
+///
+/// [[scenes.links]]
+/// to = "kitchen"
+/// azimuth = 42.0
+/// elevation = -5.0
+///
+/// [[scenes]]
+/// id = "kitchen"
+/// image = "kitchen.jpg"
+///
+/// [[scenes.links]]
+/// to = "hall"
+/// azimuth = 220.0
+/// elevation = -8.0
+/// title = "Back to the hall"
+/// arrival = { azimuth = 180.0, elevation = 0.0, fov = 90.0 }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    /// Id of the first scene, the first one of the list by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub transition: SceneTransition,
+    /// Seconds of the transition between scenes.
+    #[serde(default = "default_transition_duration")]
+    pub transition_duration: f32,
+    pub scenes: Vec<Scene>,
+}
+
+fn default_transition_duration() -> f32 {
+    1.0
+}
+
+impl Project {
+
+    /// Read a project, with the image paths made relative to the working directory.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        let mut project: Project = toml::from_str(&content).map_err(|why| format!("{}: {why}", path.display()))?;
+        project.validate().map_err(|why| format!("{}: {why}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for scene in project.scenes.iter_mut() {
+            scene.image = base.join(&scene.image);
+            if !scene.image.is_file() {
+                return Err(format!("{}: missing image \"{}\" of scene \"{}\"", path.display(), scene.image.display(), scene.id));
+            }
+        }
+        Ok(project)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.scenes.is_empty() {
+            return Err("no scenes".to_string());
+        }
+        if self.transition_duration < 0.0 {
+            return Err("negative transition_duration".to_string());
+        }
+        let mut ids = HashSet::new();
+        if let Some(scene) = self.scenes.iter().find(|scene| !ids.insert(scene.id.as_str())) {
+            return Err(format!("duplicate scene \"{}\"", scene.id));
+        }
+        if let Some(start) = self.start.as_ref().filter(|start| !ids.contains(start.as_str())) {
+            return Err(format!("unknown start scene \"{start}\""));
+        }
+        for scene in &self.scenes {
+            if let Some(link) = scene.links.iter().find(|link| !ids.contains(link.to.as_str())) {
+                return Err(format!("scene \"{}\" links to the unknown scene \"{}\"", scene.id, link.to));
+            }
+        }
+        Ok(())
+    }
+
+    /// Images of the scenes, in the order of the file.
+    pub fn images(&self) -> Vec<PathBuf> {
+        self.scenes.iter().map(|scene| scene.image.clone()).collect()
+    }
+
+    pub fn start_index(&self) -> usize {
+        self.start.as_ref()
+            .and_then(|start| self.scenes.iter().position(|scene| &scene.id == start))
+            .unwrap_or(0)
+    }
+
+    fn scene(&self, id: &str) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| scene.id == id)
+    }
+
+    fn scene_of(&self, image: &Path) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| same_file(&scene.image, image))
+    }
+
+    /// Azimuth of the north in the scene of `image`, if the project gives it.
+    pub fn north(&self, image: &Path) -> Option<f32> {
+        self.scene_of(image)?.north
+    }
+
+    /// Links of the scene of `image` as hotspots, whose targets are the linked images.
+    pub fn link_hotspots(&self, image: &Path) -> Vec<Hotspot> {
+        let Some(scene) = self.scene_of(image) else {
+            return Vec::new();
+        };
+        scene.links.iter()
+            .filter_map(|link| {
+                let target = self.scene(&link.to)?;
+                Some(Hotspot {
+                    azimuth: link.azimuth,
+                    elevation: link.elevation,
+                    title: link.title.clone().unwrap_or(target.id.clone()),
+                    description: String::new(),
+                    icon: Icon::Link,
+                    target: Some(target.image.clone()),
+                    target_view: link.arrival,
+                })
+            })
+            .collect()
+    }
+
+    /// Images the scene of `image` leads to, worth preloading.
+    pub fn linked_images(&self, image: &Path) -> Vec<PathBuf> {
+        self.link_hotspots(image).into_iter().filter_map(|hotspot| hotspot.target).collect()
+    }
+
+    /// `view` turned so that the user faces the same direction of the world in the scene
+    /// of `to` as in the scene of `from`, `None` if one of them is not in the project.
+    pub fn keep_heading(&self, from: &Path, to: &Path, view: View) -> Option<View> {
+        let (from, to) = (self.scene_of(from)?, self.scene_of(to)?);
+        Some(View {
+            azimuth: (view.azimuth - from.north.unwrap_or(0.0) + to.north.unwrap_or(0.0)).rem_euclid(360.0),
+            ..view
+        })
+    }
+}
@@ -0,0 +1,224 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::PolarCoordinate;
+use crate::sidecar;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a region is drawn, see `TINT` in the shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionEffect {
+    Tint,
+    Outline,
+    /// Hides faces or licence plates, in the live view and the exported images.
+    #[default]
+    Blur,
+    /// Filled with the color of the region.
+    Mask,
+}
+
+impl RegionEffect {
+    pub fn next(self) -> Self {
+        match self {
+            RegionEffect::Tint => RegionEffect::Outline,
+            RegionEffect::Outline => RegionEffect::Blur,
+            RegionEffect::Blur => RegionEffect::Mask,
+            RegionEffect::Mask => RegionEffect::Tint,
+        }
+    }
+
+    /// Tints and outlines only highlight, they can be hidden.
+    pub fn is_highlight(self) -> bool {
+        matches!(self, RegionEffect::Tint | RegionEffect::Outline)
+    }
+}
+
+fn default_color() -> [f32; 4] {
+    [1.0, 0.3, 0.2, 0.4]
+}
+
+fn default_blur_radius() -> f32 {
+    2.0
+}
+
+/// Spherical polygon of a panorama, its edges follow great circles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    #[serde(default)]
+    pub effect: RegionEffect,
+    /// RGBA in [0;1], the alpha is the opacity of the tint.
+    #[serde(default = "default_color")]
+    pub color: [f32; 4],
+    /// Degrees of the blur.
+    #[serde(default = "default_blur_radius")]
+    pub blur_radius: f32,
+    /// Vertices as [azimuth, elevation] in degrees.
+    pub points: Vec<[f32; 2]>,
+}
+
+impl Region {
+    pub fn directions(&self) -> Vec<Vector3<f32>> {
+        self.points.iter().map(|[azimuth, elevation]| PolarCoordinate::direction(*azimuth, *elevation)).collect()
+    }
+
+    pub fn contains(&self, direction: Vector3<f32>) -> bool {
+        polygon_contains(&self.directions(), direction)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegionFile {
+    regions: Vec<Region>,
+}
+
+/// The regions of a panorama, stored next to it in "pano.regions.json" :
+///
+/// ```json
+/// {
+///   "regions": [
+///     {
+///       "name": "Licence plate",
+///       "effect": "blur",
+///       "blur_radius": 3.0,
+///       "points": [[12.0, -20.0], [18.0, -20.0], [18.0, -23.0], [12.0, -23.0]]
+///     }
+///   ]
+/// }
+/// ```
+pub struct Regions {
+    path: PathBuf,
+    regions: Vec<Region>,
+}
+
+impl Regions {
+
+    pub fn empty(image: &Path) -> Self {
+        Self {
+            path: sidecar::regions_path(image),
+            regions: Vec::new(),
+        }
+    }
+
+    /// Read the regions of `image`, none if it has no region file.
+    pub fn load(image: &Path) -> Result<Self, String> {
+        let mut regions = Self::empty(image);
+        if !regions.path.exists() {
+            return Ok(regions);
+        }
+        let path = &regions.path;
+        let content = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        let file: RegionFile = serde_json::from_str(&content).map_err(|why| format!("{}: {why}", path.display()))?;
+        if let Some(region) = file.regions.iter().find(|region| region.points.len() < 3) {
+            return Err(format!("{}: region \"{}\" has less than 3 points", path.display(), region.name));
+        }
+        regions.regions = file.regions;
+        Ok(regions)
+    }
+
+    /// Write the regions, the file is removed once the last one is deleted.
+    fn save(&self) -> Result<(), String> {
+        let path = &self.path;
+        if self.regions.is_empty() {
+            return match path.exists() {
+                true => fs::remove_file(path).map_err(|why| format!("{}: {why}", path.display())),
+                false => Ok(()),
+            };
+        }
+        let file = RegionFile { regions: self.regions.clone() };
+        let content = serde_json::to_string_pretty(&file).map_err(|why| why.to_string())?;
+        fs::write(path, content + "\n").map_err(|why| format!("{}: {why}", path.display()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn all(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Add a region with the default effect and return its index.
+    pub fn add(&mut self, points: Vec<[f32; 2]>) -> Result<usize, String> {
+        if points.len() < 3 {
+            return Err("a region needs at least 3 points".to_string());
+        }
+        self.regions.push(Region {
+            name: format!("Region {}", self.regions.len() + 1),
+            effect: RegionEffect::default(),
+            color: default_color(),
+            blur_radius: default_blur_radius(),
+            points,
+        });
+        self.save()?;
+        Ok(self.regions.len() - 1)
+    }
+
+    /// Switch the region to its next effect and return it.
+    pub fn cycle_effect(&mut self, index: usize) -> Result<&Region, String> {
+        let region = self.regions.get_mut(index).ok_or(format!("No region {index}"))?;
+        region.effect = region.effect.next();
+        self.save()?;
+        Ok(&self.regions[index])
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<Region, String> {
+        if index >= self.regions.len() {
+            return Err(format!("No region {index}"));
+        }
+        let region = self.regions.remove(index);
+        self.save()?;
+        Ok(region)
+    }
+}
+
+/// Index of the last region containing a world direction, drawn over the other ones.
+pub fn pick(regions: &[Region], direction: Vector3<f32>) -> Option<usize> {
+    regions.iter().rposition(|region| region.contains(direction))
+}
+
+/// Whether a spherical polygon winds around `direction`, by summing the angles its edges
+/// span around it. Polygons wind around the antipode of their inside as well, which is
+/// told apart by the side of their center, so they must be smaller than a hemisphere.
+/// Same test as `region_contains` in the shader.
+pub fn polygon_contains(polygon: &[Vector3<f32>], direction: Vector3<f32>) -> bool {
+    let p = direction.normalize();
+    let total: f32 = polygon.iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| p.dot(a.cross(*b)).atan2(a.dot(*b) - a.dot(p) * b.dot(p)))
+        .sum();
+    let center: Vector3<f32> = polygon.iter().sum();
+    total.abs() > std::f32::consts::PI && center.dot(p) > 0.0
+}
+
+/// A region as the shader reads it, see `Region` in the shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RegionUniform {
+    color: [f32; 4],
+    start: u32,
+    count: u32,
+    effect: u32,
+    blur_radius: f32,
+}
+
+/// The regions and their vertices for the shader, without the highlights if they are
+/// hidden.
+pub fn shader_data(regions: &[Region], highlight: bool) -> (Vec<RegionUniform>, Vec<[f32; 4]>) {
+    let mut uniforms = Vec::new();
+    let mut points = Vec::new();
+    for region in regions.iter().filter(|region| highlight || !region.effect.is_highlight()) {
+        uniforms.push(RegionUniform {
+            color: region.color,
+            start: points.len() as u32,
+            count: region.points.len() as u32,
+            effect: region.effect as u32,
+            blur_radius: region.blur_radius,
+        });
+        points.extend(region.directions().iter().map(|direction| -> [f32; 4] { direction.normalize().extend(0.0).into() }));
+    }
+    (uniforms, points)
+}
@@ -0,0 +1,241 @@
+use wgpu::util::DeviceExt;
+use wgpu::{Device, Queue};
+
+use crate::camera::Easing;
+use crate::project::SceneTransition;
+
+use std::time::Instant;
+
+// Growth of the previous scene at the end of a zoom blend
+const ZOOM_FACTOR: f32 = 3.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FadeVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    alpha: f32,
+}
+
+impl FadeVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+struct Snapshot {
+    // kept alive for the bind group
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Transition between two scenes : a snapshot of the last frame of the previous scene,
+/// drawn over the next one until it has faded out.
+pub struct SceneFade {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    vertex_buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    snapshot: Option<Snapshot>,
+    transition: SceneTransition,
+    started: Instant,
+    duration: f32,
+    // point the zoom blend grows towards, in normalized device coordinates
+    focus: [f32; 2],
+}
+
+impl SceneFade {
+
+    pub fn new(device: &Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("snapshot_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fade Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Fade Shader"),
+            source: wgpu::ShaderSource::Wgsl(crate::shaders::FADE_SHADER.into()),
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fade Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[FadeVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fade Vertex Buffer"),
+            contents: bytemuck::cast_slice(&[FadeVertex { position: [0.0; 2], tex_coords: [0.0; 2], alpha: 0.0 }; 6]),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            render_pipeline,
+            bind_group_layout,
+            sampler,
+            vertex_buffer,
+            format,
+            snapshot: None,
+            transition: SceneTransition::Cut,
+            started: Instant::now(),
+            duration: 0.0,
+            focus: [0.0, 0.0],
+        }
+    }
+
+    /// Start a transition and return the texture the previous scene must be drawn into.
+    /// `focus` is in physical pixels.
+    pub fn begin(
+        &mut self,
+        device: &Device,
+        size: (u32, u32),
+        transition: SceneTransition,
+        duration: f32,
+        focus: (f32, f32),
+    ) -> Option<wgpu::TextureView> {
+        self.snapshot = None;
+        if transition == SceneTransition::Cut || duration <= 0.0 {
+            return None;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("snapshot_texture"),
+            size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("snapshot_bind_group"),
+        });
+
+        self.snapshot = Some(Snapshot { _texture: texture, bind_group });
+        self.transition = transition;
+        self.started = Instant::now();
+        self.duration = duration;
+        self.focus = [focus.0 / size.0 as f32 * 2.0 - 1.0, 1.0 - focus.1 / size.1 as f32 * 2.0];
+        Some(view)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Draw the previous scene over `view`, until the transition is over.
+    pub fn draw(&mut self, queue: &Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let progress = self.started.elapsed().as_secs_f32() / self.duration;
+        if progress >= 1.0 {
+            self.snapshot = None;
+        }
+        let Some(snapshot) = self.snapshot.as_ref() else {
+            return;
+        };
+
+        let alpha = 1.0 - Easing::EaseInOut.apply(progress);
+        let scale = match self.transition {
+            SceneTransition::ZoomBlend => 1.0 + (ZOOM_FACTOR - 1.0) * Easing::EaseIn.apply(progress),
+            _ => 1.0,
+        };
+        let corner = |x: f32, y: f32| FadeVertex {
+            position: [self.focus[0] + (x - self.focus[0]) * scale, self.focus[1] + (y - self.focus[1]) * scale],
+            tex_coords: [(x + 1.0) / 2.0, (1.0 - y) / 2.0],
+            alpha,
+        };
+        let vertices = [
+            corner(-1.0, 1.0), corner(-1.0, -1.0), corner(1.0, 1.0),
+            corner(1.0, 1.0), corner(-1.0, -1.0), corner(1.0, -1.0),
+        ];
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Fade Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &snapshot.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..6, 0..1);
+    }
+}
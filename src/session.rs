@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::view::View;
+use crate::window_settings::WindowGeometry;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// What was on screen when the viewer exited, restored at the next launch unless
+/// `--no-session` is given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// The playlist and the position in it.
+    pub images: Vec<PathBuf>,
+    pub index: usize,
+    /// View of the current image.
+    pub view: Option<View>,
+    pub window: Option<WindowGeometry>,
+}
+
+impl Session {
+
+    pub fn path() -> Option<PathBuf> {
+        Some(config::config_dir()?.join("session.toml"))
+    }
+
+    /// The saved session, if there is a readable one.
+    pub fn load() -> Option<Self> {
+        let path = Self::path()?;
+        let content = fs::read_to_string(&path).ok()?;
+        match toml::from_str(&content) {
+            Ok(session) => Some(session),
+            Err(why) => {
+                println!("Ignoring invalid session file \"{}\" : {why}", path.display());
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+
+        let result = toml::to_string(self).map_err(|why| why.to_string())
+            .and_then(|content| {
+                fs::create_dir_all(path.parent().unwrap()).map_err(|why| why.to_string())?;
+                fs::write(&path, content).map_err(|why| why.to_string())
+            });
+        if let Err(why) = result {
+            println!("Unable to save the session : {why}");
+        }
+    }
+}
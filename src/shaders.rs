@@ -0,0 +1,22 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The panorama shader built into the binary.
+pub const SHADER: &str = include_str!("../shaders/shader.wgsl");
+/// The text overlay shader, not reloaded from the shader directory.
+pub const HUD_SHADER: &str = include_str!("../shaders/hud.wgsl");
+/// The scene transition shader, not reloaded from the shader directory either.
+pub const FADE_SHADER: &str = include_str!("../shaders/fade.wgsl");
+/// The minimap shader, not reloaded from the shader directory either.
+pub const MINIMAP_SHADER: &str = include_str!("../shaders/minimap.wgsl");
+const SHADER_FILE: &str = "shader.wgsl";
+
+/// Path of the panorama shader in a development shader directory.
+pub fn shader_path(dir: &Path) -> PathBuf {
+    dir.join(SHADER_FILE)
+}
+
+pub fn read_shader(dir: &Path) -> Result<String, String> {
+    let path = shader_path(dir);
+    fs::read_to_string(&path).map_err(|why| format!("{}: {why}", path.display()))
+}
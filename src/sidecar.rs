@@ -0,0 +1,177 @@
+use toml_edit::{Decor, DocumentMut, Item, Table};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Metadata file stored next to a panorama, "pano.jpg" has "pano.toml".
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    image.with_extension("toml")
+}
+
+/// Hotspot file of a panorama, "pano.jpg" has "pano.hotspots.json".
+pub fn hotspots_path(image: &Path) -> PathBuf {
+    image.with_extension("hotspots.json")
+}
+
+/// Region file of a panorama, "pano.jpg" has "pano.regions.json".
+pub fn regions_path(image: &Path) -> PathBuf {
+    image.with_extension("regions.json")
+}
+
+/// Panorama with its patches and regions baked in, "pano.jpg" has "pano.baked.png".
+pub fn baked_path(image: &Path) -> PathBuf {
+    image.with_extension("baked.png")
+}
+
+/// Export of the measurements of a panorama, "pano.jpg" has "pano.measurements.csv".
+pub fn measurements_path(image: &Path, extension: &str) -> PathBuf {
+    image.with_extension(format!("measurements.{extension}"))
+}
+
+/// Content of the sidecar file of `image`, empty if there is none.
+pub fn read(image: &Path) -> Result<toml::Table, String> {
+    let path = sidecar_path(image);
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|why| format!("{}: {why}", path.display()))?;
+    content.parse::<toml::Table>().map_err(|why| format!("{}: {why}", path.display()))
+}
+
+/// Replace the `key` section of the sidecar file of `image`, the other ones are kept as
+/// written, with their comments.
+pub fn write_section(image: &Path, key: &str, value: toml::Value) -> Result<(), String> {
+    let path = sidecar_path(image);
+    let mut document = match path.exists() {
+        true => {
+            let content = fs::read_to_string(&path).map_err(|why| format!("{}: {why}", path.display()))?;
+            content.parse::<DocumentMut>().map_err(|why| format!("{}: {why}", path.display()))?
+        }
+        false => DocumentMut::new(),
+    };
+
+    let mut section = toml::Table::new();
+    section.insert(key.to_string(), value);
+    let content = toml::to_string_pretty(&section).map_err(|why| why.to_string())?;
+    let mut section = content.parse::<DocumentMut>().map_err(|why| why.to_string())?;
+    let mut item = section.remove(key).ok_or_else(|| format!("No {key} section to write"))?;
+
+    // the new tables take the place of the old ones, with the comments above them, or go
+    // after everything else
+    let old = document.get(key);
+    let position = old.and_then(|old| positions(old).into_iter().min())
+        .unwrap_or_else(|| positions(document.as_item()).into_iter().max().map_or(0, |last| last + 1));
+    let decor = match old.and_then(first_table) {
+        Some(table) => Some(table.decor().clone()),
+        None if !document.is_empty() => Some(Decor::new("\n", "")),
+        None => None,
+    };
+    set_position(&mut item, position);
+    if let (Some(table), Some(decor)) = (first_table_mut(&mut item), decor) {
+        *table.decor_mut() = decor;
+    }
+    document.insert(key, item);
+
+    fs::write(&path, document.to_string()).map_err(|why| format!("{}: {why}", path.display()))
+}
+
+// Where the tables of `item` are written in the file
+fn positions(item: &Item) -> Vec<usize> {
+    let tables: Vec<&Table> = match item {
+        Item::Table(table) => vec![table],
+        Item::ArrayOfTables(array) => array.iter().collect(),
+        _ => Vec::new(),
+    };
+    tables.into_iter()
+        .flat_map(|table| table.position().into_iter().chain(table.iter().flat_map(|(_, item)| positions(item))))
+        .collect()
+}
+
+fn set_position(item: &mut Item, position: usize) {
+    let tables: Vec<&mut Table> = match item {
+        Item::Table(table) => vec![table],
+        Item::ArrayOfTables(array) => array.iter_mut().collect(),
+        _ => Vec::new(),
+    };
+    for table in tables {
+        table.set_position(position);
+        table.iter_mut().for_each(|(_, item)| set_position(item, position));
+    }
+}
+
+fn first_table(item: &Item) -> Option<&Table> {
+    match item {
+        Item::Table(table) => Some(table),
+        Item::ArrayOfTables(array) => array.get(0),
+        _ => None,
+    }
+}
+
+fn first_table_mut(item: &mut Item) -> Option<&mut Table> {
+    match item {
+        Item::Table(table) => Some(table),
+        Item::ArrayOfTables(array) => array.get_mut(0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a panorama in a fresh directory of the temporary directory, with `sidecar` next to it
+    fn scratch_image(name: &str, sidecar: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cosmos360-sidecar-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("pano.jpg");
+        if let Some(sidecar) = sidecar {
+            fs::write(sidecar_path(&image), sidecar).unwrap();
+        }
+        image
+    }
+
+    fn bookmarks(names: &[&str]) -> toml::Value {
+        let bookmarks: Vec<toml::Table> = names.iter()
+            .map(|name| toml::Table::from_iter([("name".to_string(), toml::Value::from(*name))]))
+            .collect();
+        toml::Value::try_from(bookmarks).unwrap()
+    }
+
+    #[test]
+    fn other_sections_are_kept_as_written() {
+        let before = "# my settings\n[config]\nfov = 70.0   # wide\n\n# where to look\n[[bookmarks]]\nname = \"old\"\n\n[[bookmarks]]\nname = \"older\"\n\n[notes]\n# keep me\ntext = 'sunset'\n";
+        let image = scratch_image("replace", Some(before));
+        write_section(&image, "bookmarks", bookmarks(&["door", "tower"])).unwrap();
+
+        let after = fs::read_to_string(sidecar_path(&image)).unwrap();
+        assert!(after.starts_with("# my settings\n[config]\nfov = 70.0   # wide\n\n# where to look\n[[bookmarks]]\nname = \"door\"\n"), "{after}");
+        assert!(after.ends_with("[notes]\n# keep me\ntext = 'sunset'\n"), "{after}");
+        assert!(!after.contains("old"), "{after}");
+
+        let table = read(&image).unwrap();
+        assert_eq!(table["bookmarks"], bookmarks(&["door", "tower"]));
+        assert_eq!(table["config"]["fov"].as_float(), Some(70.0));
+        fs::remove_dir_all(image.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn new_section_goes_last() {
+        let image = scratch_image("append", Some("# my settings\n[config]\nfov = 70.0\n"));
+        write_section(&image, "bookmarks", bookmarks(&["door"])).unwrap();
+        let after = fs::read_to_string(sidecar_path(&image)).unwrap();
+        assert_eq!(after, "# my settings\n[config]\nfov = 70.0\n\n[[bookmarks]]\nname = \"door\"\n");
+        fs::remove_dir_all(image.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_sidecar_is_created() {
+        let image = scratch_image("create", None);
+        write_section(&image, "bookmarks", bookmarks(&["door"])).unwrap();
+        assert_eq!(read(&image).unwrap()["bookmarks"], bookmarks(&["door"]));
+
+        fs::write(sidecar_path(&image), "[config\n").unwrap();
+        assert!(write_section(&image, "bookmarks", bookmarks(&["door"])).is_err());
+        fs::remove_dir_all(image.parent().unwrap()).unwrap();
+    }
+}
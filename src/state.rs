@@ -1,11 +1,29 @@
 use futures::executor;
 use crate::image_data::ImageData;
-use crate::camera::Camera;
+use crate::camera::{Camera, Easing, OrientationMode, PolarCoordinate};
 use crate::camera::CameraUniform;
 use crate::camera::CameraSettingsBuffer;
 use crate::camera_controller::CameraController;
+use crate::comparison::Comparison;
+use crate::extra_window::ExtraWindow;
+use crate::gamepad::GamepadEvent;
+use crate::hotspots::{self, Hotspot};
+use crate::hud::{Annotations, Hud, Label, Marker, Polyline};
+use crate::overlays::{self, OverlayUniform};
+use crate::patches::{self, PatchTexture};
+use crate::measurement::{self, Measurement};
+use crate::minimap::Minimap;
+use crate::regions::{self, Region, RegionUniform};
+use crate::key_bindings::{Action, KeyBindings};
+use crate::project::SceneTransition;
+use crate::scene_fade::SceneFade;
+use crate::config::{ComparisonMode, Config, MinimapMode, OverlayConfig, PatchConfig};
+use crate::shaders;
 use crate::texture::Texture;
+use crate::view::View;
+use crate::window_settings::ExtraWindowSettings;
 
+use cgmath::Vector3;
 use winit::window::Window;
 use winit::event::WindowEvent;
 use winit::dpi::PhysicalSize;
@@ -19,10 +37,11 @@ use wgpu::{
     SurfaceCapabilities,
 };
 use wgpu::util::DeviceExt;
+use image::RgbaImage;
 
 use std::sync::Arc;
-use std::fs::File;
-use std::io::prelude::*;
+use std::sync::mpsc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -63,16 +82,25 @@ const VERTICES: &[Vertex] = &[
 ];
 
 pub struct State {
+    // kept to create the surfaces of the extra windows
+    instance: Instance,
+    adapter: Adapter,
     surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     render_pipeline: wgpu::RenderPipeline,
+    render_pipeline_layout: wgpu::PipelineLayout,
     window: Arc<Window>,
     vertex_buffer: wgpu::Buffer,
     num_vertices: u32,
     diffuse_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    diffuse_texture: Texture,
+    // second panorama of the comparison, a blank pixel without it
+    compare_texture: Texture,
+    comparison: Comparison,
     camera: Camera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
@@ -81,19 +109,48 @@ pub struct State {
     settings_buffer: wgpu::Buffer,
     settings_bind_group: wgpu::BindGroup,
     camera_controller: CameraController,
+    clear_color: wgpu::Color,
+    last_update: Instant,
+    animation_paused: bool,
+    hud: Hud,
+    image_name: String,
+    image_size: (u32, u32),
+    hotspots: Vec<Hotspot>,
+    hovered_hotspot: Option<usize>,
+    hotspot_editing: bool,
+    scene_fade: SceneFade,
+    overlays: OverlayConfig,
+    overlay_buffer: wgpu::Buffer,
+    overlay_bind_group: wgpu::BindGroup,
+    overlay_bind_group_layout: wgpu::BindGroupLayout,
+    // kept alive for the overlay bind group
+    region_buffers: [wgpu::Buffer; 2],
+    regions: Vec<Region>,
+    region_count: u32,
+    // tinted and outlined regions are shown
+    region_highlights: bool,
+    patch_texture: PatchTexture,
+    // nadir then zenith
+    patches: [PatchConfig; 2],
+    patch_loaded: [bool; 2],
+    // azimuth of the north in the panorama
+    north: f32,
+    minimap: Minimap,
+    measurement: Measurement,
+    measuring: bool,
 }
 
 impl State {
-    pub fn new(window: Window) -> Self {
+    pub fn new(window: Window, image: &ImageData, viewer_config: &Config) -> Self {
 
         let window_arc = Arc::new(window);
         let size = window_arc.inner_size();
         let instance = Self::create_gpu_instance();
         let surface = instance.create_surface(window_arc.clone()).unwrap();
-        let adapter = Self::create_adapter(instance, &surface);
+        let adapter = Self::create_adapter(&instance, &surface, viewer_config.render.power_preference.into());
         let (device, queue) = Self::create_device(&adapter);
         let surface_caps = surface.get_capabilities(&adapter);
-        let config = Self::create_surface_config(size, surface_caps);
+        let config = Self::create_surface_config(size, surface_caps, viewer_config.render.present_mode.into());
         
         surface.configure(&device, &config);
         
@@ -109,16 +166,26 @@ impl State {
             settings_bind_group_layout
             ) = Self::create_camera(size, &device);
         
-        let (texture_bind_group_layout, diffuse_bind_group) = Self::create_texture(&device, &queue);
+        let diffuse_texture = Texture::from_image(image, &device, &queue);
+        let image_size = (diffuse_texture.texture.width(), diffuse_texture.texture.height());
+        let compare_texture = Texture::blank(&device, &queue);
+        let (texture_bind_group_layout, diffuse_bind_group) = Self::create_texture_layout(&device, &diffuse_texture, &compare_texture);
+        let patch_texture = PatchTexture::new(&device);
+        let (overlay_buffer, region_buffers, overlay_bind_group, overlay_bind_group_layout) = Self::create_overlays(&device, &patch_texture);
         
-        let shader = Self::create_shader(&device);
-        let render_pipeline = Self::create_render_pipeline(
+        let render_pipeline_layout = Self::create_render_pipeline_layout(
             &device, 
-            &shader, 
-            &config, 
             &texture_bind_group_layout,
             &camera_bind_group_layout,
             &settings_bind_group_layout,
+            &overlay_bind_group_layout,
+        );
+        let shader = Self::create_shader(&device, shaders::SHADER);
+        let render_pipeline = Self::create_render_pipeline(
+            &device, 
+            &shader, 
+            &config, 
+            &render_pipeline_layout,
         );
 
         let vertex_buffer = device.create_buffer_init(
@@ -130,11 +197,20 @@ impl State {
         );
 
         let num_vertices = VERTICES.len() as u32;
+        let hud = Hud::new(&device, &queue, config.format, &viewer_config.hud);
+        let scene_fade = SceneFade::new(&device, config.format);
+        let minimap = Minimap::new(&device, config.format, &texture_bind_group_layout, &viewer_config.minimap);
 
-        let camera_controller = CameraController::new(2.0, 0.05);
+        let camera_controller = CameraController::new(
+            viewer_config.camera.speed,
+            viewer_config.camera.mouse_sensibility,
+            KeyBindings::load(),
+        );
 
         // Return
-        Self {
+        let mut state = Self {
+            instance,
+            adapter,
             window: window_arc,
             surface,
             device,
@@ -142,9 +218,14 @@ impl State {
             config,
             size,
             render_pipeline,
+            render_pipeline_layout,
             vertex_buffer,
             num_vertices,
             diffuse_bind_group,
+            texture_bind_group_layout,
+            diffuse_texture,
+            compare_texture,
+            comparison: Comparison::new(&viewer_config.comparison),
             camera,
             camera_uniform,
             camera_buffer,
@@ -153,12 +234,58 @@ impl State {
             settings_buffer,
             settings_bind_group,
             camera_controller,
-        }
+            clear_color: wgpu::Color::BLACK,
+            last_update: Instant::now(),
+            animation_paused: false,
+            hud,
+            image_name: String::new(),
+            image_size,
+            hotspots: Vec::new(),
+            hovered_hotspot: None,
+            hotspot_editing: false,
+            scene_fade,
+            overlays: viewer_config.overlays.clone(),
+            overlay_buffer,
+            overlay_bind_group,
+            overlay_bind_group_layout,
+            region_buffers,
+            regions: Vec::new(),
+            region_count: 0,
+            region_highlights: true,
+            patch_texture,
+            patches: [viewer_config.nadir.clone(), viewer_config.zenith.clone()],
+            patch_loaded: [false; 2],
+            north: viewer_config.viewer.north,
+            minimap,
+            measurement: Measurement::default(),
+            measuring: false,
+        };
+        state.apply_config(viewer_config);
+        state.reset_view();
+        state
+    }
+
+    /// Apply the settings which can change from a panorama to another.
+    pub fn apply_config(&mut self, viewer_config: &Config) {
+        let [r, g, b, a] = viewer_config.render.clear_color;
+        self.clear_color = wgpu::Color { r, g, b, a };
+        self.camera.set_mode(viewer_config.camera.orientation_mode);
+        self.camera.set_radial_max_range(viewer_config.camera.radial_max_range);
+        self.settings.set_projection(viewer_config.camera.projection);
+        self.camera_controller.apply_config(&viewer_config.camera, &viewer_config.gamepad, &viewer_config.attract);
+        self.hud.apply_config(&viewer_config.hud);
+        self.overlays = viewer_config.overlays.clone();
+        self.north = viewer_config.viewer.north;
+        self.minimap.apply_config(&viewer_config.minimap);
+        self.patches = [viewer_config.nadir.clone(), viewer_config.zenith.clone()];
+        self.comparison.apply_config(&viewer_config.comparison);
+        self.window.request_redraw();
     }
 
     fn create_surface_config(
         size: PhysicalSize<u32>,
         capabilities: SurfaceCapabilities,
+        present_mode: PresentMode,
     ) -> wgpu::SurfaceConfiguration {
         let surface_format = capabilities
             .formats
@@ -172,7 +299,7 @@ impl State {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: PresentMode::AutoNoVsync,
+            present_mode,
             alpha_mode: capabilities.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
@@ -185,7 +312,7 @@ impl State {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
+                    required_limits: adapter.limits(),
                     label: None,
                     memory_hints: wgpu::MemoryHints::Performance,
                 },
@@ -194,11 +321,11 @@ impl State {
         ).unwrap()
     }
 
-    fn create_adapter(instance: Instance, surface: &Surface) -> Adapter {
+    fn create_adapter(instance: &Instance, surface: &Surface, power_preference: wgpu::PowerPreference) -> Adapter {
         executor::block_on(
             instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference,
                 compatible_surface: Some(surface),
                 force_fallback_adapter: false,
             })
@@ -212,42 +339,58 @@ impl State {
         })
     }
 
-    fn create_shader(device: &Device) -> wgpu::ShaderModule {
-        let mut file = File::open("./shaders/shader.wgsl").unwrap();
-        let mut shader_str = String::new();
-        file.read_to_string(&mut shader_str).unwrap();
-
+    fn create_shader(device: &Device, source: &str) -> wgpu::ShaderModule {
         device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            //source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shader.wgsl").into()),
-            //source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/panoramic_shader.wgsl").into()),
-            source: wgpu::ShaderSource::Wgsl(shader_str.into()),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
         })
     }
 
-    fn create_render_pipeline(
+    /// Rebuild the render pipeline with a new shader, the current pipeline is kept if the
+    /// shader does not compile.
+    pub fn reload_shader(&mut self, source: &str) -> Result<(), String> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = Self::create_shader(&self.device, source);
+        let render_pipeline = Self::create_render_pipeline(&self.device, &shader, &self.config, &self.render_pipeline_layout);
+        if let Some(error) = executor::block_on(self.device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+
+        self.render_pipeline = render_pipeline;
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    fn create_render_pipeline_layout(
         device: &Device, 
-        shader: &wgpu::ShaderModule, 
-        config: &wgpu::SurfaceConfiguration,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
         settings_bind_group_layout: &wgpu::BindGroupLayout,
-        ) -> wgpu::RenderPipeline {
+        overlay_bind_group_layout: &wgpu::BindGroupLayout,
+        ) -> wgpu::PipelineLayout {
 
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    texture_bind_group_layout,
-                    camera_bind_group_layout,
-                    settings_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[
+                texture_bind_group_layout,
+                camera_bind_group_layout,
+                settings_bind_group_layout,
+                overlay_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &Device, 
+        shader: &wgpu::ShaderModule, 
+        config: &wgpu::SurfaceConfiguration,
+        render_pipeline_layout: &wgpu::PipelineLayout,
+        ) -> wgpu::RenderPipeline {
 
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(render_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: shader,
                 entry_point: Some("vs_main"), // 1.
@@ -289,135 +432,21 @@ impl State {
         })
     }
 
-    fn update_image_texture(image_data: &ImageData, device: &Device, queue: &Queue) -> wgpu::Texture {
-        let dimensions = image_data.dimensions();
-        let diffuse_rgba = image_data.rgba();
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
-        let diffuse_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("diffuse_texture"),
-                view_formats: &[],
-            }
-        );
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
+    fn create_texture_layout(device: &Device, diffuse_texture: &Texture, compare_texture: &Texture) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
             },
-            diffuse_rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
-
-        diffuse_texture
-    }
-
-    pub fn create_texture(device: &Device, queue: &Queue) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
-    
-        // Load Image
-         let diffuse_texture = Self::update_image_texture(
-                &ImageData::new("image.png").unwrap(),
-                &device,
-                &queue,
-            );
-
-        /*let image_data = ImageData::new("image.png"); 
-        let dimensions = image_data.dimensions();
-        let diffuse_rgba = image_data.rgba();
-        // Create Texture
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+            count: None,
         };
-        let diffuse_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                // All textures are stored as 3D, we represent our 2D texture
-                // by setting depth to 1.
-                size: texture_size,
-                mip_level_count: 1, // We'll talk about this a little later
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                // Most images are stored using sRGB, so we need to reflect that here.
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                // TEXTURE_BINDING tells wgpu that we want to use this texture in shaders
-                // COPY_DST means that we want to copy data to this texture
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("diffuse_texture"),
-                // This is the same as with the SurfaceConfig. It
-                // specifies what texture formats can be used to
-                // create TextureViews for this texture. The base
-                // texture format (Rgba8UnormSrgb in this case) is
-                // always supported. Note that using a different
-                // texture format is not supported on the WebGL2
-                // backend.
-                view_formats: &[],
-            }
-        );
-
-        queue.write_texture(
-            // Tells wgpu where to copy the pixel data
-            wgpu::ImageCopyTexture {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            // The actual pixel data
-            diffuse_rgba,
-            // The layout of the texture
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
-
-        */
-
-        // We don't need to configure the texture view much, so let's
-        // let wgpu define it.
-        let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::MirrorRepeat,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
+                    texture_entry(0),
                     wgpu::BindGroupLayoutEntry {
                         binding: 1,
                         visibility: wgpu::ShaderStages::FRAGMENT,
@@ -426,29 +455,392 @@ impl State {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    // second panorama of the comparison, read with the same sampler
+                    texture_entry(2),
                 ],
                 label: Some("texture_bind_group_layout"),
             });
 
-        let diffuse_bind_group = device.create_bind_group(
+        let diffuse_bind_group = Self::create_diffuse_bind_group(device, &texture_bind_group_layout, diffuse_texture, compare_texture);
+
+        // return
+        (texture_bind_group_layout, diffuse_bind_group)
+    }
+
+    fn create_diffuse_bind_group(device: &Device, layout: &wgpu::BindGroupLayout, texture: &Texture, compare_texture: &Texture) -> wgpu::BindGroup {
+        device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
+                layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                    }
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&compare_texture.view),
+                    },
                 ],
                 label: Some("diffuse_bind_group"),
             }
+        )
+    }
+
+    /// Keep the current frame to blend it with the panorama about to replace it, the zoom
+    /// blend grows towards the cursor.
+    pub fn begin_scene_transition(&mut self, transition: SceneTransition, duration: f32) {
+        let size = (self.config.width, self.config.height);
+        let focus = self.camera_controller.cursor_position();
+        let Some(view) = self.scene_fade.begin(&self.device, size, transition, duration, focus) else {
+            return;
+        };
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Snapshot Encoder"),
+        });
+        self.draw_panorama(&mut encoder, &view);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.window.request_redraw();
+    }
+
+    /// Replace the panorama, keeping the pipeline and the camera.
+    pub fn set_image(&mut self, image: &ImageData) {
+        self.diffuse_texture = Texture::from_image(image, &self.device, &self.queue);
+        self.diffuse_bind_group = Self::create_diffuse_bind_group(&self.device, &self.texture_bind_group_layout, &self.diffuse_texture, &self.compare_texture);
+        let size = self.diffuse_texture.texture.size();
+        self.image_size = (size.width, size.height);
+        self.window.request_redraw();
+    }
+
+    /// Panorama compared with the displayed one through the same camera, none ends the
+    /// comparison.
+    pub fn set_comparison_image(&mut self, image: Option<&ImageData>) {
+        self.compare_texture = match image {
+            Some(image) => Texture::from_image(image, &self.device, &self.queue),
+            None => Texture::blank(&self.device, &self.queue),
+        };
+        self.comparison.set_loaded(image.is_some());
+        self.diffuse_bind_group = Self::create_diffuse_bind_group(&self.device, &self.texture_bind_group_layout, &self.diffuse_texture, &self.compare_texture);
+        self.write_overlays((self.config.width, self.config.height));
+        self.window.request_redraw();
+    }
+
+    pub fn cycle_comparison_mode(&mut self) -> ComparisonMode {
+        self.comparison.cycle_mode();
+        self.window.request_redraw();
+        self.comparison.mode()
+    }
+
+    /// Show or hide the second panorama, and return whether it is shown.
+    pub fn toggle_comparison(&mut self) -> bool {
+        let shown = self.comparison.toggle();
+        self.window.request_redraw();
+        shown
+    }
+
+    /// Show the other panorama in flicker mode.
+    pub fn flip_comparison(&mut self) {
+        self.comparison.flip();
+        self.window.request_redraw();
+    }
+
+    /// Whether the cursor is over the wipe divider.
+    pub fn is_over_divider(&self) -> bool {
+        let (x, _) = self.camera_controller.cursor_position();
+        self.comparison.is_near_divider(x, self.config.width as f32)
+    }
+
+    /// Move the wipe divider under the cursor.
+    pub fn drag_divider(&mut self) {
+        let (x, _) = self.camera_controller.cursor_position();
+        self.comparison.move_divider(x, self.config.width as f32);
+        self.window.request_redraw();
+    }
+
+    /// When the flicker mode next switches the panoramas.
+    pub fn comparison_wake_at(&self) -> Option<Instant> {
+        self.comparison.next_flip()
+    }
+
+    // Settings of the half of the window the panorama is drawn in, side by side
+    fn viewport(&self) -> CameraSettingsBuffer {
+        let mut settings = self.settings;
+        if self.comparison.is_side_by_side() {
+            settings.resize(self.config.width as f32 / 2.0, self.config.height as f32);
+        }
+        settings
+    }
+
+    // View ray under the cursor, in the half of the window it is over side by side
+    fn cursor_ray(&self) -> Option<Vector3<f32>> {
+        let (x, y) = self.camera_controller.cursor_position();
+        let half = self.config.width as f32 / 2.0;
+        let x = match self.comparison.is_side_by_side() && x >= half {
+            true => x - half,
+            false => x,
+        };
+        self.viewport().view_ray(x, y)
+    }
+
+    /// Name of the panorama shown by the HUD.
+    pub fn set_image_name(&mut self, name: &str) {
+        self.image_name = name.to_string();
+        self.window.request_redraw();
+    }
+
+    /// Show a transient message over the view.
+    pub fn show_message(&mut self, message: &str) {
+        self.hud.show_message(message);
+        self.window.request_redraw();
+    }
+
+    pub fn toggle_hud(&mut self) {
+        self.hud.toggle();
+        self.window.request_redraw();
+    }
+
+    pub fn set_hotspots(&mut self, hotspots: &[Hotspot]) {
+        self.hotspots = hotspots.to_vec();
+        self.hovered_hotspot = self.hovered_hotspot.filter(|index| *index < hotspots.len());
+        self.window.request_redraw();
+    }
+
+    /// In edit mode the mouse places hotspots rather than turning the camera.
+    pub fn set_hotspot_editing(&mut self, editing: bool) {
+        self.hotspot_editing = editing;
+        self.window.request_redraw();
+    }
+
+    pub fn set_measurement(&mut self, measurement: &Measurement) {
+        self.measurement = measurement.clone();
+        self.window.request_redraw();
+    }
+
+    /// In measurement mode the mouse picks points rather than turning the camera.
+    pub fn set_measuring(&mut self, measuring: bool) {
+        self.measuring = measuring;
+        self.window.request_redraw();
+    }
+
+    /// Highlight the hotspot under the cursor and return it.
+    pub fn hover_hotspot(&mut self) -> Option<usize> {
+        let hovered = self.hotspot_at_cursor();
+        if hovered != self.hovered_hotspot {
+            self.hovered_hotspot = hovered;
+            self.window.request_redraw();
+        }
+        hovered
+    }
+
+    /// Pick the hotspot under the cursor by casting its view ray on the sphere.
+    pub fn hotspot_at_cursor(&self) -> Option<usize> {
+        let ray = self.cursor_ray()?;
+        let tolerance = self.hud.marker_size() as f32 / 2.0 * self.settings.degrees_per_pixel();
+        hotspots::pick(&self.hotspots, self.camera.to_world(ray), tolerance)
+    }
+
+    /// Azimuth and elevation of the panorama under the cursor.
+    pub fn cursor_direction(&self) -> Option<(f32, f32)> {
+        let ray = self.cursor_ray()?;
+        Some(PolarCoordinate::from_direction(self.camera.to_world(ray)))
+    }
+
+    pub fn toggle_minimap(&mut self) -> bool {
+        self.minimap.toggle();
+        self.window.request_redraw();
+        self.minimap.is_visible()
+    }
+
+    pub fn toggle_minimap_mode(&mut self) -> MinimapMode {
+        self.minimap.toggle_mode();
+        self.window.request_redraw();
+        self.minimap.mode()
+    }
+
+    /// Azimuth and elevation of the point of the minimap under the cursor.
+    pub fn minimap_direction(&self) -> Option<(f32, f32)> {
+        self.minimap.direction_at(self.camera_controller.cursor_position())
+    }
+
+    // Border of the screen as azimuths and elevations, clockwise from the top left corner
+    fn view_outline(&self) -> Vec<(f32, f32)> {
+        const STEPS: u32 = 16;
+        let settings = self.viewport();
+        let (width, height) = (settings.width(), self.config.height as f32);
+        let edges = [((0.0, 0.0), (width, 0.0)), ((width, 0.0), (width, height)), ((width, height), (0.0, height)), ((0.0, height), (0.0, 0.0))];
+        edges.iter()
+            .flat_map(|((x0, y0), (x1, y1))| (0..STEPS).map(move |step| {
+                let t = step as f32 / STEPS as f32;
+                (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t)
+            }))
+            .filter_map(|(x, y)| settings.view_ray(x, y))
+            .map(|ray| PolarCoordinate::from_direction(self.camera.to_world(ray)))
+            .collect()
+    }
+
+    /// When the HUD changes without input, for a message to go away.
+    pub fn hud_wake_at(&self) -> Option<Instant> {
+        self.hud.next_expiry()
+    }
+
+    fn create_overlays(device: &Device, patch_texture: &PatchTexture) -> (wgpu::Buffer, [wgpu::Buffer; 2], wgpu::BindGroup, wgpu::BindGroupLayout) {
+        let uniform = OverlayUniform::new(&OverlayConfig::default(), 0.0, (1.0, 1.0), 0, [[0.0; 4]; 2], bytemuck::Zeroable::zeroed());
+        let overlay_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
         );
 
-        // return
-        (texture_bind_group_layout, diffuse_bind_group)
+        let storage_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let overlay_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("overlay_bind_group_layout"),
+        });
+
+        let region_buffers = Self::create_region_buffers(device, &[], &[]);
+        let overlay_bind_group = Self::create_overlay_bind_group(device, &overlay_bind_group_layout, &overlay_buffer, &region_buffers, patch_texture);
+        (overlay_buffer, region_buffers, overlay_bind_group, overlay_bind_group_layout)
+    }
+
+    // Storage buffers of the regions and their vertices, never empty
+    fn create_region_buffers(device: &Device, regions: &[RegionUniform], points: &[[f32; 4]]) -> [wgpu::Buffer; 2] {
+        let regions = match regions.is_empty() {
+            true => bytemuck::bytes_of(&<RegionUniform as bytemuck::Zeroable>::zeroed()).to_vec(),
+            false => bytemuck::cast_slice(regions).to_vec(),
+        };
+        let points = match points.is_empty() {
+            true => vec![0; std::mem::size_of::<[f32; 4]>()],
+            false => bytemuck::cast_slice(points).to_vec(),
+        };
+        [("Region Buffer", regions), ("Region Point Buffer", points)].map(|(label, contents)| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: &contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        })
+    }
+
+    fn create_overlay_bind_group(
+        device: &Device,
+        layout: &wgpu::BindGroupLayout,
+        overlay_buffer: &wgpu::Buffer,
+        region_buffers: &[wgpu::Buffer; 2],
+        patch_texture: &PatchTexture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: overlay_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: region_buffers[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: region_buffers[1].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&patch_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&patch_texture.sampler),
+                },
+            ],
+            label: Some("overlay_bind_group"),
+        })
+    }
+
+    /// Image of the nadir or zenith patch, `patches::NADIR` or `patches::ZENITH`, none
+    /// hides it.
+    pub fn set_patch(&mut self, layer: usize, image: Option<&RgbaImage>) {
+        if let Some(image) = image {
+            self.patch_texture.write(&self.queue, layer, image);
+        }
+        self.patch_loaded[layer] = image.is_some();
+        self.write_overlays((self.config.width, self.config.height));
+        self.window.request_redraw();
+    }
+
+    /// Regions of the panorama drawn by the shader.
+    pub fn set_regions(&mut self, regions: &[Region]) {
+        self.regions = regions.to_vec();
+        self.upload_regions();
+    }
+
+    /// Show or hide the tinted and outlined regions, and return whether they are shown.
+    pub fn toggle_region_highlights(&mut self) -> bool {
+        self.region_highlights = !self.region_highlights;
+        self.upload_regions();
+        self.region_highlights
+    }
+
+    pub fn region_at_cursor(&self) -> Option<usize> {
+        let ray = self.cursor_ray()?;
+        regions::pick(&self.regions, self.camera.to_world(ray))
+    }
+
+    fn upload_regions(&mut self) {
+        let (uniforms, points) = regions::shader_data(&self.regions, self.region_highlights);
+        self.region_count = uniforms.len() as u32;
+        self.region_buffers = Self::create_region_buffers(&self.device, &uniforms, &points);
+        self.overlay_bind_group = Self::create_overlay_bind_group(
+            &self.device,
+            &self.overlay_bind_group_layout,
+            &self.overlay_buffer,
+            &self.region_buffers,
+            &self.patch_texture,
+        );
+        self.write_overlays((self.config.width, self.config.height));
+        self.window.request_redraw();
     }
 
     fn create_camera(size: PhysicalSize<u32>, device: &Device) -> (
@@ -568,15 +960,127 @@ impl State {
         self.camera_controller.process_events(event)
     }
 
+    pub fn set_title(&self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    pub fn reset_view(&mut self) {
+        self.camera_controller.reset_camera(&mut self.camera, &mut self.settings);
+        self.window.request_redraw();
+    }
+
+    /// Turn the camera without user input, `angular_delta` in degrees.
+    pub fn rotate_camera(&mut self, angular_delta: f32) {
+        self.camera.rotate(angular_delta, 0.0);
+        self.window.request_redraw();
+    }
+
+    pub fn view(&self) -> View {
+        View::new(self.camera.coordinates(), self.settings.focal_length(), self.settings.projection())
+    }
+
+    /// Move to `view` in `duration` seconds, at once if it is 0.
+    pub fn set_view(&mut self, view: &View, duration: f32) {
+        self.animate_view(view, duration, Easing::EaseInOut);
+    }
+
+    pub fn animate_view(&mut self, view: &View, duration: f32, easing: Easing) {
+        self.settings.set_projection(view.projection);
+        if duration > 0.0 {
+            self.camera.animate_to(view.coordinates(), (self.settings.focal_length(), view.focal_length()), duration, easing);
+        } else {
+            self.camera.set_coordinates(view.coordinates());
+            self.settings.set_focal_length(view.focal_length());
+        }
+        self.last_update = Instant::now();
+        self.window.request_redraw();
+    }
+
+    pub fn attract_starts_at(&self) -> Option<Instant> {
+        self.camera_controller.attract_starts_at()
+    }
+
+    pub fn reset_idle(&mut self) {
+        self.camera_controller.reset_idle();
+    }
+
+    /// Step the camera animation by `delta_time` seconds.
+    pub fn advance_animation(&mut self, delta_time: f32) {
+        if let Some(focal_length) = self.camera.advance(delta_time) {
+            self.settings.set_focal_length(focal_length);
+        }
+    }
+
+    /// Freeze the camera animation, user input still moves the camera.
+    pub fn pause_animation(&mut self, paused: bool) {
+        self.animation_paused = paused;
+    }
+
+    pub fn orientation_mode(&self) -> OrientationMode {
+        self.camera.mode()
+    }
+
+    pub fn set_orientation_mode(&mut self, mode: OrientationMode) {
+        self.camera.set_mode(mode);
+        self.window.request_redraw();
+    }
+
     pub fn move_camera_by_cursor(&mut self, delta_x: f64, delta_y: f64) {
         self.camera_controller.move_cursor(&mut self.camera, delta_x, delta_y);
     }
 
     pub fn update(&mut self) {
+        let now = Instant::now();
+        if !self.animation_paused {
+            self.advance_animation((now - self.last_update).as_secs_f32());
+        }
+        self.last_update = now;
+        self.comparison.advance(now);
         self.camera_controller.update_camera(&mut self.camera, &mut self.settings);
         self.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
         self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[self.settings]));
+        self.write_overlays((self.config.width, self.config.height));
+    }
+
+    fn write_overlays(&self, screen: (u32, u32)) {
+        self.write_camera_overlays(&self.camera, screen);
+    }
+
+    // Overlays of the view of `camera`, the compass shows its heading
+    fn write_camera_overlays(&self, camera: &Camera, screen: (u32, u32)) {
+        let heading = overlays::heading(camera.coordinates().azimuth, self.north);
+        let patches = [0, 1].map(|layer| patches::shader_data(&self.patches[layer], self.patch_loaded[layer]));
+        let uniform = OverlayUniform::new(&self.overlays, heading, (screen.0 as f32, screen.1 as f32), self.region_count, patches, self.comparison.uniform());
+        self.queue.write_buffer(&self.overlay_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Show or hide the overlay of a toggle action, and return its name and whether it is
+    /// now shown.
+    pub fn toggle_overlay(&mut self, action: Action) -> Option<(&'static str, bool)> {
+        let (name, shown) = match action {
+            Action::ToggleHorizonLine => ("Horizon line", &mut self.overlays.horizon),
+            Action::ToggleGrid => ("Grid", &mut self.overlays.grid),
+            Action::ToggleCompass => ("Compass", &mut self.overlays.compass),
+            Action::ToggleCrosshair => ("Crosshair", &mut self.overlays.crosshair),
+            _ => return None,
+        };
+        *shown = !*shown;
+        let shown = *shown;
+        self.window.request_redraw();
+        Some((name, shown))
+    }
+
+    pub fn gamepad_input(&mut self, event: GamepadEvent) -> bool {
+        self.camera_controller.process_gamepad_event(event)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.camera_controller.is_moving() || (self.camera.is_animating() && !self.animation_paused) || self.scene_fade.is_active()
+    }
+
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        self.camera_controller.take_actions()
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -585,42 +1089,394 @@ impl State {
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
-        // the purpose of the brackets is to be sure to drop _render_pass before calling
-        // encorder.finish()
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
+        self.draw_panorama(&mut encoder, &view);
+        self.scene_fade.draw(&self.queue, &mut encoder, &view);
+        if self.minimap.is_visible() {
+            let outline = self.view_outline();
+            let coordinates = self.camera.coordinates();
+            let center = (coordinates.azimuth, coordinates.elevation);
+            self.minimap.prepare(&self.device, &self.queue, (self.config.width, self.config.height), &outline, center);
+            self.minimap.draw(&mut encoder, &view, &self.diffuse_bind_group);
+        }
+        self.hud.frame();
+        let info = self.hud_info();
+        let mut annotations = Annotations {
+            markers: self.hotspot_markers(),
+            labels: self.overlay_labels(),
+            lines: Vec::new(),
+        };
+        self.measurement_annotations(&mut annotations);
+        self.hud.prepare(&self.device, &self.queue, (self.config.width, self.config.height), &annotations, &info);
+        self.hud.draw(&mut encoder, &view);
+
+        // submit will accept anything that implements IntoIter
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+
+        Ok(())
+    }
+
+    fn hud_info(&self) -> Vec<String> {
+        let view = self.view();
+        let mut info = vec![
+            format!("{} {}x{}", self.image_name, self.image_size.0, self.image_size.1),
+            format!("Azimuth   {:7.2}°", view.azimuth),
+            format!("Elevation {:7.2}°", view.elevation),
+            format!("Roll      {:7.2}°", view.roll),
+            format!("FOV       {:7.2}°", view.fov),
+            format!("{} ({:?})", view.projection.name(), self.camera.mode()),
+            format!("{} FPS", self.hud.fps()),
+        ];
+        if self.overlays.compass {
+            info.push(format!("Heading   {:7.2}°", overlays::heading(view.azimuth, self.north)));
+        }
+        if self.comparison.is_active() {
+            info.push(format!("Comparison : {:?}", self.comparison.mode()));
+        }
+        if self.hotspot_editing {
+            info.push("Editing hotspots : click to add, drag to move, right click to delete".to_string());
+        }
+        if self.measuring {
+            info.push("Measuring : click to add a point, right click to remove the last one".to_string());
+        }
+        if self.measuring || !self.measurement.is_empty() {
+            info.extend(self.measurement.summary());
+        }
+        info
+    }
+
+    // Screen position of a point of the panorama
+    fn project_point(&self, azimuth: f32, elevation: f32) -> Option<(f32, f32)> {
+        let settings = self.viewport();
+        let (x, y) = settings.screen_position(self.camera.to_camera_space(PolarCoordinate::direction(azimuth, elevation)))?;
+        let on_screen = (0.0..settings.width()).contains(&x) && (0.0..self.config.height as f32).contains(&y);
+        on_screen.then_some((x, y))
+    }
+
+    /// Degrees of the grid lines next to the view center, and the north of the compass.
+    fn overlay_labels(&self) -> Vec<Label> {
+        let mut labels = Vec::new();
+        let offset = self.hud.marker_size() as f32;
+        let coordinates = self.camera.coordinates();
+
+        if self.overlays.grid {
+            let step = self.overlays.grid_step;
+            let limit = (90.0 - step).max(0.0);
+            // azimuths along the parallel closest to the center, elevations along the meridian
+            let parallel = ((coordinates.elevation / step).round() * step).clamp(-limit, limit);
+            let meridian = (coordinates.azimuth / step).round() * step;
+            for index in 0..(360.0 / step).ceil() as u32 {
+                let azimuth = index as f32 * step;
+                if let Some((x, y)) = self.project_point(azimuth, parallel) {
+                    labels.push(Label { position: (x + offset, y - offset / 2.0), text: format!("{azimuth}°") });
+                }
+            }
+            let count = (90.0 / step).floor() as i32;
+            for index in -count..=count {
+                let elevation = index as f32 * step;
+                if elevation.abs() >= 90.0 || elevation == parallel {
+                    continue;
+                }
+                if let Some((x, y)) = self.project_point(meridian, elevation) {
+                    labels.push(Label { position: (x + offset, y - offset / 2.0), text: format!("{elevation:+}°") });
+                }
+            }
+        }
+
+        if self.overlays.compass {
+            let screen = (self.config.width as f32, self.config.height as f32);
+            let (x, y) = overlays::compass_center(&self.overlays, screen);
+            let heading = overlays::heading(coordinates.azimuth, self.north).to_radians();
+            let distance = self.overlays.compass_radius + offset / 2.0;
+            labels.push(Label { position: (x - heading.sin() * distance, y - heading.cos() * distance), text: "N".to_string() });
+        }
+        labels
+    }
+
+    /// Measured points with their coordinates, and the great circle arcs between them
+    /// labelled with their length.
+    fn measurement_annotations(&self, annotations: &mut Annotations) {
+        let settings = self.viewport();
+        let points = self.measurement.points();
+        for (index, point) in points.iter().enumerate() {
+            let Some(position) = settings.screen_position(self.camera.to_camera_space(point.direction())) else {
+                continue;
+            };
+            annotations.markers.push(Marker {
+                position,
+                symbol: '+',
+                color: measurement::COLOR,
+                label: format!("{} {:.2}° {:+.2}°", index + 1, point.azimuth, point.elevation),
+                selected: false,
             });
+        }
+
+        let mut edges: Vec<(usize, usize)> = (1..points.len()).map(|index| (index - 1, index)).collect();
+        if self.measurement.is_polygon() {
+            edges.push((points.len() - 1, 0));
+        }
+        for (start, end) in edges {
+            let (a, b) = (points[start].direction(), points[end].direction());
+            let distance = measurement::angular_distance(a, b);
+            // a point per degree, the line is cut where it leaves the projection
+            let arc = measurement::great_circle(a, b, distance.ceil().max(1.0) as u32);
+            let mut line = Vec::new();
+            for direction in &arc {
+                match settings.screen_position(self.camera.to_camera_space(*direction)) {
+                    Some(position) => line.push(position),
+                    None if line.len() > 1 => annotations.lines.push(Polyline { points: std::mem::take(&mut line), color: measurement::COLOR }),
+                    None => line.clear(),
+                }
+            }
+            if line.len() > 1 {
+                annotations.lines.push(Polyline { points: line, color: measurement::COLOR });
+            }
+            if let Some(position) = settings.screen_position(self.camera.to_camera_space(arc[arc.len() / 2])) {
+                annotations.labels.push(Label { position, text: format!("{distance:.2}°") });
+            }
+        }
+    }
+
+    fn hotspot_markers(&self) -> Vec<Marker> {
+        let settings = self.viewport();
+        self.hotspots.iter()
+            .enumerate()
+            .filter_map(|(index, hotspot)| {
+                let position = settings.screen_position(self.camera.to_camera_space(hotspot.direction()))?;
+                Some(Marker {
+                    position,
+                    symbol: hotspot.icon.symbol(),
+                    color: hotspot.icon.color(),
+                    label: hotspot.title.clone(),
+                    selected: self.hovered_hotspot == Some(index),
+                })
+            })
+            .collect()
+    }
+
+    fn draw_panorama(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); 
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); 
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.overlay_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
-            render_pass.draw(0..self.num_vertices,0..1);
+        render_pass.draw(0..self.num_vertices,0..1);
+    }
+
+    /// Render the current view into an offscreen texture and read it back.
+    pub fn capture(&self) -> Result<RgbaImage, String> {
+        self.capture_texture(self.config.width, self.config.height)
+    }
+
+    /// Whether the GPU can render an image of `width` by `height` pixels.
+    pub fn check_render_size(&self, width: u32, height: u32) -> Result<(), String> {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(format!("Size {width}x{height} out of the 1 to {max_size} pixels supported by the GPU"));
         }
+        Ok(())
+    }
 
-        // submit will accept anything that implements IntoIter
+    /// Render the current view at a size independent of the window, for the camera state
+    /// as it is now rather than at the last redraw.
+    pub fn render_offscreen(&mut self, width: u32, height: u32) -> Result<RgbaImage, String> {
+        self.check_render_size(width, height)?;
+
+        let mut settings = self.settings;
+        settings.resize(width as f32, height as f32);
+        self.camera_uniform.update_view_proj(&self.camera);
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+        self.write_overlays((width, height));
+        let image = self.capture_texture(width, height);
+        // the window keeps its own size
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[self.settings]));
+        self.write_overlays((self.config.width, self.config.height));
+        image
+    }
+
+    /// Render the whole panorama at its size, or the largest one the GPU supports, with the
+    /// patches and the blurred or masked regions baked in, without the guides, the
+    /// comparison and the region highlights.
+    pub fn render_equirectangular(&mut self) -> Result<RgbaImage, String> {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let scale = (max_size as f32 / self.image_size.0.max(self.image_size.1) as f32).min(1.0);
+        let (width, height) = ((self.image_size.0 as f32 * scale) as u32, (self.image_size.1 as f32 * scale) as u32);
+
+        let settings = CameraSettingsBuffer::equirectangular(width as f32, height as f32);
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[settings]));
+        let overlays = std::mem::take(&mut self.overlays);
+        let comparison_active = self.comparison.is_active();
+        if comparison_active {
+            self.comparison.toggle();
+        }
+        let region_highlights = std::mem::replace(&mut self.region_highlights, false);
+        self.upload_regions();
+        self.write_overlays((width, height));
+        // the scene fade is drawn over the panorama by `render` only, it is never captured
+        let image = self.capture_texture(width, height);
+        self.overlays = overlays;
+        if comparison_active {
+            self.comparison.toggle();
+        }
+        self.region_highlights = region_highlights;
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[self.settings]));
+        self.upload_regions();
+        image
+    }
+
+    fn capture_texture(&self, width: u32, height: u32) -> Result<RgbaImage, String> {
+        let texture_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some("capture_texture"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // rows of a texture copy must be aligned on 256 bytes
+        let unpadded_bytes_per_row = 4 * width;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Capture Encoder"),
+        });
+        self.draw_panorama(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture_size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()
+            .map_err(|why| why.to_string())?
+            .map_err(|why| why.to_string())?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in buffer_slice.get_mapped_range().chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        buffer.unmap();
+
+        if matches!(self.config.format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(width, height, pixels).ok_or("Invalid capture size".to_string())
+    }
+
+    /// Open a window next to this one, drawn with the same device, pipeline and textures.
+    pub fn open_window(&self, window: Window, settings: &ExtraWindowSettings, viewer_config: &Config) -> Result<ExtraWindow, String> {
+        let window = Arc::new(window);
+        let surface = self.instance.create_surface(window.clone()).map_err(|why| why.to_string())?;
+        let capabilities = surface.get_capabilities(&self.adapter);
+        // the pipeline renders to the format of the main window
+        if !capabilities.formats.contains(&self.config.format) {
+            return Err(format!("the window does not support the {:?} format of the main window", self.config.format));
+        }
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            alpha_mode: capabilities.alpha_modes[0],
+            ..self.config.clone()
+        };
+        surface.configure(&self.device, &config);
+        Ok(ExtraWindow::new(window, surface, config, settings, viewer_config, &self.view()))
+    }
+
+    pub fn resize_window(&self, extra_window: &mut ExtraWindow, new_size: PhysicalSize<u32>) {
+        extra_window.resize(&self.device, new_size.width, new_size.height);
+    }
+
+    /// Draw the panorama in an extra window, through its camera. The buffers shared with
+    /// this window are written back once it is submitted.
+    pub fn render_window(&mut self, extra_window: &ExtraWindow) -> Result<(), wgpu::SurfaceError> {
+        let output = match extra_window.surface().get_current_texture() {
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                extra_window.reconfigure(&self.device);
+                extra_window.surface().get_current_texture()?
+            }
+            output => output?,
+        };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(extra_window.camera());
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[*extra_window.settings()]));
+        self.write_camera_overlays(extra_window.camera(), extra_window.size());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Extra Window Encoder"),
+        });
+        self.draw_panorama(&mut encoder, &view);
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.queue.write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[self.settings]));
+        self.write_overlays((self.config.width, self.config.height));
         Ok(())
     }
+
+    /// Save the current view as a png in the working directory and return its file name.
+    pub fn screenshot(&self) -> Result<String, String> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|why| why.to_string())?;
+        let filename = format!("screenshot_{}.png", timestamp.as_millis());
+        self.capture()?.save(&filename).map_err(|why| why.to_string())?;
+        Ok(filename)
+    }
 }
@@ -1,4 +1,6 @@
-use std::result::Result;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
 use crate::image_data::ImageData;
 
 pub struct Texture {
@@ -9,15 +11,26 @@ pub struct Texture {
 
 impl Texture {
 
-    pub fn from_file(filename: &'static str, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self ,&'static str> {
-        let image = ImageData::new(filename)?;
+    /// Upload `image`, downscaled when it is larger than the GPU supports.
+    pub fn from_image(image: &ImageData, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let (width, height) = image.dimensions();
+        let max_size = device.limits().max_texture_dimension_2d;
+        if width <= max_size && height <= max_size {
+            return Self::from_rgba(image.rgba(), device, queue);
+        }
+        let scale = max_size as f32 / width.max(height) as f32;
+        let size = (((width as f32 * scale) as u32).clamp(1, max_size), ((height as f32 * scale) as u32).clamp(1, max_size));
+        println!("Image of {width}x{height} larger than the {max_size} pixels supported by the GPU, downscaled to {}x{}", size.0, size.1);
+        Self::from_rgba(&imageops::resize(image.rgba(), size.0, size.1, FilterType::Triangle), device, queue)
+    }
 
-        Ok(Self::from_image(&image, &device, &queue))
+    /// A black pixel, for a binding without image.
+    pub fn blank(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_rgba(&RgbaImage::new(1, 1), device, queue)
     }
 
-    pub fn from_image(image: &ImageData, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
-        let dimensions = image.dimensions();
-        let diffuse_rgba = image.rgba();
+    fn from_rgba(diffuse_rgba: &RgbaImage, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let dimensions = diffuse_rgba.dimensions();
         let texture_size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
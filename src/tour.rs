@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Easing;
+use crate::playlist::same_file;
+use crate::view::View;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A point of a tour, reached from the previous one in `duration` seconds and held for
+/// `pause` seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Panorama shown at this keyframe, relative to the tour file. Without it the tour
+    /// stays on the current panorama.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<PathBuf>,
+    #[serde(flatten)]
+    pub view: View,
+    #[serde(default = "default_duration")]
+    pub duration: f32,
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub pause: f32,
+}
+
+fn default_duration() -> f32 {
+    3.0
+}
+
+/// A camera path, stored as
+///
+/// ```toml
+/// loop = true
+///
+/// [[keyframes]]
+/// image = "square.jpg"
+/// azimuth = 0.0
+/// elevation = 0.0
+/// fov = 90.0
+/// pause = 2.0
+///
+/// [[keyframes]]
+/// azimuth = 120.0
+/// elevation = 30.0
+/// roll = 10.0
+/// fov = 40.0
+/// projection = "stereographic"
+/// duration = 5.0
+/// easing = "ease_out"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tour {
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Tour {
+
+    /// Read a tour, with the image paths made relative to the working directory.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        let mut tour: Tour = toml::from_str(&content).map_err(|why| format!("{}: {why}", path.display()))?;
+        if tour.keyframes.is_empty() {
+            return Err(format!("{}: no keyframes", path.display()));
+        }
+        if let Some(keyframe) = tour.keyframes.iter().find(|keyframe| keyframe.duration < 0.0 || keyframe.pause < 0.0) {
+            return Err(format!("{}: negative duration or pause at {}", path.display(), keyframe.view));
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for keyframe in tour.keyframes.iter_mut() {
+            keyframe.image = keyframe.image.as_ref().map(|image| base.join(image));
+        }
+        Ok(tour)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string_pretty(self).map_err(|why| why.to_string())?;
+        fs::write(path, content).map_err(|why| format!("{}: {why}", path.display()))
+    }
+}
+
+/// What the application must do for the tour to go on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TourCommand {
+    Open(PathBuf),
+    /// Cut to a view, after opening a panorama.
+    Jump(View),
+    Animate { view: View, duration: f32, easing: Easing },
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Start(usize),
+    Opening(usize),
+    Moving(usize, f32),
+    Pausing(usize, f32),
+    Finished,
+}
+
+/// Timeline of a tour, driven by the time elapsed between frames.
+pub struct TourPlayer {
+    tour: Tour,
+    phase: Phase,
+    paused: bool,
+    // the first keyframe is cut to, the next ones are animated
+    started: bool,
+}
+
+impl TourPlayer {
+
+    pub fn new(tour: Tour) -> Self {
+        Self {
+            tour,
+            phase: Phase::Start(0),
+            paused: false,
+            started: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Panorama of the next keyframe which changes it, worth preloading.
+    pub fn next_image(&self) -> Option<&Path> {
+        let index = match self.phase {
+            Phase::Start(index) | Phase::Opening(index) | Phase::Moving(index, _) | Phase::Pausing(index, _) => index,
+            Phase::Finished => return None,
+        };
+        let count = self.tour.keyframes.len();
+        (1..=count)
+            .map(|offset| (index + offset) % count)
+            .take_while(|next| self.tour.looping || *next > index)
+            .find_map(|next| self.tour.keyframes[next].image.as_deref())
+    }
+
+    /// Seconds since the camera started moving towards the current keyframe, while it does.
+    pub fn segment_elapsed(&self) -> Option<f32> {
+        match self.phase {
+            Phase::Moving(index, remaining) => Some(self.tour.keyframes[index].duration - remaining),
+            _ => None,
+        }
+    }
+
+    /// Advance by `delta_time` seconds. `current_image` is the panorama on screen, or
+    /// `None` while one is being opened.
+    pub fn update(&mut self, delta_time: f32, current_image: Option<&Path>) -> Vec<TourCommand> {
+        let mut commands = Vec::new();
+        if self.paused {
+            return commands;
+        }
+
+        let mut delta_time = delta_time;
+        // several phases can end in one step, each keyframe is visited at most once
+        for _ in 0..=self.tour.keyframes.len() * 3 {
+            match self.phase {
+                Phase::Start(index) => {
+                    let keyframe = &self.tour.keyframes[index];
+                    match &keyframe.image {
+                        Some(image) if !current_image.is_some_and(|current| same_file(current, image)) => {
+                            commands.push(TourCommand::Open(image.clone()));
+                            self.phase = Phase::Opening(index);
+                            break;
+                        }
+                        _ if !self.started => {
+                            commands.push(TourCommand::Jump(keyframe.view));
+                            self.phase = Phase::Pausing(index, keyframe.pause);
+                        }
+                        _ => {
+                            commands.push(TourCommand::Animate {
+                                view: keyframe.view,
+                                duration: keyframe.duration,
+                                easing: keyframe.easing,
+                            });
+                            self.phase = Phase::Moving(index, keyframe.duration);
+                        }
+                    }
+                    self.started = true;
+                }
+                Phase::Opening(index) => {
+                    let keyframe = &self.tour.keyframes[index];
+                    let opened = match (&keyframe.image, current_image) {
+                        (Some(image), Some(current)) => same_file(current, image),
+                        _ => false,
+                    };
+                    if !opened {
+                        break;
+                    }
+                    commands.push(TourCommand::Jump(keyframe.view));
+                    self.phase = Phase::Pausing(index, keyframe.pause);
+                    self.started = true;
+                }
+                Phase::Moving(index, remaining) => {
+                    if remaining > delta_time {
+                        self.phase = Phase::Moving(index, remaining - delta_time);
+                        break;
+                    }
+                    delta_time -= remaining;
+                    self.phase = Phase::Pausing(index, self.tour.keyframes[index].pause);
+                }
+                Phase::Pausing(index, remaining) => {
+                    if remaining > delta_time {
+                        self.phase = Phase::Pausing(index, remaining - delta_time);
+                        break;
+                    }
+                    delta_time -= remaining;
+                    self.phase = if index + 1 < self.tour.keyframes.len() {
+                        Phase::Start(index + 1)
+                    } else if self.tour.looping {
+                        Phase::Start(0)
+                    } else {
+                        commands.push(TourCommand::Finished);
+                        Phase::Finished
+                    };
+                }
+                Phase::Finished => break,
+            }
+        }
+
+        commands
+    }
+}
+
+/// Keyframes captured while navigating, each one reached in the time it took to set it up.
+pub struct TourRecorder {
+    keyframes: Vec<Keyframe>,
+    last_keyframe: Instant,
+}
+
+impl TourRecorder {
+
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            last_keyframe: Instant::now(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Capture `view` on `image`, the image is only stored when it changes.
+    pub fn add(&mut self, image: &Path, view: View) {
+        let image = image.canonicalize().unwrap_or(image.to_path_buf());
+        let previous_image = self.keyframes.iter().rev().find_map(|keyframe| keyframe.image.as_ref());
+        let changes_image = previous_image != Some(&image);
+        self.keyframes.push(Keyframe {
+            image: changes_image.then_some(image),
+            view,
+            duration: match self.keyframes.is_empty() {
+                true => 0.0,
+                false => self.last_keyframe.elapsed().as_secs_f32().clamp(0.5, 30.0),
+            },
+            easing: Easing::EaseInOut,
+            pause: 0.0,
+        });
+        self.last_keyframe = Instant::now();
+    }
+
+    pub fn finish(self) -> Tour {
+        Tour {
+            looping: false,
+            keyframes: self.keyframes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::camera::{PolarCoordinate, Projection};
+
+    fn keyframe(azimuth: f32, duration: f32, pause: f32) -> Keyframe {
+        Keyframe {
+            image: None,
+            view: View::new(PolarCoordinate { azimuth, elevation: 0.0, roll: 0.0 }, 1.0, Projection::Rectilinear),
+            duration,
+            easing: Easing::Linear,
+            pause,
+        }
+    }
+
+    fn player(looping: bool, keyframes: Vec<Keyframe>) -> TourPlayer {
+        TourPlayer::new(Tour { looping, keyframes })
+    }
+
+    #[test]
+    fn segment_started_during_a_step_counts_only_the_rest_of_it() {
+        let mut player = player(false, vec![keyframe(0.0, 0.0, 0.25), keyframe(90.0, 2.0, 0.0)]);
+        player.update(0.0, None);
+        assert_eq!(player.segment_elapsed(), None);
+
+        // the pause ends 0.25 s into the step, the move starts then
+        let commands = player.update(1.0, None);
+        assert!(matches!(commands[..], [TourCommand::Animate { .. }]));
+        assert!((player.segment_elapsed().unwrap() - 0.75).abs() < 1e-5);
+    }
+
+    fn azimuth(command: &TourCommand) -> f32 {
+        match command {
+            TourCommand::Jump(view) | TourCommand::Animate { view, .. } => view.azimuth,
+            _ => panic!("{command:?} has no view"),
+        }
+    }
+
+    #[test]
+    fn first_keyframe_is_cut_to_and_held() {
+        let mut player = player(false, vec![keyframe(10.0, 5.0, 1.0), keyframe(90.0, 2.0, 0.0)]);
+        let commands = player.update(0.0, None);
+        assert!(matches!(commands[..], [TourCommand::Jump(_)]));
+        assert_eq!(azimuth(&commands[0]), 10.0);
+
+        // held for its pause, its duration is ignored
+        assert!(player.update(0.5, None).is_empty());
+        let commands = player.update(0.5, None);
+        assert!(matches!(commands[..], [TourCommand::Animate { duration, .. }] if duration == 2.0));
+        assert_eq!(azimuth(&commands[0]), 90.0);
+    }
+
+    #[test]
+    fn travel_then_hold_then_end() {
+        let mut player = player(false, vec![keyframe(0.0, 0.0, 0.0), keyframe(90.0, 2.0, 1.0), keyframe(180.0, 1.0, 0.0)]);
+        assert_eq!(player.update(0.0, None).len(), 2);
+        assert_eq!(player.segment_elapsed(), Some(0.0));
+
+        assert!(player.update(1.5, None).is_empty());
+        // the move ends at 2 s, then the hold at 3 s
+        assert!(player.update(1.0, None).is_empty());
+        assert_eq!(player.segment_elapsed(), None);
+        let commands = player.update(0.5, None);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(azimuth(&commands[0]), 180.0);
+
+        assert_eq!(player.update(1.0, None), vec![TourCommand::Finished]);
+        assert!(player.update(1.0, None).is_empty());
+        assert_eq!(player.next_image(), None);
+    }
+
+    #[test]
+    fn looping_tour_starts_again_with_a_move() {
+        let mut player = player(true, vec![keyframe(0.0, 1.0, 0.0), keyframe(90.0, 1.0, 0.0)]);
+        assert!(matches!(player.update(0.0, None)[..], [TourCommand::Jump(_), TourCommand::Animate { .. }]));
+        let commands = player.update(1.0, None);
+        assert!(matches!(commands[..], [TourCommand::Animate { .. }]));
+        assert_eq!(azimuth(&commands[0]), 0.0);
+        assert!(!player.update(10.0, None).contains(&TourCommand::Finished));
+    }
+
+    #[test]
+    fn panorama_is_opened_before_going_on() {
+        let mut second = keyframe(90.0, 1.0, 0.0);
+        second.image = Some(PathBuf::from("second.jpg"));
+        let mut player = player(false, vec![keyframe(0.0, 0.0, 0.0), second]);
+        assert_eq!(player.next_image(), Some(Path::new("second.jpg")));
+
+        let commands = player.update(0.0, Some(Path::new("first.jpg")));
+        assert_eq!(commands.last(), Some(&TourCommand::Open(PathBuf::from("second.jpg"))));
+        // nothing happens until the panorama is on screen, then the view is cut to
+        assert!(player.update(1.0, None).is_empty());
+        assert!(player.update(1.0, Some(Path::new("first.jpg"))).is_empty());
+        let commands = player.update(0.0, Some(Path::new("second.jpg")));
+        assert!(matches!(commands[..], [TourCommand::Jump(_), TourCommand::Finished]));
+    }
+
+    #[test]
+    fn paused_player_does_not_move() {
+        let mut player = player(false, vec![keyframe(0.0, 0.0, 1.0), keyframe(90.0, 1.0, 0.0)]);
+        player.update(0.0, None);
+        player.set_paused(true);
+        assert!(player.is_paused());
+        assert!(player.update(5.0, None).is_empty());
+        player.set_paused(false);
+        assert!(matches!(player.update(1.0, None)[..], [TourCommand::Animate { .. }]));
+    }
+
+    #[test]
+    fn recorder_stores_the_image_when_it_changes() {
+        let view = |azimuth| View::new(PolarCoordinate { azimuth, elevation: 0.0, roll: 0.0 }, 1.0, Projection::Rectilinear);
+        let mut recorder = TourRecorder::new();
+        recorder.add(Path::new("no/such/first.jpg"), view(0.0));
+        recorder.add(Path::new("no/such/first.jpg"), view(90.0));
+        recorder.add(Path::new("no/such/second.jpg"), view(180.0));
+        recorder.add(Path::new("no/such/first.jpg"), view(270.0));
+        assert_eq!(recorder.len(), 4);
+
+        let tour = recorder.finish();
+        assert!(!tour.looping);
+        let images: Vec<Option<&Path>> = tour.keyframes.iter().map(|keyframe| keyframe.image.as_deref()).collect();
+        assert_eq!(images, vec![
+            Some(Path::new("no/such/first.jpg")),
+            None,
+            Some(Path::new("no/such/second.jpg")),
+            Some(Path::new("no/such/first.jpg")),
+        ]);
+        assert_eq!(tour.keyframes[0].duration, 0.0);
+        // quick captures are given the shortest move
+        assert!(tour.keyframes[1..].iter().all(|keyframe| keyframe.duration == 0.5));
+        assert_eq!(tour.keyframes[3].view.azimuth, 270.0);
+    }
+}
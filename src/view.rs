@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{PolarCoordinate, Projection};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything needed to reproduce what is on screen, angles in degrees.
+///
+/// Its textual form "azimuth,elevation,roll,fov,projection", such as
+/// "121.5,-12.0,0.0,75.0,stereographic", can be given with `--view` or pasted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub azimuth: f32,
+    pub elevation: f32,
+    #[serde(default)]
+    pub roll: f32,
+    /// Vertical field of view.
+    pub fov: f32,
+    #[serde(default = "default_projection")]
+    pub projection: Projection,
+}
+
+fn default_projection() -> Projection {
+    Projection::Rectilinear
+}
+
+impl View {
+
+    pub fn new(coordinates: PolarCoordinate, focal_length: f32, projection: Projection) -> Self {
+        Self {
+            azimuth: coordinates.azimuth,
+            elevation: coordinates.elevation,
+            roll: coordinates.roll,
+            fov: 2.0 * (1.0 / focal_length).atan().to_degrees(),
+            projection,
+        }
+    }
+
+    pub fn coordinates(&self) -> PolarCoordinate {
+        PolarCoordinate {
+            azimuth: self.azimuth,
+            elevation: self.elevation,
+            roll: self.roll,
+        }
+    }
+
+    pub fn focal_length(&self) -> f32 {
+        1.0 / (self.fov.clamp(0.1, 179.9) / 2.0).to_radians().tan()
+    }
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2},{:.2},{:.2},{:.2},{}", self.azimuth, self.elevation, self.roll, self.fov, self.projection.name())
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    /// The roll, field of view and projection may be left out.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.trim().split(',').map(str::trim).collect();
+        if !(2..=5).contains(&fields.len()) {
+            return Err(format!("Invalid view \"{text}\", expected azimuth,elevation,roll,fov,projection"));
+        }
+
+        let number = |index: usize, default: f32| -> Result<f32, String> {
+            match fields.get(index) {
+                Some(field) => field.parse::<f32>().ok().filter(|value| value.is_finite())
+                    .ok_or(format!("Invalid number \"{field}\" in view \"{text}\"")),
+                None => Ok(default),
+            }
+        };
+        let projection = match fields.get(4) {
+            Some(name) => Projection::from_name(name).ok_or(format!("Unknown projection \"{name}\" in view \"{text}\""))?,
+            None => default_projection(),
+        };
+        let fov = number(3, 90.0)?;
+        if !(0.1..=179.9).contains(&fov) {
+            return Err(format!("Field of view {fov} out of [0.1;179.9] in view \"{text}\""));
+        }
+
+        Ok(Self {
+            azimuth: number(0, 0.0)?,
+            elevation: number(1, 0.0)?.clamp(-90.0, 90.0),
+            roll: number(2, 0.0)?,
+            fov,
+            projection,
+        })
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event_loop::ActiveEventLoop,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window, WindowAttributes},
+};
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// Fullscreen window at the resolution of the desktop.
+    Borderless,
+    /// Fullscreen with the best video mode of the monitor.
+    Exclusive,
+}
+
+/// A monitor given by its index in the system list or by its name.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum MonitorSelection {
+    Index(usize),
+    Name(String),
+}
+
+/// Window options, the `[window]` section of the configuration.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    /// Title prefix of the window.
+    pub title: String,
+    /// Initial inner size in logical pixels, when there is no saved geometry.
+    pub width: u32,
+    pub height: u32,
+    /// "windowed", "borderless" or "exclusive" at startup.
+    pub mode: WindowMode,
+    /// Monitor used in fullscreen, its index or its name.
+    pub monitor: Option<MonitorSelection>,
+    /// Hide the cursor and only quit with the `kiosk_exit` key binding.
+    pub kiosk: bool,
+    /// Save the window position and size at exit and restore them at startup.
+    pub remember_geometry: bool,
+    /// Other windows on the panorama, the `[[window.extra]]` tables.
+    pub extra: Vec<ExtraWindowSettings>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            title: "Cosmos360".to_string(),
+            width: 1920,
+            height: 1080,
+            mode: WindowMode::Windowed,
+            monitor: None,
+            kiosk: false,
+            remember_geometry: true,
+            extra: Vec::new(),
+        }
+    }
+}
+
+/// A window opened next to the main one, such as the side screens of a wraparound
+/// installation :
+///
+/// ```toml
+/// [[window.extra]]
+/// mode = "borderless"
+/// monitor = 1
+/// azimuth_offset = -60.0
+///
+/// [[window.extra]]
+/// mode = "borderless"
+/// monitor = 2
+/// azimuth_offset = 60.0
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtraWindowSettings {
+    /// Inner size in logical pixels.
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+    /// Monitor used in fullscreen, its index or its name.
+    pub monitor: Option<MonitorSelection>,
+    /// Follow the view of the main window turned by the offsets, else the window is
+    /// navigated on its own.
+    pub linked: bool,
+    /// Degrees added to the azimuth of the main view, which grows to the left. The
+    /// horizontal field of view of a screen puts it next to the main one.
+    pub azimuth_offset: f32,
+    pub elevation_offset: f32,
+}
+
+impl Default for ExtraWindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            mode: WindowMode::Windowed,
+            monitor: None,
+            linked: true,
+            azimuth_offset: 0.0,
+            elevation_offset: 0.0,
+        }
+    }
+}
+
+impl ExtraWindowSettings {
+    pub fn window_attributes(&self, title: &str) -> WindowAttributes {
+        WindowAttributes::default()
+            .with_title(title)
+            .with_inner_size(winit::dpi::LogicalSize::new(self.width, self.height))
+    }
+}
+
+/// Position and size of the window out of fullscreen, saved with the session.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WindowGeometry {
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub monitor: Option<String>,
+}
+
+impl WindowGeometry {
+    pub fn of(window: &Window) -> Self {
+        let position = window.outer_position().unwrap_or_default();
+        let size = window.inner_size();
+        Self {
+            position: (position.x, position.y),
+            size: (size.width, size.height),
+            monitor: window.current_monitor().and_then(|monitor| monitor.name()),
+        }
+    }
+}
+
+impl WindowSettings {
+
+    /// The configured monitor, else the one of the saved geometry, else the primary one.
+    pub fn select_monitor(&self, event_loop: &ActiveEventLoop, geometry: Option<&WindowGeometry>) -> Option<MonitorHandle> {
+        select_monitor(event_loop, self.monitor.as_ref(), geometry)
+    }
+
+    pub fn window_attributes(&self, geometry: Option<&WindowGeometry>) -> WindowAttributes {
+        let attributes = WindowAttributes::default()
+                .with_title(self.title.as_str())
+                .with_inner_size(winit::dpi::LogicalSize::new(self.width, self.height));
+
+        match geometry.filter(|_| self.remember_geometry) {
+            Some(geometry) => attributes
+                .with_inner_size(PhysicalSize::new(geometry.size.0, geometry.size.1))
+                .with_position(PhysicalPosition::new(geometry.position.0, geometry.position.1)),
+            None => attributes,
+        }
+    }
+}
+
+/// The `selection` monitor, else the one of the saved geometry, else the primary one.
+pub fn select_monitor(event_loop: &ActiveEventLoop, selection: Option<&MonitorSelection>, geometry: Option<&WindowGeometry>) -> Option<MonitorHandle> {
+    let by_name = |name: &str| event_loop.available_monitors()
+        .find(|monitor| monitor.name().as_deref() == Some(name));
+    let selected = match selection {
+        Some(MonitorSelection::Index(index)) => event_loop.available_monitors().nth(*index),
+        Some(MonitorSelection::Name(name)) => by_name(name),
+        None => geometry.and_then(|geometry| geometry.monitor.as_deref()).and_then(by_name),
+    };
+    if selected.is_none() {
+        if let Some(monitor) = selection {
+            println!("Monitor {monitor:?} not found, available monitors :");
+            for (index, monitor) in event_loop.available_monitors().enumerate() {
+                println!("  {index} : {}", monitor.name().unwrap_or_default());
+            }
+        }
+    }
+    selected.or_else(|| event_loop.primary_monitor())
+}
+
+/// Fullscreen setting of `mode` on `monitor`.
+pub fn fullscreen(mode: WindowMode, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => {
+            let video_mode = monitor.as_ref().and_then(|monitor| monitor.video_modes()
+                .max_by_key(|video_mode| (video_mode.size().width * video_mode.size().height, video_mode.refresh_rate_millihertz())));
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    println!("No video mode available for exclusive fullscreen, using borderless");
+                    Some(Fullscreen::Borderless(monitor))
+                }
+            }
+        }
+    }
+}